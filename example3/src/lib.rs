@@ -1,3 +1,4 @@
+#[path = "../../shared/dom_util.rs"]
 mod dom_util;
 mod vec2d;
 
@@ -80,10 +81,7 @@ impl MyApp {
     }
 
     pub fn init(&mut self) {
-        // 初期化して、Ballを生成する
-        let canvas = self.context.canvas().unwrap();
-        canvas.set_width(self.wx);
-        canvas.set_height(self.wy);
+        // 初期化して、Ballを生成する。Canvas の大きさは描くたびに表示に合わせる
         self.balls = Vec::with_capacity(self.initial_n_balls as usize);
         self.balls_history = Vec::with_capacity(self.balls_history_size as usize);
 
@@ -228,12 +226,29 @@ impl MyApp {
 
     fn render(&self) {
         // contextにレンダリングする
+        // 毎コマ描くので、ResizeObserver を使わずに描く前に表示の大きさが変わっていないか調べる
+        let canvas = self.context.canvas().unwrap();
+        fit_canvas_to_display(&canvas);
+        let (width, height) = (canvas.width() as f64, canvas.height() as f64);
         self.context.save();
         //
+        self.context.set_transform(1., 0., 0., 1., 0., 0.).unwrap();
         self.context
             .set_fill_style(&JsValue::from("rgb(0, 0, 0, 1)"));
+        self.context.fill_rect(0., 0., width, height);
+
+        // wx * wy の世界を、縦横比を保って中央に収まるように拡大する
+        let scale = (width / self.wx as f64).min(height / self.wy as f64);
         self.context
-            .fill_rect(0., 0., self.wx as f64, self.wy as f64);
+            .set_transform(
+                scale,
+                0.,
+                0.,
+                scale,
+                (width - self.wx as f64 * scale) / 2.,
+                (height - self.wy as f64 * scale) / 2.,
+            )
+            .unwrap();
 
         self.context
             .set_fill_style(&JsValue::from(format!("rgb(255, 0, 0, 1)")));
@@ -244,6 +259,11 @@ impl MyApp {
         }
         self.render_balls(&self.balls, 1.);
         //
+        // 文字は画面の大きさ(CSS px)で書く
+        let ratio = device_pixel_ratio();
+        self.context
+            .set_transform(ratio, 0., 0., ratio, 0., 0.)
+            .unwrap();
         self.context
             .set_fill_style(&JsValue::from(format!("rgb(0, 0, 255, 1)")));
         self.context
@@ -1,3 +1,7 @@
+#[path = "../../shared/input.rs"]
+mod input;
+
+use crate::input::{Gesture, PointerInput, PointerPhase, PointerTracker, POINTER_EVENTS};
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -29,15 +33,16 @@ pub fn start() {
         request_animation_frame(closure_cloned.borrow().as_ref().unwrap());
     }
 
-    // setup onClick
-    {
+    // setup Pointer Events (マウスもタッチもペンも同じように受け取る)
+    for (name, phase) in POINTER_EVENTS.iter() {
         let app_for_closure = Rc::clone(&my_app_rc);
+        let phase = *phase;
         let c = Closure::wrap(Box::new(move |e| {
-            app_for_closure.borrow_mut().on_click(e);
+            app_for_closure.borrow_mut().on_pointer(phase, e);
         }) as Box<dyn FnMut(JsValue)>);
         // Closure to js_sys::Function: .as_ref().unchecked_ref()
         document()
-            .add_event_listener_with_callback("click", c.as_ref().unchecked_ref())
+            .add_event_listener_with_callback(name, c.as_ref().unchecked_ref())
             .unwrap();
         c.forget(); // c を Rustのメモリ管理から外して JSのGCにわたす
     }
@@ -47,6 +52,8 @@ pub struct MyApp {
     pub my_name: String,
     pub my_counter: i32,
     pub clicks: u32,
+    pub zoom: f64, // ピンチで拡大・縮小した倍率
+    pointers: PointerTracker,
 }
 
 impl MyApp {
@@ -55,6 +62,8 @@ impl MyApp {
             my_name: "App!".into(),
             my_counter: 0,
             clicks: 0,
+            zoom: 1.,
+            pointers: PointerTracker::new(),
         }
     }
 
@@ -62,18 +71,35 @@ impl MyApp {
         self.my_counter += 1;
         if self.my_counter % 60 == 0 {
             log!(
-                "name={}, time={}, count={}, clicks={}",
+                "name={}, time={}, count={}, clicks={}, zoom={:.2}",
                 &self.my_name,
                 time,
                 self.my_counter,
                 self.clicks,
+                self.zoom,
             );
         }
     }
 
-    pub fn on_click(&mut self, event: JsValue) {
-        self.clicks += 1;
-        log!("{:#?}", &event);
+    pub fn on_pointer(&mut self, phase: PointerPhase, event: JsValue) {
+        let input = match PointerInput::from_event(phase, &event) {
+            Some(input) => input,
+            None => return,
+        };
+        for gesture in self.pointers.handle(&input) {
+            match gesture {
+                // 動かしている間は多いので出さない
+                Gesture::Move { .. } | Gesture::Hover { .. } => {}
+                Gesture::Pinch { scale, .. } => self.zoom *= scale,
+                Gesture::Tap { .. } => {
+                    self.clicks += 1;
+                    log!("{:?}", gesture);
+                }
+                _ => {
+                    log!("{:?}", gesture);
+                }
+            }
+        }
     }
 }
 
@@ -1,3 +1,7 @@
+#[path = "../../shared/input.rs"]
+mod input;
+
+use crate::input::{Gesture, PointerInput, PointerPhase, PointerTracker, POINTER_EVENTS};
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -50,13 +54,14 @@ pub fn start() {
         request_animation_frame(closure_cloned.borrow().as_ref().unwrap());
     }
 
-    // setup onClick
-    {
+    // setup Pointer Events (マウスもタッチもペンも同じように受け取る)
+    for (name, phase) in POINTER_EVENTS.iter() {
+        let phase = *phase;
         let c = Closure::wrap(Box::new(move |e| {
-            my_app_mut().on_click(e);
+            my_app_mut().on_pointer(phase, e);
         }) as Box<dyn FnMut(JsValue)>);
         document()
-            .add_event_listener_with_callback("click", c.as_ref().unchecked_ref())
+            .add_event_listener_with_callback(name, c.as_ref().unchecked_ref())
             .unwrap();
         c.forget(); // c を Rustのメモリ管理から外して JSのGCにわたす
     }
@@ -67,6 +72,8 @@ pub struct MyApp {
     pub my_counter: i32,
     pub clicks: u32,
     pub async_count: u32,
+    pub zoom: f64, // ピンチで拡大・縮小した倍率
+    pointers: PointerTracker,
 }
 
 impl MyApp {
@@ -76,6 +83,8 @@ impl MyApp {
             my_counter: 0,
             clicks: 0,
             async_count: 0,
+            zoom: 1.,
+            pointers: PointerTracker::new(),
         }
     }
 
@@ -83,17 +92,33 @@ impl MyApp {
         self.my_counter += 1;
         if self.my_counter % 60 == 0 {
             log!(
-                "name={}, time={}, count={}, clicks={}, async_count={}",
+                "name={}, time={}, count={}, clicks={}, async_count={}, zoom={:.2}",
                 &self.my_name,
                 time,
                 self.my_counter,
                 self.clicks,
                 self.async_count,
+                self.zoom,
             );
         }
     }
 
-    pub fn on_click(&mut self, _event: JsValue) {
+    pub fn on_pointer(&mut self, phase: PointerPhase, event: JsValue) {
+        let input = match PointerInput::from_event(phase, &event) {
+            Some(input) => input,
+            None => return,
+        };
+        for gesture in self.pointers.handle(&input) {
+            match gesture {
+                Gesture::Tap { .. } => self.on_click(),
+                Gesture::Pinch { scale, .. } => self.zoom *= scale,
+                _ => {}
+            }
+        }
+    }
+
+    // クリック(タップ)したとき
+    pub fn on_click(&mut self) {
         self.clicks += 1;
         // 仮にこれをselfが持っていれば渡せることになる
         let x: Rc<RefCell<MyApp>> = Rc::new(RefCell::new(MyApp::new()));
@@ -0,0 +1,152 @@
+use crate::coloring::{cell_colors, ColorBy, Legend, Palette};
+use crate::{fill_polygon, get_element_by_id, my_app_mut, MyApp};
+use wasm_bindgen::prelude::*;
+
+/// NodeId ごとの値を与える(色分けを value にすると使われる)
+#[wasm_bindgen]
+pub fn set_cell_values(node_ids: &[u32], values: &[f64]) {
+    let app = my_app_mut();
+    app.cell_values = node_ids
+        .iter()
+        .cloned()
+        .zip(values.iter().cloned())
+        .collect();
+    app.sessions.mark_unsaved();
+    app.draw();
+}
+
+#[wasm_bindgen]
+pub fn clear_cell_values() {
+    let app = my_app_mut();
+    app.cell_values.clear();
+    app.sessions.mark_unsaved();
+    app.draw();
+}
+
+impl MyApp {
+    // 地図を表示していないときだけ、選んだ値でセルを塗る
+    pub fn draw_cell_colors(&self, context: &web_sys::CanvasRenderingContext2d) -> Option<Legend> {
+        if self.map.is_some() {
+            return None;
+        }
+        let color_by: web_sys::HtmlSelectElement = get_element_by_id("color_by");
+        let palette: web_sys::HtmlSelectElement = get_element_by_id("palette");
+        let (colors, legend) = cell_colors(
+            &self.diagram,
+            ColorBy::from_name(&color_by.value()),
+            Palette::from_name(&palette.value()),
+            &self.cell_values,
+        );
+        for (_cell, color) in self.diagram.cells.iter().zip(colors.iter()) {
+            if let Some(color) = color {
+                context.set_fill_style(&JsValue::from(color.as_str()));
+                fill_polygon(
+                    context,
+                    &self.viewport,
+                    &_cell.borrow().bound.polygon.points,
+                );
+            }
+        }
+        legend
+    }
+
+    // セルの重心に CellId を書く
+    pub fn draw_labels(&self, context: &web_sys::CanvasRenderingContext2d) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("labels");
+        if !elem.checked() {
+            return;
+        }
+        context.set_font("10px sans-serif");
+        context.set_text_align("center");
+        context.set_text_baseline("middle");
+        context.set_fill_style(&JsValue::from("rgb(255, 255, 255, 1)"));
+        for _cell in self.diagram.cells.iter() {
+            let cell = _cell.borrow();
+            let p = self.viewport.to_screen(cell.bound.polygon.centroid());
+            context
+                .fill_text(&cell.cell_id.to_string(), p.x, p.y)
+                .unwrap();
+        }
+        context.set_text_align("start");
+        context.set_text_baseline("alphabetic");
+    }
+}
+
+// 右上に凡例を描く
+pub fn draw_legend(
+    context: &web_sys::CanvasRenderingContext2d,
+    legend: &Legend,
+    canvas_width: f64,
+) {
+    const WIDTH: f64 = 140.;
+    const ROW: f64 = 16.;
+    let x = canvas_width - WIDTH - 10.;
+    let y = 10.;
+    let (title, rows) = match legend {
+        Legend::Range { title, .. } => (title, 2.),
+        Legend::Categories {
+            title,
+            items,
+            omitted,
+        } => (
+            title,
+            items.len() as f64 + if *omitted > 0 { 1. } else { 0. },
+        ),
+    };
+    context.set_fill_style(&JsValue::from("rgba(0, 0, 0, 0.7)"));
+    context.fill_rect(x, y, WIDTH, ROW * (rows + 1.) + 8.);
+    context.set_font("12px sans-serif");
+    context.set_text_baseline("middle");
+    context.set_fill_style(&JsValue::from("rgb(255, 255, 255, 1)"));
+    context.fill_text(title, x + 6., y + 4. + ROW / 2.).unwrap();
+    let y = y + 4. + ROW;
+    match legend {
+        Legend::Range {
+            min, max, palette, ..
+        } => {
+            // 色の帯と両端の値
+            let steps = 32;
+            let bar = WIDTH - 12.;
+            for i in 0..steps {
+                context
+                    .set_fill_style(&JsValue::from(palette.color(i as f64 / (steps - 1) as f64)));
+                context.fill_rect(
+                    x + 6. + bar * i as f64 / steps as f64,
+                    y + 2.,
+                    bar / steps as f64 + 0.5,
+                    ROW - 4.,
+                );
+            }
+            context.set_fill_style(&JsValue::from("rgb(255, 255, 255, 1)"));
+            context
+                .fill_text(&format!("{:.4}", min), x + 6., y + ROW * 1.5)
+                .unwrap();
+            context.set_text_align("end");
+            context
+                .fill_text(&format!("{:.4}", max), x + WIDTH - 6., y + ROW * 1.5)
+                .unwrap();
+            context.set_text_align("start");
+        }
+        Legend::Categories { items, omitted, .. } => {
+            for (i, (label, color)) in items.iter().enumerate() {
+                let row_y = y + ROW * i as f64;
+                context.set_fill_style(&JsValue::from(color.as_str()));
+                context.fill_rect(x + 6., row_y + 3., ROW - 6., ROW - 6.);
+                context.set_fill_style(&JsValue::from("rgb(255, 255, 255, 1)"));
+                context
+                    .fill_text(label, x + 6. + ROW, row_y + ROW / 2.)
+                    .unwrap();
+            }
+            if *omitted > 0 {
+                context
+                    .fill_text(
+                        &format!("... 他 {}", omitted),
+                        x + 6.,
+                        y + ROW * items.len() as f64 + ROW / 2.,
+                    )
+                    .unwrap();
+            }
+        }
+    }
+    context.set_text_baseline("alphabetic");
+}
@@ -0,0 +1,105 @@
+use crate::kinetic::Kinetic;
+use crate::{get_element_by_id, log, MyApp};
+use wasm_bindgen::JsValue;
+
+const FRAME_TIME: f64 = 1. / 60.; // 1コマ進めるときの時間(秒)
+const MAX_FRAME_TIME: f64 = 0.1; // タブが裏にあった後などに一度に進める時間の上限(秒)
+
+/// 点を動かすアニメーションの状態
+pub struct Animation {
+    kinetic: Kinetic,
+    running: bool,
+    last_frame_time: Option<f64>, // 前のコマの時刻(ms)
+    fps_counter: u32,
+    fps_time: f64,
+}
+
+impl Animation {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            kinetic: Kinetic::new(speed, 0),
+            running: false,
+            last_frame_time: None,
+            fps_counter: 0,
+            fps_time: 0.,
+        }
+    }
+
+    // 速度を与える乱数の種を決め直す (速さはそのまま)
+    pub fn reseed(&mut self, seed: u64) {
+        self.kinetic = Kinetic::new(self.kinetic.speed, seed);
+    }
+}
+
+impl MyApp {
+    pub fn on_animate(&mut self, _e: JsValue) {
+        self.set_animating(!self.animation.running);
+    }
+
+    // 止めて1コマだけ進める
+    pub fn on_step_frame(&mut self, _e: JsValue) {
+        self.set_animating(false);
+        self.advance(FRAME_TIME);
+    }
+
+    pub fn on_speed(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("speed");
+        if let Ok(speed) = elem.value().parse::<f64>() {
+            self.animation.kinetic.speed = speed.max(0.);
+        }
+    }
+
+    pub fn set_animating(&mut self, animating: bool) {
+        self.animation.running = animating;
+        self.animation.last_frame_time = None;
+        self.animation.fps_counter = 0;
+        let button: web_sys::HtmlElement = get_element_by_id("animate");
+        button.set_inner_text(if animating { "Pause" } else { "Animate" });
+        if !animating {
+            let fps: web_sys::HtmlElement = get_element_by_id("fps");
+            fps.set_inner_text("");
+            // 動かした位置は履歴に残らないので、止めたときに保存する
+            self.sessions.mark_unsaved();
+        }
+    }
+
+    // アニメーション中なら、前のコマからの時間だけ進める
+    pub fn animate_frame(&mut self, time: f64) {
+        if !self.animation.running {
+            return;
+        }
+        self.calc_fps(time);
+        let dt = match self.animation.last_frame_time {
+            Some(last) => ((time - last) / 1000.).clamp(0., MAX_FRAME_TIME),
+            None => FRAME_TIME,
+        };
+        self.animation.last_frame_time = Some(time);
+        self.advance(dt);
+    }
+
+    // 1秒ごとに、その間に描いたコマ数を表示する
+    fn calc_fps(&mut self, time: f64) {
+        let animation = &mut self.animation;
+        if animation.fps_time + 1000. < time {
+            let fps: web_sys::HtmlElement = get_element_by_id("fps");
+            fps.set_inner_text(&format!("fps {}", animation.fps_counter));
+            animation.fps_counter = 0;
+            animation.fps_time = time;
+        }
+        animation.fps_counter += 1;
+    }
+
+    // 点を dt 秒だけ動かす (編集の履歴には残さない)
+    fn advance(&mut self, dt: f64) {
+        if self.pointer.is_dragging() || self.diagram.cells.is_empty() {
+            return;
+        }
+        self.clear_layers();
+        if let Err(e) = self.animation.kinetic.step(&mut self.diagram, dt) {
+            log!("animation: {}", e);
+            self.set_animating(false);
+        }
+        self.hover_cell = None;
+        self.draw();
+    }
+}
@@ -0,0 +1,116 @@
+use crate::geometry::voronoi_diagram::types::NodeId;
+use crate::site_loader::{parse_sites, to_center_points, SiteBuilder, SiteFormat};
+use crate::{get_element_by_id, my_app_mut, window, MyApp};
+use futures::prelude::*;
+use js_sys::Promise;
+use rand::Rng;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Request, RequestInit, RequestMode, Response};
+
+const LOAD_POINTS_PER_FRAME: usize = 2000; // 読み込んだ点を1コマで追加する数
+
+/// 読み込んだ点から図を作っている途中
+pub struct Loading {
+    builder: SiteBuilder,
+    values: HashMap<NodeId, f64>, // 読み込んだ点の weight
+}
+
+impl MyApp {
+    // URL から点のリスト(JSON か CSV)を読み込む
+    pub fn on_load_sites(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("sites_url");
+        let url = elem.value();
+        set_load_status(&format!("loading {} ...", url));
+        spawn_local(async move {
+            let result = fetch_text(&url).await;
+            my_app_mut().on_sites_fetched(&url, result);
+        });
+    }
+
+    fn on_sites_fetched(&mut self, url: &str, result: Result<String, JsValue>) {
+        let text = match result {
+            Ok(text) => text,
+            Err(e) => {
+                let message = e.as_string().unwrap_or(format!("{:?}", e));
+                set_load_status(&format!("failed to load {}: {}", url, message));
+                return;
+            }
+        };
+        let records = match parse_sites(&text, SiteFormat::detect(url, &text)) {
+            Ok(records) => records,
+            Err(e) => {
+                set_load_status(&format!("parse error in {}: {}", url, e));
+                return;
+            }
+        };
+        let bounds = self.diagram.outbound.polygon.bounding_rect();
+        let (points, values) = to_center_points(&records, &bounds, self.next_node_id);
+        self.next_node_id += points.len() as NodeId;
+        let seed = self.rng.gen();
+        self.loading = Some(Loading {
+            builder: SiteBuilder::new(bounds, points, seed),
+            values,
+        });
+        set_load_status(&format!("building 0/{}", records.len()));
+    }
+
+    // 読み込んだ点を少しずつ追加して、全部追加したら今の図と入れ替える
+    pub fn build_sites(&mut self) {
+        let builder = &mut self.loading.as_mut().unwrap().builder;
+        match builder.add_points(LOAD_POINTS_PER_FRAME) {
+            Ok(false) => {
+                let (done, total) = builder.progress();
+                set_load_status(&format!("building {}/{}", done, total));
+                return;
+            }
+            Ok(true) => {}
+            Err(e) => {
+                set_load_status(&format!("failed to build: {}", e));
+                self.loading = None;
+                return;
+            }
+        }
+        let loading = self.loading.take().unwrap();
+        let skipped = loading.builder.skipped;
+        self.replace_diagram(loading.builder.diagram);
+        if !loading.values.is_empty() {
+            self.cell_values = loading.values;
+        }
+        let mut status = format!("loaded {} sites", self.diagram.cells.len());
+        if skipped > 0 {
+            status = format!("{} ({} skipped: same position)", status, skipped);
+        }
+        set_load_status(&status);
+        self.draw();
+    }
+}
+
+// url の中身を文字列で取得する
+fn fetch_text(url: &str) -> impl Future<Output = Result<String, JsValue>> {
+    let mut opts = RequestInit::new();
+    opts.method("GET");
+    opts.mode(RequestMode::Cors);
+    let request = Request::new_with_str_and_init(url, &opts);
+    let request_promise = match request {
+        Ok(request) => window().fetch_with_request(&request),
+        Err(e) => Promise::reject(&e),
+    };
+    JsFuture::from(request_promise)
+        .and_then(|resp_value| async move {
+            let resp: Response = resp_value.dyn_into()?;
+            if !resp.ok() {
+                return Err(JsValue::from(format!("HTTP {}", resp.status())));
+            }
+            resp.text()
+        })
+        .and_then(|value: Promise| JsFuture::from(value))
+        .map_ok(|text| text.as_string().unwrap_or_default())
+}
+
+fn set_load_status(message: &str) {
+    let elem: web_sys::HtmlElement = get_element_by_id("load_status");
+    elem.set_inner_text(message);
+}
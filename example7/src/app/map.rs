@@ -0,0 +1,43 @@
+use crate::geometry::voronoi_diagram::types::NodeId;
+use crate::map_generator::{generate_map, MapConfig};
+use crate::{draw_lines, fill_polygon, log, MyApp};
+use rand::Rng;
+use wasm_bindgen::JsValue;
+
+impl MyApp {
+    pub fn on_generate_map(&mut self, _e: JsValue) {
+        let config = MapConfig::new(self.rng.gen());
+        match generate_map(&config) {
+            Ok((diagram, map)) => {
+                // 地図の CellId は生成した図のものなので、図はそのまま入れ替えて履歴だけ積む
+                self.replace_diagram(diagram);
+                self.next_node_id = self.next_node_id.max(config.n_sites as NodeId);
+                self.map = Some(map);
+            }
+            Err(e) => {
+                log!("generate_map: {}", e);
+            }
+        }
+        self.draw();
+    }
+
+    // セルをバイオームの色で塗り、川を流量に応じた太さで描く
+    pub fn draw_map(&self, context: &web_sys::CanvasRenderingContext2d) {
+        let map = match &self.map {
+            Some(map) => map,
+            None => return,
+        };
+        for _cell in self.diagram.cells.iter() {
+            let cell = _cell.borrow();
+            let biome = map.cells[cell.cell_id as usize].biome;
+            context.set_fill_style(&JsValue::from(biome.color()));
+            fill_polygon(context, &self.viewport, &cell.bound.polygon.points);
+        }
+        context.set_stroke_style(&JsValue::from("rgb(51, 102, 153, 1)"));
+        for river in map.rivers.iter() {
+            context.set_line_width(1. + (river.flow as f64).sqrt());
+            draw_lines(context, &self.viewport, std::slice::from_ref(&river.line));
+        }
+        context.set_line_width(1.);
+    }
+}
@@ -0,0 +1,12 @@
+pub mod coloring;
+pub mod kinetic;
+pub mod loader;
+pub mod map;
+pub mod pointer;
+pub mod recorder;
+pub mod resize;
+pub mod sessions;
+pub mod skeleton;
+pub mod steps;
+pub mod stipple;
+pub mod viewport;
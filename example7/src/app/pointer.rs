@@ -0,0 +1,236 @@
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::history::EditOp;
+use crate::geometry::voronoi_diagram::types::NodeId;
+use crate::input::{Gesture, Modifiers, PointerInput, PointerPhase, PointerTracker, PointerType};
+use crate::{get_element_by_id, get_nested_property, log, MyApp};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+const HIT_RADIUS: f64 = 6.; // 点をつかめる距離(px)
+const TOUCH_HIT_RADIUS: f64 = 16.; // 指で点をつかめる距離(px)
+
+struct Drag {
+    node_id: NodeId,
+    from: Vec2d,
+    to: Option<Vec2d>, // 動かしていなければ None
+}
+
+/// 押しているポインタと、その操作の途中の状態
+#[derive(Default)]
+pub struct PointerState {
+    tracker: PointerTracker,
+    drag: Option<Drag>,
+    suppress_click: bool, // ドラッグや削除の後の click で点を追加しない
+}
+
+impl PointerState {
+    pub fn is_pressed(&self) -> bool {
+        self.tracker.is_pressed()
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+}
+
+impl MyApp {
+    // マウスもタッチもペンも PointerTracker で Gesture にしてから扱う
+    pub fn on_pointer_down(&mut self, e: JsValue) {
+        // 押したまま Canvas の外に出ても追いかける (再生のときは失敗するが構わない)
+        if let Some(id) = get_nested_property(&e, vec!["pointerId"]).and_then(|v| v.as_f64()) {
+            let canvas: web_sys::HtmlElement = get_element_by_id("canvas");
+            let _ = canvas.set_pointer_capture(id as i32);
+        }
+        self.on_pointer(PointerPhase::Down, &e);
+    }
+
+    pub fn on_pointer_move(&mut self, e: JsValue) {
+        self.on_pointer(PointerPhase::Move, &e);
+    }
+
+    pub fn on_pointer_up(&mut self, e: JsValue) {
+        self.on_pointer(PointerPhase::Up, &e);
+    }
+
+    pub fn on_pointer_cancel(&mut self, e: JsValue) {
+        self.on_pointer(PointerPhase::Cancel, &e);
+    }
+
+    pub fn on_pointer_leave(&mut self, _e: JsValue) {
+        if !self.pointer.tracker.is_pressed() {
+            self.hover_cell = None;
+            self.draw();
+        }
+    }
+
+    fn on_pointer(&mut self, phase: PointerPhase, e: &JsValue) {
+        let input = match PointerInput::from_event(phase, e) {
+            Some(input) => input,
+            None => return,
+        };
+        // 中ボタンの自動スクロールをさせない
+        if phase == PointerPhase::Down && input.button == 1 {
+            e.unchecked_ref::<web_sys::Event>().prevent_default();
+        }
+        if self.handle_pointer(&input) {
+            self.draw();
+        }
+    }
+
+    // Gesture にして点や表示を変える。描き直すなら true。
+    // DOM を使わないので、記録の再生では JS のイベントを作らずにこれを呼ぶ
+    pub fn handle_pointer(&mut self, input: &PointerInput) -> bool {
+        let mut redraw = false;
+        for gesture in self.pointer.tracker.handle(input) {
+            redraw |= match gesture {
+                Gesture::Down {
+                    pos,
+                    button,
+                    pointer_type,
+                    modifiers,
+                } => self.on_press(to_vec2d(pos), button, pointer_type, modifiers),
+                Gesture::Move { pos } => self.on_drag(to_vec2d(pos)),
+                Gesture::Up { .. } | Gesture::Cancel => {
+                    self.on_release();
+                    false
+                }
+                Gesture::Tap { pos } => self.on_tap(to_vec2d(pos)),
+                Gesture::Hover { pos } => self.on_hover(to_vec2d(pos)),
+                Gesture::Pinch {
+                    center,
+                    scale,
+                    delta,
+                } => {
+                    self.on_pinch(to_vec2d(center), scale, to_vec2d(delta));
+                    true
+                }
+            };
+        }
+        redraw
+    }
+
+    // 点を追加する
+    fn on_tap(&mut self, screen: Vec2d) -> bool {
+        if self.pointer.suppress_click {
+            self.pointer.suppress_click = false;
+            return false;
+        }
+        let vp = self.new_center_point(self.viewport.to_world(screen));
+        self.clear_layers();
+        match self.add_point_with_steps(&vp) {
+            Ok(_) => self.history.record(EditOp::Add(vec![vp])),
+            Err(e) => {
+                log!("add_point: {}", e);
+            }
+        }
+        true
+    }
+
+    // 点の近くを押したらドラッグ開始、何もないところなら表示を動かす。
+    // Shift/Ctrl/Cmd を押しながらなら削除。中ボタンはいつも表示を動かす。削除したら true
+    fn on_press(
+        &mut self,
+        screen: Vec2d,
+        button: i16,
+        pointer_type: PointerType,
+        modifiers: Modifiers,
+    ) -> bool {
+        self.pointer.suppress_click = false;
+        if button == 1 {
+            self.start_pan(screen);
+            return false;
+        }
+        if button != 0 {
+            return false;
+        }
+        let pos = self.viewport.to_world(screen);
+        if modifiers.any() {
+            self.pointer.suppress_click = true;
+            return self.delete_at(pos);
+        }
+        // 指は太いので広めにつかめるようにする
+        let hit_radius = match pointer_type {
+            PointerType::Touch => TOUCH_HIT_RADIUS,
+            _ => HIT_RADIUS,
+        };
+        if let Some(cell) = self.diagram.find_cell_at(pos) {
+            let point = cell.borrow().point.clone();
+            if point.pos.distance_from(pos) * self.viewport.scale <= hit_radius {
+                self.pointer.suppress_click = true;
+                self.pointer.drag = Some(Drag {
+                    node_id: point.node_id,
+                    from: point.pos,
+                    to: None,
+                });
+                return false;
+            }
+        }
+        self.start_pan(screen);
+        false
+    }
+
+    fn on_drag(&mut self, screen: Vec2d) -> bool {
+        if self.pan.is_some() {
+            return self.pan_to(screen);
+        }
+        let pos = self.viewport.to_world(screen);
+        let node_id = match self.pointer.drag.as_ref() {
+            Some(drag) => drag.node_id,
+            None => return false,
+        };
+        self.clear_layers();
+        // 動かすたびに全体を作り直すと重いので、動かした点のまわりだけ作り直す。
+        // 履歴には離したときに1回だけ残す
+        match self.diagram.move_point_incremental(node_id, pos) {
+            Ok(()) => {
+                // Clamp のときは pos とは少し違う位置に置かれる
+                let moved = self.diagram.get_cell_by_node_id(node_id);
+                self.pointer.drag.as_mut().unwrap().to = moved.map(|c| c.borrow().point.pos);
+            }
+            Err(e) => {
+                log!("move_point_incremental: {}", e);
+            }
+        }
+        self.hover_cell = None;
+        true
+    }
+
+    // セルが変わったときだけ描き直す
+    fn on_hover(&mut self, screen: Vec2d) -> bool {
+        let pos = self.viewport.to_world(screen);
+        let hover_cell = self.diagram.find_cell_at(pos).map(|c| c.borrow().cell_id);
+        if hover_cell == self.hover_cell {
+            return false;
+        }
+        self.hover_cell = hover_cell;
+        true
+    }
+
+    fn on_release(&mut self) {
+        // 表示を動かしただけなら点は追加しない
+        if self.end_pan() {
+            self.pointer.suppress_click = true;
+        }
+        if let Some(drag) = self.pointer.drag.take() {
+            if let Some(to) = drag.to {
+                self.history.record(EditOp::Move {
+                    node_id: drag.node_id,
+                    from: drag.from,
+                    to,
+                });
+            }
+        }
+    }
+
+    // 右クリックで削除
+    pub fn on_context_menu(&mut self, e: JsValue) {
+        e.unchecked_ref::<web_sys::Event>().prevent_default();
+        if self.delete_at(self.event_pos(&e)) {
+            self.draw();
+        }
+    }
+}
+
+fn to_vec2d((x, y): (f64, f64)) -> Vec2d {
+    Vec2d { x, y }
+}
@@ -0,0 +1,383 @@
+use crate::app::pointer::PointerState;
+use crate::geometry::vec2d::Vec2d;
+use crate::recorder::{ControlValue, EventData, InputLog, InputRecord, Recorder};
+use crate::{document, get_element_by_id, get_nested_property, my_app_mut, Handler, MyApp};
+use js_sys::Math::random;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+// 記録を始めたときに値を残しておくフォームの要素
+const CONTROL_IDS: [&str; 12] = [
+    "n_stipples",
+    "color_by",
+    "palette",
+    "labels",
+    "outline",
+    "alpha",
+    "trace",
+    "step_mode",
+    "speed",
+    "sites_url",
+    "snapshot_name",
+    "snapshots",
+];
+// 記録しない要素 (記録と再生のボタンと、記録を書き出す欄)
+const UNRECORDED_IDS: [&str; 3] = ["record", "replay", "export"];
+
+/// 入力操作の記録と、再生するときに呼ぶハンドラ
+#[derive(Default)]
+pub struct Recording {
+    recorder: Option<Recorder>,                   // 記録中なら Some
+    handlers: HashMap<(String, String), Handler>, // (要素の id, event) ごと
+}
+
+impl Recording {
+    pub fn add_handler(&mut self, key: (String, String), handler: Handler) {
+        self.handlers.insert(key, handler);
+    }
+}
+
+/// 入力操作の記録を始める
+#[wasm_bindgen]
+pub fn start_recording() {
+    my_app_mut().start_recording();
+}
+
+/// 記録を止めて、記録した入力操作を JSON で返す(記録していなければ空文字列)
+#[wasm_bindgen]
+pub fn stop_recording() -> String {
+    my_app_mut()
+        .stop_recording()
+        .map(|log| log.to_json())
+        .unwrap_or_default()
+}
+
+/// 記録した入力操作を再生する。結果が記録したときと違えばエラー
+#[wasm_bindgen]
+pub fn replay_input(log: &str) -> Result<(), JsValue> {
+    let app = my_app_mut();
+    let log = InputLog::from_json(log).map_err(|e| js_sys::Error::new(&e))?;
+    let result = app.replay(&log);
+    app.draw();
+    result.map_err(|e| js_sys::Error::new(&e).into())
+}
+
+impl MyApp {
+    // 記録を始める/止める。止めたら記録を書き出す欄に出す
+    pub fn on_record(&mut self, _e: JsValue) {
+        if self.recording.recorder.is_none() {
+            self.start_recording();
+            return;
+        }
+        if let Some(log) = self.stop_recording() {
+            let elem: web_sys::HtmlTextAreaElement = get_element_by_id("export");
+            elem.set_value(&log.to_json());
+            set_record_status(&format!("recorded {} inputs", log.records.len()));
+        }
+    }
+
+    // 書き出す欄の記録を再生する
+    pub fn on_replay(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlTextAreaElement = get_element_by_id("export");
+        let log = match InputLog::from_json(&elem.value()) {
+            Ok(log) => log,
+            Err(e) => {
+                set_record_status(&format!("invalid input log: {}", e));
+                return;
+            }
+        };
+        match self.replay(&log) {
+            Ok(()) => {
+                set_record_status(&format!("replayed {} inputs", log.records.len()));
+            }
+            Err(e) => {
+                set_record_status(&format!("replay differs: {}", e));
+            }
+        }
+        self.draw();
+    }
+
+    fn start_recording(&mut self) {
+        // 再生するときに同じ乱数になるように、ここから種を決め直す
+        let seed = (random() * 1e9) as u64;
+        self.reseed(seed);
+        let session = self.session();
+        let viewport = (
+            self.viewport.scale,
+            self.viewport.offset.x,
+            self.viewport.offset.y,
+        );
+        let controls = CONTROL_IDS
+            .iter()
+            .filter_map(|id| control_value(id))
+            .collect();
+        let log = InputLog::new(seed, session, viewport, controls);
+        self.recording.recorder = Some(Recorder::new(log, js_sys::Date::now()));
+        let button: web_sys::HtmlElement = get_element_by_id("record");
+        button.set_inner_text("Stop");
+        set_record_status("recording");
+    }
+
+    fn stop_recording(&mut self) -> Option<InputLog> {
+        let mut log = self.recording.recorder.take()?.log;
+        log.expected = Some(self.session());
+        let button: web_sys::HtmlElement = get_element_by_id("record");
+        button.set_inner_text("Record");
+        Some(log)
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.animation.reseed(seed);
+    }
+
+    // ハンドラを呼ぶ前に、記録中ならイベントを記録する
+    pub fn record_event(&mut self, name: &str, event: &str, e: &JsValue) {
+        if self.recording.recorder.is_none() || UNRECORDED_IDS.contains(&name) {
+            return;
+        }
+        // 押していないときのポインタの移動は表示しか変えない
+        if event == "pointermove" && !self.pointer.is_pressed() {
+            return;
+        }
+        let mut data = EventData::from_event(e);
+        if name == "canvas" {
+            data.pos = data.offset.map(|(x, y)| {
+                let pos = self.viewport.to_world(Vec2d { x, y });
+                (pos.x, pos.y)
+            });
+        }
+        let recorder = self.recording.recorder.as_mut().unwrap();
+        recorder.event(js_sys::Date::now(), name, event, data);
+    }
+
+    pub fn record_control(&mut self, e: &JsValue) {
+        let recorder = match self.recording.recorder.as_mut() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let id = get_nested_property(e, vec!["target", "id"])
+            .and_then(|id| id.as_string())
+            .unwrap_or_default();
+        if id.is_empty() || UNRECORDED_IDS.contains(&id.as_str()) {
+            return;
+        }
+        if let Some(control) = control_value(&id) {
+            recorder.control(js_sys::Date::now(), control);
+        }
+    }
+
+    // 記録を始めたときの状態に戻してから、記録した順にハンドラを呼び直す。
+    // 結果が記録を止めたときと違えばエラー
+    fn replay(&mut self, log: &InputLog) -> Result<(), String> {
+        if self.recording.recorder.is_some() {
+            self.stop_recording();
+        }
+        self.set_animating(false);
+        self.reset_for_replay(log);
+        for control in log.controls.iter() {
+            set_control(control)?;
+        }
+        // フォームの値から作っている状態を合わせる (速さを変えても乱数の種はそのまま)
+        self.on_step_mode(JsValue::NULL);
+        self.on_trace(JsValue::NULL);
+        self.on_speed(JsValue::NULL);
+        self.replay_records(log)
+    }
+
+    // 図と表示を記録を始めたときに戻す。フォームの値は戻さない
+    fn reset_for_replay(&mut self, log: &InputLog) {
+        self.loading = None;
+        self.pointer = PointerState::default();
+        self.pan = None;
+        self.hover_cell = None;
+        self.restore(&log.session, false);
+        self.history.clear();
+        self.next_node_id = log.session.next_node_id;
+        let (scale, x, y) = log.viewport;
+        self.viewport.scale = scale;
+        self.viewport.offset = Vec2d { x, y };
+        self.reseed(log.seed);
+    }
+
+    // Canvas の Pointer Events は JS のイベントを作らずに handle_pointer に渡す
+    fn replay_records(&mut self, log: &InputLog) -> Result<(), String> {
+        for record in log.records.iter() {
+            match record {
+                InputRecord::Control {
+                    id, value, checked, ..
+                } => {
+                    set_control(&ControlValue {
+                        id: id.clone(),
+                        value: value.clone(),
+                        checked: *checked,
+                    })?;
+                }
+                InputRecord::Event {
+                    target,
+                    event,
+                    data,
+                    ..
+                } => {
+                    let input = match target.as_str() {
+                        "canvas" => data.to_pointer_input(event),
+                        _ => None,
+                    };
+                    if let Some(input) = input {
+                        self.handle_pointer(&input);
+                        continue;
+                    }
+                    let handler = *self
+                        .recording
+                        .handlers
+                        .get(&(target.clone(), event.clone()))
+                        .ok_or(format!("no handler for {} {}", target, event))?;
+                    handler(self, data.to_event());
+                }
+            }
+        }
+        log.check(&self.session())
+    }
+}
+
+fn set_record_status(message: &str) {
+    let elem: web_sys::HtmlElement = get_element_by_id("record_status");
+    elem.set_inner_text(message);
+}
+
+// フォームの要素の今の値。要素が無ければ None
+fn control_value(id: &str) -> Option<ControlValue> {
+    let elem: JsValue = document().get_element_by_id(id)?.into();
+    Some(ControlValue {
+        id: id.to_string(),
+        value: get_nested_property(&elem, vec!["value"])
+            .and_then(|v| v.as_string())
+            .unwrap_or_default(),
+        checked: get_nested_property(&elem, vec!["checked"])
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    })
+}
+
+fn set_control(control: &ControlValue) -> Result<(), String> {
+    let elem = document()
+        .get_element_by_id(&control.id)
+        .ok_or(format!("not found: {}", control.id))?;
+    match elem.dyn_ref::<web_sys::HtmlInputElement>() {
+        Some(input) if input.type_() == "checkbox" => input.set_checked(control.checked),
+        _ => {
+            js_sys::Reflect::set(
+                &elem,
+                &JsValue::from("value"),
+                &JsValue::from(control.value.as_str()),
+            )
+            .map_err(|_| format!("failed to set {}", control.id))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::voronoi_diagram::types::{NodeId, VoronoiCenterPoint};
+    use crate::storage::MemoryStorage;
+
+    fn new_app() -> MyApp {
+        MyApp::with_storage(Box::new(MemoryStorage::new()), 0)
+    }
+
+    fn pointer(event: &str, offset: (f64, f64), button: f64, modifiers: &[&str]) -> InputRecord {
+        InputRecord::Event {
+            time: 0.,
+            target: String::from("canvas"),
+            event: event.to_string(),
+            data: EventData {
+                offset: Some(offset),
+                button: Some(button),
+                pointer_id: Some(1.),
+                pointer_type: Some(String::from("mouse")),
+                modifiers: modifiers.iter().map(|m| m.to_string()).collect(),
+                ..EventData::default()
+            },
+        }
+    }
+
+    fn click(offset: (f64, f64), modifiers: &[&str]) -> Vec<InputRecord> {
+        vec![
+            pointer("pointerdown", offset, 0., modifiers),
+            pointer("pointerup", offset, 0., modifiers),
+        ]
+    }
+
+    fn drag(from: (f64, f64), to: (f64, f64), button: f64) -> Vec<InputRecord> {
+        vec![
+            pointer("pointerdown", from, button, &[]),
+            pointer("pointermove", to, -1., &[]),
+            pointer("pointerup", to, button, &[]),
+        ]
+    }
+
+    // 記録したときと同じ操作を、DOM を使わずに再生し直せること
+    #[test]
+    fn replay_reproduces_recorded_session() {
+        let mut app = new_app();
+        for (node_id, x, y) in [(0, 0.25, 0.25), (1, 0.75, 0.75)].iter() {
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d { x: *x, y: *y },
+                node_id: *node_id,
+                cluster_id: *node_id as _,
+            };
+            app.diagram.add_point(&vp).unwrap();
+        }
+        app.next_node_id = 2;
+        let mut log = InputLog::new(7, app.session(), (400., 0., 0.), Vec::new());
+
+        let records: Vec<InputRecord> = vec![
+            click((100., 300.), &[]),             // (0.25, 0.75) に点 2 を追加
+            drag((300., 300.), (320., 120.), 0.), // 点 1 を (0.8, 0.3) へ
+            click((100., 100.), &["shiftKey"]),   // 点 0 を削除
+            drag((200., 200.), (240., 200.), 1.), // 中ボタンで表示を右に 40px
+            click((240., 200.), &[]),             // (0.5, 0.5) に点 3 を追加
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        // 記録する側: 記録を始めたときの状態から入力を渡す
+        app.reset_for_replay(&log);
+        for record in records.iter() {
+            if let InputRecord::Event { event, data, .. } = record {
+                app.handle_pointer(&data.to_pointer_input(event).unwrap());
+            }
+        }
+        log.records = records;
+        let expected = app.session();
+        let mut sites: Vec<(NodeId, f64, f64)> = expected
+            .sites
+            .iter()
+            .map(|s| (s.node_id, s.x, s.y))
+            .collect();
+        sites.sort_by_key(|s| s.0);
+        assert_eq!(vec![(1, 0.8, 0.3), (2, 0.25, 0.75), (3, 0.5, 0.5)], sites);
+        assert_eq!(4, expected.next_node_id);
+        log.expected = Some(expected);
+
+        // 別のアプリで JSON から読み直して再生する
+        let log = InputLog::from_json(&log.to_json()).unwrap();
+        let mut replayed = new_app();
+        replayed.reset_for_replay(&log);
+        assert_eq!(Ok(()), replayed.replay_records(&log));
+        assert!(replayed.history.can_undo());
+
+        // 結果が違えばエラーになる
+        let mut wrong = log.clone();
+        wrong.expected.as_mut().unwrap().sites[0].x += 0.01;
+        let mut replayed = new_app();
+        replayed.reset_for_replay(&wrong);
+        assert!(replayed.replay_records(&wrong).is_err());
+    }
+}
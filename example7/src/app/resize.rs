@@ -0,0 +1,79 @@
+use crate::dom_util::{device_pixel_ratio, fit_canvas_to_display};
+use crate::geometry::vec2d::Vec2d;
+use crate::{get_element_by_id, get_nested_property, my_app_mut, window, Handler, MyApp};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Canvas の表示の大きさと、描画バッファの倍率
+pub struct CanvasSize {
+    pub size: Vec2d,      // 表示の大きさ(CSS px)。マウスの位置や Viewport はこの単位
+    pub pixel_ratio: f64, // 描画バッファの画素数 / CSS px
+}
+
+impl Default for CanvasSize {
+    fn default() -> Self {
+        Self {
+            size: Vec2d { x: 0., y: 0. },
+            pixel_ratio: 1.,
+        }
+    }
+}
+
+impl MyApp {
+    // Canvas の大きさか devicePixelRatio が変わったら、描画バッファを合わせて描き直す
+    pub fn on_resize(&mut self, _e: JsValue) {
+        let before = self.canvas.size;
+        if self.resize_canvas() {
+            // 表示している中心を変えない
+            self.viewport.pan((self.canvas.size - before).div(2.));
+            self.hover_cell = None;
+            self.draw();
+        }
+    }
+
+    // 描画バッファを 表示の大きさ(CSS px) × devicePixelRatio にする。変えたら true
+    pub fn resize_canvas(&mut self) -> bool {
+        let canvas: web_sys::HtmlCanvasElement = get_element_by_id("canvas");
+        let size = Vec2d {
+            x: canvas.client_width() as f64,
+            y: canvas.client_height() as f64,
+        };
+        let ratio = device_pixel_ratio();
+        // 表示されていなければ何もしない
+        if size.x <= 0.
+            || size.y <= 0.
+            || (size == self.canvas.size && ratio == self.canvas.pixel_ratio)
+        {
+            return false;
+        }
+        self.canvas.size = size;
+        self.canvas.pixel_ratio = ratio;
+        fit_canvas_to_display(&canvas);
+        true
+    }
+}
+
+// id の要素の大きさが変わったら handler を呼ぶ。
+// ResizeObserver は web-sys のこの版に無いので JS から探す。無ければウィンドウの resize だけ
+pub fn observe_resize(id: &str, handler: Handler) {
+    let elem: web_sys::HtmlElement = get_element_by_id(id);
+    let c = Closure::wrap(Box::new(move |e| {
+        handler(my_app_mut(), e);
+    }) as Box<dyn FnMut(JsValue)>);
+    // devicePixelRatio はブラウザの拡大率を変えると変わり、そのときは resize が来る
+    window()
+        .add_event_listener_with_callback("resize", c.as_ref().unchecked_ref())
+        .unwrap();
+    let constructor = get_nested_property(&window(), vec!["ResizeObserver"])
+        .and_then(|v| v.dyn_into::<js_sys::Function>().ok());
+    if let Some(constructor) = constructor {
+        let observer =
+            js_sys::Reflect::construct(&constructor, &js_sys::Array::of1(c.as_ref())).unwrap();
+        get_nested_property(&observer, vec!["observe"])
+            .unwrap()
+            .unchecked_into::<js_sys::Function>()
+            .call1(&observer, &elem)
+            .unwrap();
+    }
+    c.forget(); // c を Rustのメモリ管理から外して JSのGCにわたす
+}
@@ -0,0 +1,157 @@
+use crate::geometry::voronoi_diagram::history::EditOp;
+use crate::session::{Session, SessionStore};
+use crate::storage::{LocalStorage, MemoryStorage, Storage};
+use crate::{document, get_element_by_id, log, MyApp};
+use wasm_bindgen::JsValue;
+
+/// 保存先と、最後に自動保存したときの状態
+pub struct Sessions {
+    store: SessionStore,
+    saved_version: Option<u64>, // 最後に自動保存したときの history.version()
+}
+
+impl Sessions {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        Self {
+            store: SessionStore::new(storage),
+            saved_version: None,
+        }
+    }
+
+    // 履歴に残らない変更をしたので、次のコマで保存し直す
+    pub fn mark_unsaved(&mut self) {
+        self.saved_version = None;
+    }
+}
+
+impl MyApp {
+    // 前回の続きから始める
+    pub fn restore_autosave(&mut self) {
+        match self.sessions.store.load_autosave() {
+            Ok(Some(session)) => {
+                self.restore(&session, false);
+                self.history.clear();
+            }
+            Ok(None) => {}
+            Err(e) => {
+                set_session_status(&format!("failed to restore: {}", e));
+            }
+        }
+        self.sessions.saved_version = Some(self.history.version());
+        self.update_snapshot_list();
+    }
+
+    // 今の図を保存する形にする
+    pub fn session(&self) -> Session {
+        Session::new(&self.diagram, self.next_node_id, &self.cell_values)
+    }
+
+    // 図が変わっていたら保存する
+    pub fn autosave(&mut self) {
+        if self.sessions.saved_version == Some(self.history.version()) {
+            return;
+        }
+        let session = self.session();
+        if let Err(e) = self.sessions.store.autosave(&session) {
+            set_session_status(&format!("failed to autosave: {}", e));
+        }
+        // 失敗しても毎コマ試さないように、保存したことにする
+        self.sessions.saved_version = Some(self.history.version());
+    }
+
+    // record_history が true なら元に戻せるように履歴に積む
+    pub fn restore(&mut self, session: &Session, record_history: bool) {
+        let op = EditOp::replace_all(&self.diagram, session.points());
+        if record_history {
+            self.edit(op);
+        } else {
+            if let Err(e) = op.apply(&mut self.diagram) {
+                set_session_status(&format!("failed to restore: {}", e));
+                return;
+            }
+            self.clear_layers();
+        }
+        self.next_node_id = self.next_node_id.max(session.next_node_id);
+        self.cell_values = session.cell_values();
+    }
+
+    pub fn on_save_snapshot(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("snapshot_name");
+        let name = elem.value().trim().to_string();
+        let session = self.session();
+        match self.sessions.store.save_snapshot(&name, &session) {
+            Ok(()) => {
+                set_session_status(&format!("saved {}", name));
+                self.update_snapshot_list();
+                let select: web_sys::HtmlSelectElement = get_element_by_id("snapshots");
+                select.set_value(&name);
+            }
+            Err(e) => {
+                set_session_status(&format!("failed to save {}: {}", name, e));
+            }
+        }
+    }
+
+    pub fn on_load_snapshot(&mut self, _e: JsValue) {
+        let select: web_sys::HtmlSelectElement = get_element_by_id("snapshots");
+        let name = select.value();
+        match self.sessions.store.load_snapshot(&name) {
+            Ok(session) => {
+                self.restore(&session, true);
+                set_session_status(&format!("loaded {}", name));
+                self.draw();
+            }
+            Err(e) => {
+                set_session_status(&format!("failed to load {}: {}", name, e));
+            }
+        }
+    }
+
+    pub fn on_delete_snapshot(&mut self, _e: JsValue) {
+        let select: web_sys::HtmlSelectElement = get_element_by_id("snapshots");
+        let name = select.value();
+        match self.sessions.store.delete_snapshot(&name) {
+            Ok(()) => {
+                set_session_status(&format!("deleted {}", name));
+                self.update_snapshot_list();
+            }
+            Err(e) => {
+                set_session_status(&format!("failed to delete {}: {}", name, e));
+            }
+        }
+    }
+
+    fn update_snapshot_list(&self) {
+        let select: web_sys::HtmlSelectElement = get_element_by_id("snapshots");
+        select.set_inner_html("");
+        let names = match self.sessions.store.snapshot_names() {
+            Ok(names) => names,
+            Err(e) => {
+                set_session_status(&format!("failed to list snapshots: {}", e));
+                return;
+            }
+        };
+        for name in names {
+            let option = document().create_element("option").unwrap();
+            option.set_text_content(Some(&name));
+            option.set_attribute("value", &name).unwrap();
+            select.append_child(&option).unwrap();
+        }
+    }
+}
+
+// localStorage が使えなければ、ページを閉じるまでのメモリに保存する
+pub fn open_storage() -> Box<dyn Storage> {
+    match LocalStorage::new() {
+        Ok(storage) => Box::new(storage),
+        Err(e) => {
+            log!("localStorage: {}", e);
+            Box::new(MemoryStorage::new())
+        }
+    }
+}
+
+fn set_session_status(message: &str) {
+    let elem: web_sys::HtmlElement = get_element_by_id("session_status");
+    elem.set_inner_text(message);
+}
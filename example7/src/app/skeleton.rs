@@ -0,0 +1,49 @@
+use crate::geometry::voronoi_diagram::medial_axis::{
+    medial_axis, MedialAxisConfig, MedialAxisEdge,
+};
+use crate::{draw_lines, get_element_by_id, log, MyApp};
+use wasm_bindgen::JsValue;
+
+// alpha shape の大きさに対する、境界上の点の間隔
+const SAMPLE_STEP_RATIO: f64 = 0.01;
+
+impl MyApp {
+    // alpha shape (Outline の黄色の線) の中心線を作る
+    pub fn on_skeleton(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("alpha");
+        let alpha = elem.value().parse::<f64>().unwrap_or(0.05);
+        let polygons = match self.diagram.alpha_shape(alpha) {
+            Ok(polygons) => polygons,
+            Err(e) => {
+                log!("alpha_shape: {}", e);
+                return;
+            }
+        };
+        let mut edges: Vec<MedialAxisEdge> = Vec::new();
+        for polygon in polygons.iter() {
+            let bounds = polygon.bounding_rect();
+            let step = bounds.size.x.max(bounds.size.y) * SAMPLE_STEP_RATIO;
+            match medial_axis(polygon, &MedialAxisConfig::new(step)) {
+                Ok(axis) => edges.extend(axis),
+                Err(e) => {
+                    log!("medial_axis: {}", e);
+                }
+            }
+        }
+        self.skeleton = Some(edges);
+        self.draw();
+    }
+
+    pub fn draw_skeleton(&self, context: &web_sys::CanvasRenderingContext2d) {
+        let edges = match &self.skeleton {
+            Some(edges) => edges,
+            None => return,
+        };
+        context.set_line_width(2.);
+        context.set_stroke_style(&JsValue::from("rgb(255, 0, 255, 1)"));
+        for edge in edges.iter() {
+            draw_lines(context, &self.viewport, std::slice::from_ref(&edge.line));
+        }
+        context.set_line_width(1.);
+    }
+}
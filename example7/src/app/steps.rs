@@ -0,0 +1,172 @@
+use crate::geometry::line::Line;
+use crate::geometry::voronoi_diagram::cell_key::CellKey;
+use crate::geometry::voronoi_diagram::trace::{CollectingTraceSink, TraceEvent};
+use crate::geometry::voronoi_diagram::types::{VoronoiCenterPoint, VoronoiError};
+use crate::{draw_lines, draw_point, fill_polygon, get_element_by_id, MyApp};
+use wasm_bindgen::JsValue;
+
+/// ステップ実行: クリックで追加した点の add_point の途中経過を1つずつ表示する
+#[derive(Default)]
+pub struct Steps {
+    enabled: bool,
+    events: Vec<TraceEvent>,
+    index: usize,
+    lines_before: Vec<Line>, // 点を追加する前のセルの境界線
+}
+
+impl Steps {
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    // 再生中(最後のステップより前)か
+    fn is_playing(&self) -> bool {
+        self.enabled && self.index + 1 < self.events.len()
+    }
+}
+
+impl MyApp {
+    pub fn on_step_mode(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("step_mode");
+        self.steps.enabled = elem.checked();
+        self.steps.clear();
+        self.draw();
+    }
+
+    pub fn on_prev_step(&mut self, _e: JsValue) {
+        if self.steps.index > 0 {
+            self.steps.index -= 1;
+        }
+        self.draw();
+    }
+
+    pub fn on_next_step(&mut self, _e: JsValue) {
+        if self.steps.index + 1 < self.steps.events.len() {
+            self.steps.index += 1;
+        }
+        self.draw();
+    }
+
+    // ステップ実行中なら、この点を追加する間だけ途中経過を貯めておく
+    pub fn add_point_with_steps(
+        &mut self,
+        vp: &VoronoiCenterPoint,
+    ) -> Result<CellKey, VoronoiError> {
+        if !self.steps.enabled {
+            return self.diagram.add_point(vp);
+        }
+        self.steps.lines_before = self.diagram_lines();
+        let sink = CollectingTraceSink::new();
+        let events = sink.events();
+        let saved_sink = self.diagram.trace_sink.replace(Box::new(sink));
+        let result = self.diagram.add_point(vp);
+        self.diagram.trace_sink = saved_sink;
+        self.steps.events = events.borrow().clone();
+        self.steps.index = 0;
+        result
+    }
+
+    // 再生中なら点を追加する前の図を表示する
+    pub fn step_lines(&self) -> Vec<Line> {
+        if self.steps.is_playing() {
+            self.steps.lines_before.clone()
+        } else {
+            self.diagram_lines()
+        }
+    }
+
+    pub fn draw_step(&self, context: &web_sys::CanvasRenderingContext2d) {
+        let info: web_sys::HtmlElement = get_element_by_id("step_info");
+        let step = match self.steps.events.get(self.steps.index) {
+            Some(step) if self.steps.enabled => step,
+            _ => {
+                info.set_inner_text("");
+                return;
+            }
+        };
+        info.set_inner_text(&format!(
+            "{}/{}: {}",
+            self.steps.index + 1,
+            self.steps.events.len(),
+            step
+        ));
+
+        context.set_line_width(2.);
+        match step {
+            TraceEvent::InitCell { point, .. } | TraceEvent::Locate { point, .. } => {
+                context.set_fill_style(&JsValue::from("rgb(255, 255, 0, 1)"));
+                draw_point(context, &self.viewport, *point, 5.);
+            }
+            TraceEvent::Bisector {
+                site,
+                polygon,
+                line,
+                ..
+            } => {
+                context.set_fill_style(&JsValue::from("rgba(0, 128, 255, 0.4)"));
+                fill_polygon(context, &self.viewport, polygon);
+                context.set_fill_style(&JsValue::from("rgb(0, 128, 255, 1)"));
+                draw_point(context, &self.viewport, *site, 5.);
+                context.set_stroke_style(&JsValue::from("rgb(255, 255, 0, 1)"));
+                draw_lines(context, &self.viewport, std::slice::from_ref(line));
+            }
+            TraceEvent::BoundPoints {
+                remain_points,
+                separated_points,
+                ..
+            } => {
+                context.set_fill_style(&JsValue::from("rgb(0, 128, 255, 1)"));
+                for pos in remain_points.iter() {
+                    draw_point(context, &self.viewport, *pos, 4.);
+                }
+                context.set_fill_style(&JsValue::from("rgb(255, 128, 0, 1)"));
+                for pos in separated_points.iter() {
+                    draw_point(context, &self.viewport, *pos, 4.);
+                }
+            }
+            TraceEvent::CellSplit {
+                middle_line,
+                cut_lines,
+                polygon,
+                ..
+            } => {
+                context.set_fill_style(&JsValue::from("rgba(0, 128, 255, 0.4)"));
+                fill_polygon(context, &self.viewport, polygon);
+                context.set_stroke_style(&JsValue::from("rgb(0, 255, 255, 1)"));
+                draw_lines(context, &self.viewport, cut_lines);
+                context.set_stroke_style(&JsValue::from("rgb(255, 0, 255, 1)"));
+                draw_lines(context, &self.viewport, std::slice::from_ref(middle_line));
+            }
+            TraceEvent::Queue { neighbor_cells } => {
+                context.set_fill_style(&JsValue::from("rgb(255, 128, 0, 1)"));
+                for cell_id in neighbor_cells.iter() {
+                    let pos = self.diagram.get_cell(*cell_id).borrow().point.pos;
+                    draw_point(context, &self.viewport, pos, 5.);
+                }
+            }
+            TraceEvent::NeighborEnqueued { cell_id, .. } => {
+                let pos = self.diagram.get_cell(*cell_id).borrow().point.pos;
+                context.set_fill_style(&JsValue::from("rgb(255, 128, 0, 1)"));
+                draw_point(context, &self.viewport, pos, 5.);
+            }
+            TraceEvent::BoundaryLineAdded { line } => {
+                context.set_stroke_style(&JsValue::from("rgb(255, 255, 0, 1)"));
+                draw_lines(context, &self.viewport, std::slice::from_ref(line));
+            }
+            TraceEvent::AddCell { polygon, .. } => {
+                context.set_fill_style(&JsValue::from("rgba(255, 255, 0, 0.4)"));
+                fill_polygon(context, &self.viewport, polygon);
+            }
+            TraceEvent::RemoveCell { polygon, .. } => {
+                context.set_fill_style(&JsValue::from("rgba(255, 0, 0, 0.4)"));
+                fill_polygon(context, &self.viewport, polygon);
+            }
+            TraceEvent::CellMerged { polygon, .. } => {
+                context.set_fill_style(&JsValue::from("rgba(0, 128, 255, 0.4)"));
+                fill_polygon(context, &self.viewport, polygon);
+            }
+            TraceEvent::Rebuild { .. } => {}
+        }
+        context.set_line_width(1.);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::stippling::{stipple, to_svg, GrayImage, StippleConfig};
+use crate::{context2d, get_element_by_id, log, MyApp};
+use core::f64::consts::PI;
+use rand::Rng;
+use wasm_bindgen::JsValue;
+
+impl MyApp {
+    // いま Canvas に描かれている絵を点描にする (描画バッファの画素の大きさで)
+    pub fn on_stipple(&mut self, _e: JsValue) {
+        let canvas: web_sys::HtmlCanvasElement = get_element_by_id("canvas");
+        let (width, height) = (canvas.width() as usize, canvas.height() as usize);
+        let context = context2d("canvas");
+        let image_data = context
+            .get_image_data(0., 0., width as f64, height as f64)
+            .unwrap();
+        let image = match GrayImage::from_rgba(width, height, &image_data.data()) {
+            Ok(image) => image,
+            Err(e) => {
+                log!("stipple: {}", e);
+                return;
+            }
+        };
+        let elem: web_sys::HtmlInputElement = get_element_by_id("n_stipples");
+        let n = elem.value().parse::<usize>().unwrap_or(3000);
+        let mut config = StippleConfig::new(n, self.rng.gen());
+        config.iterations = 10;
+        match stipple(&image, &config) {
+            Ok(stipples) => {
+                let elem: web_sys::HtmlTextAreaElement = get_element_by_id("export");
+                elem.set_value(&to_svg(&stipples, width, height));
+                self.stipples = Some(stipples);
+            }
+            Err(e) => {
+                log!("stipple: {}", e);
+            }
+        }
+        self.draw();
+    }
+
+    // 点描があれば白地に描いて true (図は描かない)
+    pub fn draw_stipples(&self, context: &web_sys::CanvasRenderingContext2d) -> bool {
+        let stipples = match &self.stipples {
+            Some(stipples) => stipples,
+            None => return false,
+        };
+        // 点描は描画バッファの画素の座標
+        context.set_transform(1., 0., 0., 1., 0., 0.).unwrap();
+        let canvas: web_sys::HtmlCanvasElement = get_element_by_id("canvas");
+        context.set_fill_style(&JsValue::from("rgb(255, 255, 255, 1)"));
+        context.fill_rect(0., 0., canvas.width() as f64, canvas.height() as f64);
+        context.set_fill_style(&JsValue::from("rgb(0, 0, 0, 1)"));
+        for s in stipples.iter() {
+            context.begin_path();
+            context
+                .arc(s.pos.x, s.pos.y, s.radius, 0., PI * 2.)
+                .unwrap();
+            context.fill();
+        }
+        true
+    }
+}
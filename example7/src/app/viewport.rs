@@ -0,0 +1,89 @@
+use crate::geometry::polygon::Polygon;
+use crate::geometry::vec2d::Vec2d;
+use crate::{get_nested_property, MyApp};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+const FIT_PADDING: f64 = 20.; // 全体表示のときの余白(px)
+const WHEEL_ZOOM_SPEED: f64 = 0.002; // ホイール1目盛り(deltaY=100)で約1.2倍
+
+/// 何もないところをドラッグして表示を動かしている途中
+pub struct Pan {
+    last: Vec2d, // 前回の画面上の位置
+    moved: bool,
+}
+
+impl MyApp {
+    pub fn start_pan(&mut self, screen: Vec2d) {
+        self.pan = Some(Pan {
+            last: screen,
+            moved: false,
+        });
+    }
+
+    // 表示を動かしている途中なら screen まで動かす。動かしたら true
+    pub fn pan_to(&mut self, screen: Vec2d) -> bool {
+        let pan = match self.pan.as_mut() {
+            Some(pan) if pan.last != screen => pan,
+            _ => return false,
+        };
+        self.viewport.pan(screen - pan.last);
+        pan.last = screen;
+        pan.moved = true;
+        true
+    }
+
+    // 表示を動かし終える。少しでも動かしていたら true
+    pub fn end_pan(&mut self) -> bool {
+        matches!(self.pan.take(), Some(Pan { moved: true, .. }))
+    }
+
+    // 2本指で表示を動かしながら拡大・縮小 (描き直すのは呼んだ側)
+    pub fn on_pinch(&mut self, center: Vec2d, scale: f64, delta: Vec2d) {
+        self.viewport.pan(delta);
+        self.viewport.zoom_at(center, scale);
+        self.hover_cell = None;
+    }
+
+    // マウスの位置を中心に拡大・縮小
+    pub fn on_wheel(&mut self, e: JsValue) {
+        e.unchecked_ref::<web_sys::Event>().prevent_default();
+        let delta = get_nested_property(&e, vec!["deltaY"])
+            .and_then(|d| d.as_f64())
+            .unwrap_or(0.);
+        self.viewport
+            .zoom_at(screen_pos(&e), (-delta * WHEEL_ZOOM_SPEED).exp());
+        self.hover_cell = None;
+        self.draw();
+    }
+
+    // 点が全部入るように表示する(点が2つより少なければ外周全体)
+    pub fn on_fit(&mut self, _e: JsValue) {
+        let bounds = self.diagram.outbound.polygon.bounding_rect();
+        let rect = if self.diagram.cells.len() < 2 {
+            bounds
+        } else {
+            Polygon::new(self.diagram.points().iter().map(|p| p.pos).collect()).bounding_rect()
+        };
+        self.viewport.fit(&rect, self.canvas.size, FIT_PADDING);
+        self.draw();
+    }
+
+    // マウスイベントの図の上の位置
+    pub fn event_pos(&self, e: &JsValue) -> Vec2d {
+        self.viewport.to_world(screen_pos(e))
+    }
+}
+
+// マウスイベントの Canvas 上の位置(px)
+fn screen_pos(e: &JsValue) -> Vec2d {
+    let x = get_nested_property(e, vec!["offsetX"])
+        .unwrap()
+        .as_f64()
+        .unwrap();
+    let y = get_nested_property(e, vec!["offsetY"])
+        .unwrap()
+        .as_f64()
+        .unwrap();
+    Vec2d { x, y }
+}
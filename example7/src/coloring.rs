@@ -0,0 +1,372 @@
+use crate::geometry::voronoi_diagram::types::{ClusterId, NodeId};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use std::collections::{BTreeSet, HashMap};
+
+/// セルを何で塗り分けるか
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorBy {
+    Nothing,
+    Cluster,
+    Area,
+    Value,     // NodeId ごとに与えた値
+    Neighbors, // 隣り合うセルが違う色になるように塗る
+}
+
+impl ColorBy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "cluster" => ColorBy::Cluster,
+            "area" => ColorBy::Area,
+            "value" => ColorBy::Value,
+            "neighbors" => ColorBy::Neighbors,
+            _ => ColorBy::Nothing,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Palette {
+    Viridis,
+    Rainbow,
+    Grayscale,
+    Category, // 区別しやすい10色を繰り返す
+}
+
+// matplotlib の viridis から9色を抜き出したもの
+const VIRIDIS: [(u8, u8, u8); 9] = [
+    (68, 1, 84),
+    (71, 45, 123),
+    (59, 82, 139),
+    (44, 114, 142),
+    (33, 145, 140),
+    (40, 174, 128),
+    (94, 201, 98),
+    (173, 220, 48),
+    (253, 231, 37),
+];
+
+// d3 の schemeCategory10
+const CATEGORY10: [(u8, u8, u8); 10] = [
+    (31, 119, 180),
+    (255, 127, 14),
+    (44, 160, 44),
+    (214, 39, 40),
+    (148, 103, 189),
+    (140, 86, 75),
+    (227, 119, 194),
+    (127, 127, 127),
+    (188, 189, 34),
+    (23, 190, 207),
+];
+
+impl Palette {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "rainbow" => Palette::Rainbow,
+            "grayscale" => Palette::Grayscale,
+            "category" => Palette::Category,
+            _ => Palette::Viridis,
+        }
+    }
+
+    /// t (0~1) の色
+    pub fn color(&self, t: f64) -> String {
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+        match self {
+            Palette::Viridis => {
+                let x = t * (VIRIDIS.len() - 1) as f64;
+                let i = (x.floor() as usize).min(VIRIDIS.len() - 2);
+                let (c1, c2, f) = (VIRIDIS[i], VIRIDIS[i + 1], x - i as f64);
+                let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+                rgb((mix(c1.0, c2.0), mix(c1.1, c2.1), mix(c1.2, c2.2)))
+            }
+            Palette::Rainbow => format!("hsl({:.0}, 90%, 55%)", 240. * (1. - t)),
+            Palette::Grayscale => {
+                let v = (40. + 200. * t).round() as u8;
+                rgb((v, v, v))
+            }
+            Palette::Category => {
+                let i = (t * (CATEGORY10.len() - 1) as f64).round() as usize;
+                rgb(CATEGORY10[i])
+            }
+        }
+    }
+
+    /// index 番目の区分の色。count は区分の数
+    pub fn category_color(&self, index: usize, count: usize) -> String {
+        match self {
+            Palette::Category => rgb(CATEGORY10[index % CATEGORY10.len()]),
+            _ if count <= 1 => self.color(0.5),
+            _ => self.color(index as f64 / (count - 1) as f64),
+        }
+    }
+}
+
+fn rgb(c: (u8, u8, u8)) -> String {
+    format!("rgb({}, {}, {})", c.0, c.1, c.2)
+}
+
+/// 凡例。Range は min~max の連続値、Categories はラベルと色の組
+pub enum Legend {
+    Range {
+        title: String,
+        min: f64,
+        max: f64,
+        palette: Palette,
+    },
+    Categories {
+        title: String,
+        items: Vec<(String, String)>,
+        omitted: usize, // 表示しきれなかった数
+    },
+}
+
+const MAX_LEGEND_ITEMS: usize = 10;
+
+/// CellId ごとの色 (値が無いセルは None) と凡例
+pub fn cell_colors(
+    diagram: &VoronoiDiagram,
+    color_by: ColorBy,
+    palette: Palette,
+    values: &HashMap<NodeId, f64>,
+) -> (Vec<Option<String>>, Option<Legend>) {
+    match color_by {
+        ColorBy::Nothing => (vec![None; diagram.cells.len()], None),
+        ColorBy::Cluster => {
+            let cluster_ids: Vec<ClusterId> = diagram
+                .cells
+                .iter()
+                .map(|c| c.borrow().point.cluster_id)
+                .collect();
+            let uniq: Vec<ClusterId> = cluster_ids
+                .iter()
+                .cloned()
+                .collect::<BTreeSet<ClusterId>>()
+                .into_iter()
+                .collect();
+            let index: HashMap<ClusterId, usize> =
+                uniq.iter().enumerate().map(|(i, c)| (*c, i)).collect();
+            let indices: Vec<usize> = cluster_ids.iter().map(|c| index[c]).collect();
+            let labels: Vec<String> = uniq.iter().map(|c| c.to_string()).collect();
+            categorical(String::from("cluster"), &labels, &indices, palette)
+        }
+        ColorBy::Neighbors => {
+            let (indices, count) = neighbor_coloring(diagram);
+            let labels: Vec<String> = (1..=count).map(|i| i.to_string()).collect();
+            categorical(String::from("neighbors"), &labels, &indices, palette)
+        }
+        ColorBy::Area => {
+            let areas: Vec<Option<f64>> = diagram
+                .cells
+                .iter()
+                .map(|c| Some(c.borrow().bound.polygon.area()))
+                .collect();
+            continuous(String::from("area"), &areas, palette)
+        }
+        ColorBy::Value => {
+            let cell_values: Vec<Option<f64>> = diagram
+                .cells
+                .iter()
+                .map(|c| values.get(&c.borrow().point.node_id).cloned())
+                .collect();
+            continuous(String::from("value"), &cell_values, palette)
+        }
+    }
+}
+
+/// 隣り合うセルが同じ番号にならない色の番号 (DSatur)。CellId ごとの番号と、番号の数
+pub fn neighbor_coloring(diagram: &VoronoiDiagram) -> (Vec<usize>, usize) {
+    let n = diagram.cells.len();
+    let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for edge in diagram.adjacency_edges() {
+        neighbors[edge.cell1 as usize].push(edge.cell2 as usize);
+        neighbors[edge.cell2 as usize].push(edge.cell1 as usize);
+    }
+    let mut colors: Vec<Option<usize>> = vec![None; n];
+    // 隣のセルに使われている番号
+    let mut saturation: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); n];
+    let mut count = 0;
+    for _ in 0..n {
+        // 隣の番号の種類が一番多いセルから塗る。同じなら隣の数が多いセル
+        let cell = (0..n)
+            .filter(|c| colors[*c].is_none())
+            .max_by_key(|c| (saturation[*c].len(), neighbors[*c].len(), n - *c))
+            .unwrap();
+        let color = (0..).find(|i| !saturation[cell].contains(i)).unwrap();
+        colors[cell] = Some(color);
+        count = count.max(color + 1);
+        for neighbor in neighbors[cell].iter() {
+            saturation[*neighbor].insert(color);
+        }
+    }
+    (colors.into_iter().map(|c| c.unwrap()).collect(), count)
+}
+
+// indices[CellId] 番目の区分で塗り分ける。labels は区分の名前
+fn categorical(
+    title: String,
+    labels: &[String],
+    indices: &[usize],
+    palette: Palette,
+) -> (Vec<Option<String>>, Option<Legend>) {
+    let colors = indices
+        .iter()
+        .map(|i| Some(palette.category_color(*i, labels.len())))
+        .collect();
+    let items = labels
+        .iter()
+        .enumerate()
+        .take(MAX_LEGEND_ITEMS)
+        .map(|(i, label)| (label.clone(), palette.category_color(i, labels.len())))
+        .collect();
+    let legend = Legend::Categories {
+        title,
+        items,
+        omitted: labels.len().saturating_sub(MAX_LEGEND_ITEMS),
+    };
+    (colors, Some(legend))
+}
+
+fn continuous(
+    title: String,
+    values: &[Option<f64>],
+    palette: Palette,
+) -> (Vec<Option<String>>, Option<Legend>) {
+    let known: Vec<f64> = values.iter().filter_map(|v| *v).collect();
+    if known.is_empty() {
+        return (vec![None; values.len()], None);
+    }
+    let min = known.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = known.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let colors = values
+        .iter()
+        .map(|v| {
+            v.map(|v| {
+                if max > min {
+                    palette.color((v - min) / (max - min))
+                } else {
+                    palette.color(0.5)
+                }
+            })
+        })
+        .collect();
+    let legend = Legend::Range {
+        title,
+        min,
+        max,
+        palette,
+    };
+    (colors, Some(legend))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::vec2d::Vec2d;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_diagram(n: u32, clusters: u32) -> VoronoiDiagram {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut points: Vec<VoronoiCenterPoint> = (0..n)
+            .map(|node_id| VoronoiCenterPoint {
+                pos: Vec2d {
+                    x: rng.gen(),
+                    y: rng.gen(),
+                },
+                node_id,
+                cluster_id: node_id % clusters,
+            })
+            .collect();
+        let mut diagram = VoronoiDiagram::new();
+        diagram.calculate_diagram(&mut points, 2).unwrap();
+        diagram
+    }
+
+    #[test]
+    fn neighbors_have_different_colors() {
+        let diagram = random_diagram(300, 1);
+        let (indices, count) = neighbor_coloring(&diagram);
+        assert_eq!(diagram.cells.len(), indices.len());
+        assert!(count <= 6, "{} colors", count);
+        assert!(indices.iter().all(|i| *i < count));
+
+        let (colors, legend) = cell_colors(
+            &diagram,
+            ColorBy::Neighbors,
+            Palette::Category,
+            &HashMap::new(),
+        );
+        let edges = diagram.adjacency_edges();
+        assert!(!edges.is_empty());
+        for edge in edges.iter() {
+            let (c1, c2) = (edge.cell1 as usize, edge.cell2 as usize);
+            assert_ne!(indices[c1], indices[c2]);
+            assert_ne!(colors[c1], colors[c2]);
+        }
+        match legend {
+            Some(Legend::Categories { items, omitted, .. }) => {
+                assert_eq!((count, 0), (items.len(), omitted))
+            }
+            _ => panic!("no legend"),
+        }
+    }
+
+    #[test]
+    fn category_palette_cycles() {
+        let palette = Palette::Category;
+        for (i, c) in CATEGORY10.iter().enumerate() {
+            assert_eq!(rgb(*c), palette.category_color(i, 25));
+            assert_eq!(
+                palette.category_color(i, 25),
+                palette.category_color(i + CATEGORY10.len(), 25)
+            );
+        }
+        // 連続的な色は区分の数で 0~1 に割り振る
+        let palette = Palette::Viridis;
+        assert_eq!(palette.color(0.), palette.category_color(0, 5));
+        assert_eq!(palette.color(1.), palette.category_color(4, 5));
+        assert_eq!(palette.color(0.5), palette.category_color(0, 1));
+        assert_eq!("rgb(68, 1, 84)", palette.color(f64::NAN));
+        assert_eq!("rgb(253, 231, 37)", palette.color(2.));
+    }
+
+    #[test]
+    fn legend_items_are_limited() {
+        for (clusters, shown, omitted) in [(3, 3, 0), (10, 10, 0), (15, 10, 5)].iter() {
+            let diagram = random_diagram(60, *clusters);
+            let (colors, legend) = cell_colors(
+                &diagram,
+                ColorBy::Cluster,
+                Palette::Category,
+                &HashMap::new(),
+            );
+            assert!(colors.iter().all(|c| c.is_some()));
+            match legend {
+                Some(Legend::Categories {
+                    title,
+                    items,
+                    omitted: o,
+                }) => {
+                    assert_eq!("cluster", title);
+                    assert_eq!((*shown, *omitted), (items.len(), o));
+                    assert_eq!("0", items[0].0);
+                }
+                _ => panic!("no legend"),
+            }
+        }
+
+        // 値の無いセルは塗らず、凡例は値のあるセルの範囲
+        let diagram = random_diagram(20, 1);
+        let values: HashMap<NodeId, f64> = [(3, 2.), (5, -1.)].iter().cloned().collect();
+        let (colors, legend) = cell_colors(&diagram, ColorBy::Value, Palette::Viridis, &values);
+        assert_eq!(2, colors.iter().filter(|c| c.is_some()).count());
+        match legend {
+            Some(Legend::Range { min, max, .. }) => assert_eq!((-1., 2.), (min, max)),
+            _ => panic!("no legend"),
+        }
+        let (colors, legend) = cell_colors(&diagram, ColorBy::Nothing, Palette::Viridis, &values);
+        assert!(colors.iter().all(|c| c.is_none()) && legend.is_none());
+    }
+}
@@ -21,6 +21,16 @@ impl Line {
         ret
     }
 
+    /// 端点や線上で接するだけのときは false
+    pub fn is_cross(l1: &Line, l2: &Line) -> bool {
+        Line::half_side_product(l1, l2) < 0. && Line::half_side_product(l2, l1) < 0.
+    }
+
+    #[inline]
+    fn half_side_product(l1: &Line, l2: &Line) -> f64 {
+        l1.point_side_value(l2.p1) * l1.point_side_value(l2.p2)
+    }
+
     #[inline]
     fn half_is_intersect(l1: &Line, l2: &Line) -> bool {
         let s =
@@ -32,8 +42,13 @@ impl Line {
 
     /// Line に対して どっち側にptがあるか。 return -1. or 1.
     pub fn point_side(&self, pt: Vec2d) -> f64 {
+        self.point_side_value(pt).signum()
+    }
+
+    /// point_side の符号付きの値(Lineを延長した直線からの距離に比例する)
+    pub fn point_side_value(&self, pt: Vec2d) -> f64 {
         let l1 = self;
-        ((l1.p1.x - l1.p2.x) * (pt.y - l1.p1.y) - (l1.p1.y - l1.p2.y) * (pt.x - l1.p1.x)).signum()
+        (l1.p1.x - l1.p2.x) * (pt.y - l1.p1.y) - (l1.p1.y - l1.p2.y) * (pt.x - l1.p1.x)
     }
 
     pub fn intersection(l1: &Line, l2: &Line) -> Option<Vec2d> {
@@ -1,4 +1,5 @@
 use crate::geometry::line::Line;
+use crate::geometry::rect::Rect;
 use crate::geometry::vec2d::Vec2d;
 
 #[derive(Clone)]
@@ -30,6 +31,7 @@ impl Polygon {
         lines
     }
 
+    #[allow(dead_code)]
     pub fn is_include(&self, point: Vec2d) -> bool {
         // https://www.nttpc.co.jp/technology/number_algorithm.html
         // 1.1.Crossing Number Algorithm（交差数判定）
@@ -43,8 +45,178 @@ impl Polygon {
         cross_count % 2 == 1
     }
 
+    /// 境界上も含めて、pointが多角形の中にあるか(凸多角形専用)
+    #[allow(dead_code)]
+    pub fn contains(&self, point: Vec2d) -> bool {
+        let mut sign = 0.;
+        for line in self.lines.iter() {
+            let v = line.point_side_value(point);
+            if v == 0. {
+                continue;
+            }
+            let s = v.signum();
+            if sign == 0. {
+                sign = s;
+            } else if sign != s {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 外接する矩形
+    pub fn bounding_rect(&self) -> Rect {
+        let mut min = self.points[0];
+        let mut max = self.points[0];
+        for p in self.points.iter() {
+            min.x = min.x.min(p.x);
+            min.y = min.y.min(p.y);
+            max.x = max.x.max(p.x);
+            max.y = max.y.max(p.y);
+        }
+        Rect {
+            pos: min,
+            size: max - min,
+        }
+    }
+
+    /// 水平線 y との交差区間 (x_min, x_max)。交差しなければ None (凸多角形専用)
+    pub fn x_range_at(&self, y: f64) -> Option<(f64, f64)> {
+        let mut range: Option<(f64, f64)> = None;
+        for line in self.lines.iter() {
+            let (p1, p2) = (line.p1, line.p2);
+            if (p1.y - y) * (p2.y - y) > 0. || p1.y == p2.y {
+                continue;
+            }
+            let x = p1.x + (p2.x - p1.x) * (y - p1.y) / (p2.y - p1.y);
+            range = match range {
+                Some((x1, x2)) => Some((x1.min(x), x2.max(x))),
+                None => Some((x, x)),
+            };
+        }
+        range
+    }
+
+    /// 頂点の平均(凸多角形なら内側の点)
+    pub fn center(&self) -> Vec2d {
+        let mut sum = Vec2d { x: 0., y: 0. };
+        for p in self.points.iter() {
+            sum = sum + *p;
+        }
+        sum.div(self.points.len() as f64)
+    }
+
+    /// 2つの凸多角形が重なる(接する場合も含む)か
+    #[allow(dead_code)]
+    pub fn is_overlap(&self, other: &Polygon) -> bool {
+        if self.points.len() < 3 || other.points.len() < 3 {
+            return false;
+        }
+        let center = other.center();
+        let mut clipped = self.clone();
+        for line in other.lines.iter() {
+            clipped = clipped.clip_by_line(line, line.point_side(center));
+            if clipped.points.is_empty() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 線分のうち多角形の内側にある部分 (Cyrus-Beck, 凸多角形専用)
+    #[allow(dead_code)]
+    pub fn clip_line(&self, line: &Line) -> Option<Line> {
+        let center = self.center();
+        let (mut t1, mut t2) = (0., 1.);
+        let d = line.p2 - line.p1;
+        for edge in self.lines.iter() {
+            // 内側で正になるように向きをそろえる
+            let side = edge.point_side(center);
+            let v1 = edge.point_side_value(line.p1) * side;
+            let v2 = edge.point_side_value(line.p2) * side;
+            if v1 < 0. && v2 < 0. {
+                return None;
+            }
+            if v1 < 0. {
+                t1 = f64::max(t1, v1 / (v1 - v2));
+            } else if v2 < 0. {
+                t2 = f64::min(t2, v1 / (v1 - v2));
+            }
+        }
+        if t1 > t2 {
+            return None;
+        }
+        // 切らなかった端点はそのまま返す
+        let p1 = if t1 > 0. {
+            line.p1 + d.mul(t1)
+        } else {
+            line.p1
+        };
+        let p2 = if t2 < 1. {
+            line.p1 + d.mul(t2)
+        } else {
+            line.p2
+        };
+        Some(Line::new(p1, p2))
+    }
+
+    /// 面積
+    #[allow(dead_code)]
+    pub fn area(&self) -> f64 {
+        let n = self.points.len();
+        if n < 3 {
+            return 0.;
+        }
+        let mut s = 0.;
+        for i in 0..n {
+            let (p1, p2) = (self.points[i], self.points[(i + 1) % n]);
+            s += p1.x * p2.y - p2.x * p1.y;
+        }
+        (s / 2.).abs()
+    }
+
+    /// 重心 (面積で重み付け)
+    #[allow(dead_code)]
+    pub fn centroid(&self) -> Vec2d {
+        let n = self.points.len();
+        let mut a = 0.;
+        let mut c = Vec2d { x: 0., y: 0. };
+        for i in 0..n {
+            let (p1, p2) = (self.points[i], self.points[(i + 1) % n]);
+            let cross = p1.x * p2.y - p2.x * p1.y;
+            a += cross;
+            c = c + (p1 + p2).mul(cross);
+        }
+        if a == 0. {
+            return self.center();
+        }
+        c.div(3. * a)
+    }
+
+    /// line(を延長した直線)で切って、side(-1. or 1.)側に残る多角形を返す
+    #[allow(dead_code)]
+    pub fn clip_by_line(&self, line: &Line, side: f64) -> Polygon {
+        // Sutherland-Hodgman
+        let n = self.points.len();
+        let mut points: Vec<Vec2d> = Vec::with_capacity(n + 1);
+        for i in 0..n {
+            let (p1, p2) = (self.points[i], self.points[(i + 1) % n]);
+            let v1 = line.point_side_value(p1) * side;
+            let v2 = line.point_side_value(p2) * side;
+            if v1 >= 0. {
+                points.push(p1);
+            }
+            if (v1 > 0. && v2 < 0.) || (v1 < 0. && v2 > 0.) {
+                let t = v1 / (v1 - v2);
+                points.push(p1 + (p2 - p1).mul(t));
+            }
+        }
+        Polygon::new(points)
+    }
+
     /// point から右側に水平にのばした線が、lineと交差するか?
     #[inline]
+    #[allow(dead_code)]
     fn is_cross_with_line_to_right(point: &Vec2d, line: &Line) -> bool {
         let max_x = line.p1.x.max(line.p2.x).max(point.x) + 1.;
         let line_to_right = Line::new(
@@ -1,5 +1,7 @@
+use crate::geometry::polygon::Polygon;
 use crate::geometry::vec2d::Vec2d;
 
+#[derive(PartialEq, Debug)]
 pub struct Rect {
     pub pos: Vec2d,
     pub size: Vec2d,
@@ -21,4 +23,14 @@ impl Rect {
             && self.pos.y <= point.y
             && point.y < self.pos.y + self.size.y
     }
+
+    pub fn to_polygon(&self) -> Polygon {
+        let (p1, p2) = (self.pos, self.pos + self.size);
+        Polygon::new(vec![
+            Vec2d { x: p1.x, y: p1.y },
+            Vec2d { x: p2.x, y: p1.y },
+            Vec2d { x: p2.x, y: p2.y },
+            Vec2d { x: p1.x, y: p2.y },
+        ])
+    }
 }
@@ -0,0 +1,342 @@
+use crate::geometry::polygon::Polygon;
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{arg2pi_from_center, CellId, VoronoiError};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Delaunay 三角形。cells は中心点が反時計回り(画面では時計回り)に並ぶ
+#[allow(dead_code)]
+pub struct DelaunayTriangle {
+    pub cells: [CellId; 3],
+    pub circumcenter: Vec2d, // セルの頂点
+    pub circumradius: f64,
+}
+
+// 外接円の中心が外周の外にある三角形を探すときに、外周を広げる倍率と回数
+const EXPAND_RATIO: f64 = 4.;
+const EXPAND_TIMES: u32 = 12;
+// 凸包の辺上にあるとみなす距離(辺の長さに対する比率)
+const HULL_EPS: f64 = 1e-9;
+
+#[allow(dead_code)]
+impl VoronoiDiagram {
+    /// 中心点の Delaunay 三角形分割。
+    /// セルの頂点は外周の中にしか無いので、外接円の中心が外周の外にある三角形が足りなければ、
+    /// 外周を広げた図を作り直して探す。広げても見つからなければ FarCircumcenter を返す
+    pub fn delaunay_triangles(&self) -> Result<Vec<DelaunayTriangle>, VoronoiError> {
+        let count = self.delaunay_triangle_count();
+        let mut triangles = self.triangles_at_vertices();
+        let bounds = self.outbound.polygon.bounding_rect();
+        let (mut pos, mut size) = (bounds.pos, bounds.size);
+        for _ in 0..EXPAND_TIMES {
+            if triangles.len() >= count {
+                return Ok(triangles);
+            }
+            pos = pos - size.mul((EXPAND_RATIO - 1.) / 2.);
+            size = size.mul(EXPAND_RATIO);
+            // CellId の順に追加し直すので、CellId は同じになる
+            let mut diagram = VoronoiDiagram::with_bounds(Rect { pos, size });
+            for point in self.points().iter() {
+                diagram.add_point(point)?;
+            }
+            triangles = diagram.triangles_at_vertices();
+        }
+        if triangles.len() >= count {
+            Ok(triangles)
+        } else {
+            Err(VoronoiError::FarCircumcenter)
+        }
+    }
+
+    // 三角形分割の三角形の数。凸包の辺上の点も含めた凸包上の点の数を h として 2n - h - 2
+    fn delaunay_triangle_count(&self) -> usize {
+        let hull = self.convex_hull();
+        if hull.points.len() < 3 {
+            return 0;
+        }
+        let lines = Polygon::each_lines(&hull.points);
+        let on_hull = self
+            .unbounded_cells()
+            .into_iter()
+            .filter(|c| {
+                let pos = self.get_cell(*c).borrow().point.pos;
+                lines.iter().any(|l| {
+                    let (d, v) = (l.p2 - l.p1, pos - l.p1);
+                    let t = d.x * v.x + d.y * v.y;
+                    (d.x * v.y - d.y * v.x).abs() <= HULL_EPS * d.square()
+                        && 0. <= t
+                        && t <= d.square()
+                })
+            })
+            .count();
+        2 * self.cells.len() - on_hull - 2
+    }
+
+    // セルの頂点(外周上を除く)ごとに、そこで接するセルの中心点を結んだ三角形
+    fn triangles_at_vertices(&self) -> Vec<DelaunayTriangle> {
+        let mut vertices: HashMap<(i64, i64), (Vec2d, BTreeSet<CellId>)> = HashMap::new();
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            for point in cell.bound.points.iter() {
+                if point.on_outbound() {
+                    continue;
+                }
+                let key = (
+                    (point.pos.x * 1e9).round() as i64,
+                    (point.pos.y * 1e9).round() as i64,
+                );
+                vertices
+                    .entry(key)
+                    .or_insert((point.pos, BTreeSet::new()))
+                    .1
+                    .insert(cell.cell_id);
+            }
+        }
+
+        let mut triangles: Vec<DelaunayTriangle> = Vec::new();
+        for (_, (center, cell_ids)) in vertices.into_iter() {
+            if cell_ids.len() < 3 {
+                continue;
+            }
+            // 4つ以上のセルが接する(同じ円に乗る)ときは扇形に分ける
+            let mut around: Vec<(f64, CellId)> = cell_ids
+                .iter()
+                .map(|c| {
+                    let pos = self.get_cell(*c).borrow().point.pos;
+                    (arg2pi_from_center(&center, &pos), *c)
+                })
+                .collect();
+            around.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let radius = center.distance_from(self.get_cell(around[0].1).borrow().point.pos);
+            for i in 1..around.len() - 1 {
+                triangles.push(DelaunayTriangle {
+                    cells: [around[0].1, around[i].1, around[i + 1].1],
+                    circumcenter: center,
+                    circumradius: radius,
+                });
+            }
+        }
+        triangles.sort_by_key(|t| t.cells);
+        triangles
+    }
+
+    /// 外接円の半径が alpha 以下の Delaunay 三角形をつなげた領域の境界。
+    /// 離れた塊ごとの外側の境界と、穴の境界がそれぞれ Polygon になる
+    pub fn alpha_shape(&self, alpha: f64) -> Result<Vec<Polygon>, VoronoiError> {
+        // 残した三角形の有向辺のうち、逆向きの辺が無いものが境界
+        let mut directed: BTreeSet<(CellId, CellId)> = BTreeSet::new();
+        for triangle in self.delaunay_triangles()? {
+            if triangle.circumradius > alpha {
+                continue;
+            }
+            let c = triangle.cells;
+            for (a, b) in [(c[0], c[1]), (c[1], c[2]), (c[2], c[0])].iter() {
+                directed.insert((*a, *b));
+            }
+        }
+        let mut next: BTreeMap<CellId, Vec<CellId>> = BTreeMap::new();
+        for (a, b) in directed.iter() {
+            if !directed.contains(&(*b, *a)) {
+                next.entry(*a).or_default().push(*b);
+            }
+        }
+
+        // 境界の辺をたどって輪にする
+        let mut polygons: Vec<Polygon> = Vec::new();
+        while let Some(&start) = next.keys().next() {
+            let mut cell_ids: Vec<CellId> = Vec::new();
+            let mut current = start;
+            while let Some(to) = next.get_mut(&current) {
+                let to = to.pop().unwrap();
+                if next[&current].is_empty() {
+                    next.remove(&current);
+                }
+                cell_ids.push(current);
+                current = to;
+                if current == start {
+                    break;
+                }
+            }
+            if cell_ids.len() >= 3 {
+                polygons.push(Polygon::new(
+                    cell_ids
+                        .iter()
+                        .map(|c| self.get_cell(*c).borrow().point.pos)
+                        .collect(),
+                ));
+            }
+        }
+        Ok(polygons)
+    }
+
+    /// 外周に接するセル。外周が無限に広ければはみ出していく(有界でない)セルは全部含まれるが、
+    /// 外周に届いているだけで、外周を広げれば閉じるセルも含まれる
+    pub fn unbounded_cells(&self) -> Vec<CellId> {
+        self.cells
+            .iter()
+            .map(|cell| cell.borrow())
+            .filter(|cell| cell.lines.iter().any(|l| l.is_outbound()))
+            .map(|cell| cell.cell_id)
+            .collect()
+    }
+
+    /// 凸包の頂点になるセル(反時計回り)。凸包の点は必ず外周に接するセルの中にある
+    pub fn convex_hull_cells(&self) -> Vec<CellId> {
+        // Andrew's monotone chain
+        let mut sites: Vec<(Vec2d, CellId)> = self
+            .unbounded_cells()
+            .into_iter()
+            .map(|c| (self.get_cell(c).borrow().point.pos, c))
+            .collect();
+        if sites.len() < 3 {
+            return sites.iter().map(|(_, c)| *c).collect();
+        }
+        sites.sort_by(|a, b| (a.0.x, a.0.y).partial_cmp(&(b.0.x, b.0.y)).unwrap());
+        let cross =
+            |o: Vec2d, a: Vec2d, b: Vec2d| (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x);
+        let mut hull: Vec<(Vec2d, CellId)> = Vec::new();
+        for pass in 0..2 {
+            let start = hull.len();
+            let iter: Box<dyn Iterator<Item = &(Vec2d, CellId)>> = if pass == 0 {
+                Box::new(sites.iter())
+            } else {
+                Box::new(sites.iter().rev())
+            };
+            for site in iter {
+                while hull.len() >= start + 2
+                    && cross(hull[hull.len() - 2].0, hull[hull.len() - 1].0, site.0) <= 0.
+                {
+                    hull.pop();
+                }
+                hull.push(*site);
+            }
+            hull.pop(); // 次の列の始点と同じ
+        }
+        hull.iter().map(|(_, c)| *c).collect()
+    }
+
+    pub fn convex_hull(&self) -> Polygon {
+        Polygon::new(
+            self.convex_hull_cells()
+                .iter()
+                .map(|c| self.get_cell(*c).borrow().point.pos)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+
+    fn diagram_of(points: &[(f64, f64)]) -> VoronoiDiagram {
+        let mut diagram = VoronoiDiagram::new();
+        for (i, (x, y)) in points.iter().enumerate() {
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d { x: *x, y: *y },
+                node_id: i as u32,
+                cluster_id: 0,
+            };
+            diagram.add_point(&vp).unwrap();
+        }
+        diagram
+    }
+
+    fn sorted_points(polygon: &Polygon) -> Vec<(f64, f64)> {
+        let mut points: Vec<(f64, f64)> = polygon.points.iter().map(|p| (p.x, p.y)).collect();
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        points
+    }
+
+    // 正方形の頂点と内側の点
+    const SQUARE: [(f64, f64); 5] = [(0.2, 0.2), (0.8, 0.2), (0.8, 0.8), (0.2, 0.8), (0.45, 0.55)];
+
+    #[test]
+    fn convex_hull_of_square() {
+        let diagram = diagram_of(&SQUARE);
+        assert_eq!(vec![0, 1, 2, 3], diagram.convex_hull_cells());
+        assert!((diagram.convex_hull().area() - 0.36).abs() < 1e-12);
+
+        // 辺の途中の点は凸包の頂点にしない
+        let mut points = SQUARE.to_vec();
+        points.push((0.5, 0.2));
+        let diagram = diagram_of(&points);
+        assert_eq!(vec![0, 1, 2, 3], diagram.convex_hull_cells());
+        assert_eq!(5, diagram.delaunay_triangles().unwrap().len());
+    }
+
+    #[test]
+    fn alpha_shape_of_square() {
+        let diagram = diagram_of(&SQUARE);
+        let triangles = diagram.delaunay_triangles().unwrap();
+        assert_eq!(4, triangles.len());
+        assert!(triangles.iter().all(|t| t.cells.contains(&4)));
+
+        // alpha が大きければ凸包と同じ
+        let polygons = diagram.alpha_shape(f64::INFINITY).unwrap();
+        assert_eq!(1, polygons.len());
+        assert_eq!(
+            sorted_points(&diagram.convex_hull()),
+            sorted_points(&polygons[0])
+        );
+
+        // alpha が小さければ何も残らない
+        assert!(diagram.alpha_shape(0.).unwrap().is_empty());
+        assert!(diagram.alpha_shape(0.1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn alpha_shape_of_non_convex_sites() {
+        // 0.1 間隔の格子から、上辺の真ん中の切り欠きを抜いた U 字。
+        // 4点が同じ円に乗らないように少しずらす
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        for i in 0..7 {
+            for j in 0..7 {
+                if i == 3 && j >= 3 {
+                    continue;
+                }
+                let k = points.len() as f64;
+                points.push((
+                    0.2 + 0.1 * i as f64 + 0.003 * (k * 1.7).sin(),
+                    0.2 + 0.1 * j as f64 + 0.003 * (k * 2.3).cos(),
+                ));
+            }
+        }
+        let diagram = diagram_of(&points);
+        assert!(diagram.delaunay_triangles().is_ok());
+
+        // 格子の三角形の外接円の半径はおよそ 0.1/√2 で、切り欠きをまたぐ三角形はおよそ 0.1
+        let polygons = diagram.alpha_shape(0.08).unwrap();
+        assert_eq!(1, polygons.len());
+        assert_eq!(30, polygons[0].points.len());
+        assert!((polygons[0].area() - 0.30).abs() < 0.02);
+        let hull_area = diagram.convex_hull().area();
+        assert!((hull_area - 0.36).abs() < 0.01);
+        let polygons = diagram.alpha_shape(f64::INFINITY).unwrap();
+        assert_eq!(1, polygons.len());
+        assert!((polygons[0].area() - hull_area).abs() < 1e-12);
+    }
+
+    #[test]
+    fn far_circumcenter() {
+        // 外接円の中心 (0.5, 2.075) が外周の外にある三角形
+        let diagram = diagram_of(&[(0.1, 0.5), (0.9, 0.5), (0.5, 0.45)]);
+        let triangles = diagram.delaunay_triangles().unwrap();
+        assert_eq!(1, triangles.len());
+        let center = triangles[0].circumcenter;
+        assert!((center.x - 0.5).abs() < 1e-9 && (center.y - 2.075).abs() < 1e-9);
+        assert!((triangles[0].circumradius - 1.625).abs() < 1e-9);
+        assert_eq!(1, diagram.alpha_shape(2.).unwrap().len());
+        assert!(diagram.alpha_shape(1.).unwrap().is_empty());
+
+        // 外周をいくら広げても届かないほど遠ければエラーにする
+        let diagram = diagram_of(&[(0.1, 0.5), (0.9, 0.5), (0.5, 0.5 + 1e-12)]);
+        assert_eq!(
+            Some(VoronoiError::FarCircumcenter),
+            diagram.delaunay_triangles().err()
+        );
+        assert!(diagram.alpha_shape(f64::INFINITY).is_err());
+    }
+}
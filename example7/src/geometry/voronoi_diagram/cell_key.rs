@@ -0,0 +1,255 @@
+use crate::geometry::voronoi_diagram::types::{CellId, NodeId};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// セルを外から参照するためのID。
+/// CellId (cells の添字) は点の削除や移動で作り直すと変わるが、CellKey は変わらない。
+/// 削除されたセルの CellKey は generation が合わなくなるので、別のセルを指すことはない。
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CellKey {
+    pub index: u32,
+    pub generation: u32,
+}
+
+impl Display for CellKey {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+struct CellSlot {
+    generation: u32,
+    cell_id: Option<CellId>, // 空きなら None
+}
+
+/// CellKey <-> CellId <-> NodeId の対応表
+pub struct CellKeys {
+    slots: Vec<CellSlot>,
+    free_slots: Vec<u32>,
+    by_cell: Vec<CellKey>, // CellId -> CellKey
+    by_node: HashMap<NodeId, CellKey>,
+}
+
+#[allow(dead_code)]
+impl CellKeys {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            by_cell: Vec::new(),
+            by_node: HashMap::new(),
+        }
+    }
+
+    /// 新しいセル(cell_id は cells の末尾)に CellKey を割り当てる
+    pub fn insert(&mut self, node_id: NodeId, cell_id: CellId) -> CellKey {
+        let key = match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.cell_id = Some(cell_id);
+                CellKey {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(CellSlot {
+                    generation: 0,
+                    cell_id: Some(cell_id),
+                });
+                CellKey {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+        self.by_cell.push(key);
+        self.by_node.insert(node_id, key);
+        key
+    }
+
+    /// key を無効にする。by_cell は呼び出し側が remap で作り直すこと。
+    pub fn remove(&mut self, key: CellKey, node_id: NodeId) {
+        if self.cell_id(key).is_none() {
+            return;
+        }
+        let slot = &mut self.slots[key.index as usize];
+        slot.generation += 1;
+        slot.cell_id = None;
+        self.free_slots.push(key.index);
+        self.by_node.remove(&node_id);
+    }
+
+    /// セルを作り直した後の CellId で対応表を更新する。keys[cell_id] がそのセルの CellKey。
+    pub fn remap(&mut self, keys: Vec<CellKey>) {
+        for (cell_id, key) in keys.iter().enumerate() {
+            self.slots[key.index as usize].cell_id = Some(cell_id as CellId);
+        }
+        self.by_cell = keys;
+    }
+
+    /// 2つのセルの CellId を入れ替える
+    pub fn swap_cells(&mut self, a: CellId, b: CellId) {
+        self.by_cell.swap(a as usize, b as usize);
+        for cell_id in [a, b].iter() {
+            let key = self.by_cell[*cell_id as usize];
+            self.slots[key.index as usize].cell_id = Some(*cell_id);
+        }
+    }
+
+    pub fn cell_id(&self, key: CellKey) -> Option<CellId> {
+        let slot = self.slots.get(key.index as usize)?;
+        if slot.generation == key.generation {
+            slot.cell_id
+        } else {
+            None
+        }
+    }
+
+    pub fn key_of_cell(&self, cell_id: CellId) -> Option<CellKey> {
+        self.by_cell.get(cell_id as usize).copied()
+    }
+
+    pub fn key_of_node(&self, node_id: NodeId) -> Option<CellKey> {
+        self.by_node.get(&node_id).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::vec2d::Vec2d;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+    use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+
+    #[test]
+    fn reused_slot_bumps_generation() {
+        let mut keys = CellKeys::new();
+        let a = keys.insert(10, 0);
+        let b = keys.insert(11, 1);
+        keys.remove(a, 10);
+        keys.remap(vec![b]);
+        assert_eq!(None, keys.cell_id(a));
+        assert_eq!(None, keys.key_of_node(10));
+        assert_eq!(Some(0), keys.cell_id(b));
+
+        // 空いた場所を使い回すが、generation が違うので古い CellKey とは別物
+        let c = keys.insert(12, 1);
+        assert_eq!((a.index, a.generation + 1), (c.index, c.generation));
+        assert_eq!(None, keys.cell_id(a));
+        assert_eq!(Some(1), keys.cell_id(c));
+        assert_eq!(Some(c), keys.key_of_cell(1));
+
+        // 2回消しても generation は1回しか増えない
+        keys.remove(a, 10);
+        assert_eq!(Some(1), keys.cell_id(c));
+    }
+
+    #[test]
+    fn swap_cells_and_remap() {
+        let mut keys = CellKeys::new();
+        let a = keys.insert(0, 0);
+        let b = keys.insert(1, 1);
+        let c = keys.insert(2, 2);
+        keys.swap_cells(0, 2);
+        assert_eq!(
+            (Some(2), Some(1), Some(0)),
+            (keys.cell_id(a), keys.cell_id(b), keys.cell_id(c))
+        );
+        assert_eq!(Some(c), keys.key_of_cell(0));
+        keys.remap(vec![b, a, c]);
+        assert_eq!(
+            (Some(1), Some(0), Some(2)),
+            (keys.cell_id(a), keys.cell_id(b), keys.cell_id(c))
+        );
+        assert_eq!(Some(b), keys.key_of_node(1));
+    }
+
+    fn diagram_with(n: NodeId) -> VoronoiDiagram {
+        let mut diagram = VoronoiDiagram::new();
+        for node_id in 0..n {
+            let angle = node_id as f64 * 2.4;
+            let r = 0.05 + 0.4 * node_id as f64 / n as f64;
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d {
+                    x: 0.5 + r * angle.cos(),
+                    y: 0.5 + r * angle.sin(),
+                },
+                node_id,
+                cluster_id: 0,
+            };
+            diagram.add_point(&vp).unwrap();
+        }
+        diagram
+    }
+
+    // 残っている点の CellKey が、同じ NodeId のセルを指していること
+    fn assert_resolves(diagram: &VoronoiDiagram, keys: &[(NodeId, CellKey)]) {
+        for (node_id, key) in keys.iter() {
+            let cell = diagram.get_cell_by_key(*key).unwrap().borrow();
+            assert_eq!(*node_id, cell.point.node_id);
+            assert_eq!(Some(*key), diagram.key_of_node(*node_id));
+        }
+    }
+
+    #[test]
+    fn key_goes_stale_after_remove_point() {
+        let mut diagram = diagram_with(6);
+        let keys: Vec<(NodeId, CellKey)> = (0..6)
+            .map(|n| (n, diagram.key_of_node(n).unwrap()))
+            .collect();
+        diagram.remove_point(2).unwrap();
+        assert!(diagram.get_cell_by_key(keys[2].1).is_none());
+        assert_eq!(None, diagram.key_of_node(2));
+
+        // 消したセルの場所を使い回しても、古い CellKey は新しいセルを指さない
+        let vp = VoronoiCenterPoint {
+            pos: Vec2d { x: 0.9, y: 0.1 },
+            node_id: 6,
+            cluster_id: 0,
+        };
+        let new_key = diagram.add_point(&vp).unwrap();
+        assert_eq!(keys[2].1.index, new_key.index);
+        assert_ne!(keys[2].1.generation, new_key.generation);
+        assert!(diagram.get_cell_by_key(keys[2].1).is_none());
+        assert_eq!(
+            6,
+            diagram
+                .get_cell_by_key(new_key)
+                .unwrap()
+                .borrow()
+                .point
+                .node_id
+        );
+    }
+
+    #[test]
+    fn keys_resolve_after_rebuild_and_move() {
+        let mut diagram = diagram_with(8);
+        let mut keys: Vec<(NodeId, CellKey)> = (0..8)
+            .map(|n| (n, diagram.key_of_node(n).unwrap()))
+            .collect();
+        // remove_points は残りの点で作り直すので CellId が詰められる (remap)
+        diagram.remove_points(&[0, 5]).unwrap();
+        keys.retain(|(n, _)| *n != 0 && *n != 5);
+        assert_resolves(&diagram, &keys);
+        // 1つでも無い点があれば何も変えない
+        assert!(diagram.remove_points(&[1, 5]).is_err());
+        assert_resolves(&diagram, &keys);
+
+        // move_point_incremental は動かした点と最後のセルの CellId を入れ替える (swap_cells)
+        let cell_id = diagram.get_cell_by_key(keys[0].1).unwrap().borrow().cell_id;
+        diagram
+            .move_point_incremental(keys[0].0, Vec2d { x: 0.15, y: 0.85 })
+            .unwrap();
+        assert_ne!(
+            cell_id,
+            diagram.get_cell_by_key(keys[0].1).unwrap().borrow().cell_id
+        );
+        assert_resolves(&diagram, &keys);
+        diagram
+            .move_point(keys[1].0, Vec2d { x: 0.8, y: 0.8 })
+            .unwrap();
+        assert_resolves(&diagram, &keys);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::geometry::voronoi_diagram::types::{CellId, NodeId};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+
+/// 隣接するセルの組と、共有している辺の長さ
+pub struct AdjacencyEdge {
+    pub cell1: CellId,
+    pub cell2: CellId,
+    pub length: f64,
+}
+
+/// 全セルの多角形をまとめて詰めたもの (WebGL などにそのまま渡せる形)。
+/// i番目のセルの頂点は coords[offsets[i] * 2 .. offsets[i + 1] * 2] ([x0, y0, x1, y1, ...])。
+/// triangles は頂点の番号を3つずつ並べたもの (セルは凸なので扇形に分割)。
+pub struct PolygonBuffers {
+    pub coords: Vec<f64>,
+    pub offsets: Vec<u32>, // セルの数 + 1
+    pub cell_ids: Vec<CellId>,
+    pub node_ids: Vec<NodeId>,
+    pub triangles: Vec<u32>,
+}
+
+impl VoronoiDiagram {
+    pub fn polygon_buffers(&self) -> PolygonBuffers {
+        let mut buffers = PolygonBuffers {
+            coords: Vec::new(),
+            offsets: vec![0],
+            cell_ids: Vec::with_capacity(self.cells.len()),
+            node_ids: Vec::with_capacity(self.cells.len()),
+            triangles: Vec::new(),
+        };
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            let first = (buffers.coords.len() / 2) as u32;
+            let points = &cell.bound.polygon.points;
+            for p in points.iter() {
+                buffers.coords.push(p.x);
+                buffers.coords.push(p.y);
+            }
+            for i in 1..points.len().saturating_sub(1) as u32 {
+                buffers.triangles.extend(&[first, first + i, first + i + 1]);
+            }
+            buffers.offsets.push(first + points.len() as u32);
+            buffers.cell_ids.push(cell.cell_id);
+            buffers.node_ids.push(cell.point.node_id);
+        }
+        buffers
+    }
+
+    /// セルの隣接関係 (cell1 < cell2)
+    pub fn adjacency_edges(&self) -> Vec<AdjacencyEdge> {
+        // 同じ組の辺が複数あれば長さを足す
+        let mut edges: BTreeMap<(CellId, CellId), f64> = BTreeMap::new();
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            for line in cell.lines.iter() {
+                if let Some(another_cell_id) = line.another_cell(cell.cell_id) {
+                    if cell.cell_id < another_cell_id {
+                        let length = line.line.p1.distance_from(line.line.p2);
+                        *edges.entry((cell.cell_id, another_cell_id)).or_insert(0.) += length;
+                    }
+                }
+            }
+        }
+        edges
+            .into_iter()
+            .map(|((cell1, cell2), length)| AdjacencyEdge {
+                cell1,
+                cell2,
+                length,
+            })
+            .collect()
+    }
+
+    /// Graphviz の DOT 形式。ノードは NodeId、pos は中心の座標。
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("graph voronoi {\n");
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            let p = &cell.point;
+            writeln!(
+                s,
+                "  {} [pos=\"{},{}!\", cell_id={}, cluster_id={}];",
+                p.node_id, p.pos.x, p.pos.y, cell.cell_id, p.cluster_id
+            )
+            .unwrap();
+        }
+        for edge in self.adjacency_edges() {
+            writeln!(
+                s,
+                "  {} -- {} [length={}];",
+                self.get_cell(edge.cell1).borrow().point.node_id,
+                self.get_cell(edge.cell2).borrow().point.node_id,
+                edge.length
+            )
+            .unwrap();
+        }
+        s.push_str("}\n");
+        s
+    }
+
+    /// GraphML 形式。ノードは NodeId、属性は x, y, cell_id, cluster_id, 辺は length。
+    pub fn to_graphml(&self) -> String {
+        let mut s = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"x\" for=\"node\" attr.name=\"x\" attr.type=\"double\"/>\n",
+            "  <key id=\"y\" for=\"node\" attr.name=\"y\" attr.type=\"double\"/>\n",
+            "  <key id=\"cell_id\" for=\"node\" attr.name=\"cell_id\" attr.type=\"int\"/>\n",
+            "  <key id=\"cluster_id\" for=\"node\" attr.name=\"cluster_id\" attr.type=\"int\"/>\n",
+            "  <key id=\"length\" for=\"edge\" attr.name=\"length\" attr.type=\"double\"/>\n",
+            "  <graph id=\"voronoi\" edgedefault=\"undirected\">\n",
+        ));
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            let p = &cell.point;
+            writeln!(s, "    <node id=\"{}\">", graphml_node_id(p.node_id)).unwrap();
+            write_graphml_data(&mut s, "x", p.pos.x);
+            write_graphml_data(&mut s, "y", p.pos.y);
+            write_graphml_data(&mut s, "cell_id", cell.cell_id);
+            write_graphml_data(&mut s, "cluster_id", p.cluster_id);
+            s.push_str("    </node>\n");
+        }
+        for edge in self.adjacency_edges() {
+            writeln!(
+                s,
+                "    <edge source=\"{}\" target=\"{}\">",
+                graphml_node_id(self.get_cell(edge.cell1).borrow().point.node_id),
+                graphml_node_id(self.get_cell(edge.cell2).borrow().point.node_id),
+            )
+            .unwrap();
+            write_graphml_data(&mut s, "length", edge.length);
+            s.push_str("    </edge>\n");
+        }
+        s.push_str("  </graph>\n</graphml>\n");
+        s
+    }
+}
+
+// XML の属性値・テキストに入れられない文字を実体参照にする
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn graphml_node_id(node_id: NodeId) -> String {
+    escape_xml(&format!("n{}", node_id))
+}
+
+fn write_graphml_data<T: Display>(s: &mut String, key: &str, value: T) {
+    writeln!(
+        s,
+        "      <data key=\"{}\">{}</data>",
+        escape_xml(key),
+        escape_xml(&value.to_string())
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::vec2d::Vec2d;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+
+    // 正方形の頂点と中心。NodeId は CellId と違う番号にする
+    fn square_diagram() -> VoronoiDiagram {
+        let mut diagram = VoronoiDiagram::new();
+        let points = [(0.2, 0.2), (0.8, 0.2), (0.8, 0.8), (0.2, 0.8), (0.5, 0.5)];
+        for (i, (x, y)) in points.iter().enumerate() {
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d { x: *x, y: *y },
+                node_id: 10 + i as NodeId,
+                cluster_id: i as u32 % 2,
+            };
+            diagram.add_point(&vp).unwrap();
+        }
+        diagram
+    }
+
+    #[test]
+    fn adjacency_edges_are_undirected() {
+        let diagram = square_diagram();
+        let edges = diagram.adjacency_edges();
+        let pairs: Vec<(CellId, CellId)> = edges.iter().map(|e| (e.cell1, e.cell2)).collect();
+        // 隣り合う頂点同士と、中心と各頂点。向きは cell1 < cell2 の1本だけ
+        assert_eq!(
+            vec![
+                (0, 1),
+                (0, 3),
+                (0, 4),
+                (1, 2),
+                (1, 4),
+                (2, 3),
+                (2, 4),
+                (3, 4)
+            ],
+            pairs
+        );
+        for edge in edges.iter() {
+            let expected = if edge.cell2 == 4 {
+                0.3 * 2f64.sqrt()
+            } else {
+                0.2
+            };
+            assert!((edge.length - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn dot_has_every_site() {
+        let diagram = square_diagram();
+        let dot = diagram.to_dot();
+        assert!(dot.starts_with("graph voronoi {\n"));
+        assert!(dot.ends_with("}\n"));
+        for node_id in 10..15 {
+            let prefix = format!("  {} [pos=", node_id);
+            assert_eq!(1, dot.lines().filter(|l| l.starts_with(&prefix)).count());
+        }
+        assert!(dot.contains("  10 [pos=\"0.2,0.2!\", cell_id=0, cluster_id=0];"));
+        assert_eq!(8, dot.lines().filter(|l| l.contains(" -- ")).count());
+        assert!(dot.contains("  10 -- 11 [length="));
+        assert!(!dot.contains("->"));
+    }
+
+    // 開いたタグと閉じたタグの対応を調べて、要素名の一覧を返す
+    fn element_names(xml: &str) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut stack: Vec<String> = Vec::new();
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            let end = start + rest[start..].find('>').unwrap();
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with('?') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(Some(name.to_string()), stack.pop());
+                continue;
+            }
+            let name = tag.split_whitespace().next().unwrap().to_string();
+            names.push(name.clone());
+            if !tag.ends_with('/') {
+                stack.push(name);
+            }
+        }
+        assert!(stack.is_empty());
+        names
+    }
+
+    #[test]
+    fn graphml_structure() {
+        let diagram = square_diagram();
+        let graphml = diagram.to_graphml();
+        assert!(graphml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        let names = element_names(&graphml);
+        let count = |name: &str| names.iter().filter(|n| *n == name).count();
+        assert_eq!("graphml", names[0]);
+        assert_eq!(1, count("graph"));
+        assert_eq!(5, count("key"));
+        assert_eq!(5, count("node"));
+        assert_eq!(8, count("edge"));
+        assert_eq!(5 * 4 + 8, count("data"));
+        assert!(graphml.contains("<graph id=\"voronoi\" edgedefault=\"undirected\">"));
+
+        // 辺の両端は全てノードの id
+        for line in graphml.lines().filter(|l| l.contains("<edge ")) {
+            for attr in ["source=\"", "target=\""].iter() {
+                let value = line.split(attr).nth(1).unwrap().split('"').next().unwrap();
+                assert!(graphml.contains(&format!("<node id=\"{}\">", value)));
+            }
+        }
+        assert!(graphml.contains("<node id=\"n14\">\n      <data key=\"x\">0.5</data>"));
+    }
+
+    #[test]
+    fn escape_attributes() {
+        assert_eq!("n1", escape_xml("n1"));
+        assert_eq!("a&lt;b&gt;&amp;&quot;c&apos;", escape_xml("a<b>&\"c'"));
+        let mut s = String::new();
+        write_graphml_data(&mut s, "a\"b", "x<y");
+        assert_eq!("      <data key=\"a&quot;b\">x&lt;y</data>\n", s);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{NodeId, VoronoiCenterPoint, VoronoiError};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use std::collections::VecDeque;
+
+/// VoronoiDiagram への編集。図全体ではなく点だけを持ち、逆の編集で元に戻す
+#[derive(Clone)]
+pub enum EditOp {
+    Add(Vec<VoronoiCenterPoint>),
+    Remove(Vec<VoronoiCenterPoint>),
+    Move {
+        node_id: NodeId,
+        from: Vec2d,
+        to: Vec2d,
+    },
+    Batch(Vec<EditOp>), // 順に適用する
+}
+
+impl EditOp {
+    /// 全部の点を消す編集
+    pub fn clear(diagram: &VoronoiDiagram) -> Self {
+        EditOp::Remove(diagram.points())
+    }
+
+    /// 今の点を全部 points に入れ替える編集
+    pub fn replace_all(diagram: &VoronoiDiagram, points: Vec<VoronoiCenterPoint>) -> Self {
+        EditOp::Batch(vec![EditOp::clear(diagram), EditOp::Add(points)])
+    }
+
+    pub fn inverse(&self) -> Self {
+        match self {
+            EditOp::Add(points) => EditOp::Remove(points.clone()),
+            EditOp::Remove(points) => EditOp::Add(points.clone()),
+            EditOp::Move { node_id, from, to } => EditOp::Move {
+                node_id: *node_id,
+                from: *to,
+                to: *from,
+            },
+            EditOp::Batch(ops) => EditOp::Batch(ops.iter().rev().map(|op| op.inverse()).collect()),
+        }
+    }
+
+    /// 途中で失敗したら、それまでの編集を戻してエラーを返す
+    pub fn apply(&self, diagram: &mut VoronoiDiagram) -> Result<(), VoronoiError> {
+        match self {
+            EditOp::Add(points) => {
+                for (i, point) in points.iter().enumerate() {
+                    if let Err(e) = diagram.add_point(point) {
+                        let added: Vec<NodeId> = points[..i].iter().map(|p| p.node_id).collect();
+                        diagram.remove_points(&added)?;
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+            EditOp::Remove(points) => {
+                let node_ids: Vec<NodeId> = points.iter().map(|p| p.node_id).collect();
+                diagram.remove_points(&node_ids)
+            }
+            EditOp::Move { node_id, to, .. } => diagram.move_point(*node_id, *to),
+            EditOp::Batch(ops) => {
+                for (i, op) in ops.iter().enumerate() {
+                    if let Err(e) = op.apply(diagram) {
+                        for done in ops[..i].iter().rev() {
+                            done.inverse().apply(diagram)?;
+                        }
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // 持っている点の数(メモリの目安)
+    fn size(&self) -> usize {
+        match self {
+            EditOp::Add(points) | EditOp::Remove(points) => points.len(),
+            EditOp::Move { .. } => 1,
+            EditOp::Batch(ops) => ops.iter().map(|op| op.size()).sum(),
+        }
+    }
+}
+
+/// 元に戻す/やり直すための編集の履歴。持つ点の数が max_points を超えたら古いものから捨てる
+pub struct History {
+    undo_ops: VecDeque<EditOp>,
+    redo_ops: Vec<EditOp>,
+    max_points: usize,
+    version: u64, // 図を変えるたびに増える
+}
+
+#[allow(dead_code)]
+impl History {
+    pub fn new(max_points: usize) -> Self {
+        Self {
+            undo_ops: VecDeque::new(),
+            redo_ops: Vec::new(),
+            max_points,
+            version: 0,
+        }
+    }
+
+    /// op を適用して履歴に積む
+    pub fn apply(&mut self, diagram: &mut VoronoiDiagram, op: EditOp) -> Result<(), VoronoiError> {
+        op.apply(diagram)?;
+        self.record(op);
+        Ok(())
+    }
+
+    /// 適用済みの op を履歴に積む (ドラッグのように途中の変化をまとめたいとき)
+    pub fn record(&mut self, op: EditOp) {
+        self.redo_ops.clear();
+        self.undo_ops.push_back(op);
+        self.version += 1;
+        let mut size: usize = self.undo_ops.iter().map(|op| op.size()).sum();
+        while size > self.max_points && self.undo_ops.len() > 1 {
+            size -= self.undo_ops.pop_front().unwrap().size();
+        }
+    }
+
+    /// 戻す編集が無ければ Ok(false)
+    pub fn undo(&mut self, diagram: &mut VoronoiDiagram) -> Result<bool, VoronoiError> {
+        let op = match self.undo_ops.pop_back() {
+            Some(op) => op,
+            None => return Ok(false),
+        };
+        if let Err(e) = op.inverse().apply(diagram) {
+            self.undo_ops.push_back(op);
+            return Err(e);
+        }
+        self.redo_ops.push(op);
+        self.version += 1;
+        Ok(true)
+    }
+
+    /// やり直す編集が無ければ Ok(false)
+    pub fn redo(&mut self, diagram: &mut VoronoiDiagram) -> Result<bool, VoronoiError> {
+        let op = match self.redo_ops.pop() {
+            Some(op) => op,
+            None => return Ok(false),
+        };
+        if let Err(e) = op.apply(diagram) {
+            self.redo_ops.push(op);
+            return Err(e);
+        }
+        self.undo_ops.push_back(op);
+        self.version += 1;
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_ops.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_ops.is_empty()
+    }
+
+    /// 履歴を通して図を変えた回数 (保存が必要かどうかの判定に使う)
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_ops.clear();
+        self.redo_ops.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(node_id: NodeId, x: f64, y: f64) -> VoronoiCenterPoint {
+        VoronoiCenterPoint {
+            pos: Vec2d { x, y },
+            node_id,
+            cluster_id: 0,
+        }
+    }
+
+    // NodeId の順に並べた点の位置
+    fn positions(diagram: &VoronoiDiagram) -> Vec<(NodeId, f64, f64)> {
+        let mut ret: Vec<(NodeId, f64, f64)> = diagram
+            .points()
+            .iter()
+            .map(|p| (p.node_id, p.pos.x, p.pos.y))
+            .collect();
+        ret.sort_by_key(|p| p.0);
+        ret
+    }
+
+    fn diagram_with(points: &[VoronoiCenterPoint]) -> VoronoiDiagram {
+        let mut diagram = VoronoiDiagram::new();
+        for p in points.iter() {
+            diagram.add_point(p).unwrap();
+        }
+        diagram
+    }
+
+    #[test]
+    fn failed_add_rolls_back() {
+        let mut diagram = diagram_with(&[point(0, 0.5, 0.5)]);
+        let op = EditOp::Add(vec![point(1, 0.2, 0.2), point(2, 0.5, 0.5)]);
+        assert_eq!(Err(VoronoiError::SamePosition), op.apply(&mut diagram));
+        assert_eq!(vec![(0, 0.5, 0.5)], positions(&diagram));
+    }
+
+    #[test]
+    fn failed_batch_rolls_back() {
+        let mut diagram = diagram_with(&[point(0, 0.5, 0.5)]);
+        let op = EditOp::Batch(vec![
+            EditOp::Add(vec![point(1, 0.2, 0.2)]),
+            EditOp::Move {
+                node_id: 0,
+                from: Vec2d { x: 0.5, y: 0.5 },
+                to: Vec2d { x: 0.7, y: 0.5 },
+            },
+            EditOp::Remove(vec![point(9, 0.1, 0.1)]),
+        ]);
+        assert_eq!(Err(VoronoiError::NodeNotFound), op.apply(&mut diagram));
+        assert_eq!(vec![(0, 0.5, 0.5)], positions(&diagram));
+    }
+
+    // Batch は逆順に戻す (追加した点を動かしたなら、動かしたのを戻してから消す)
+    #[test]
+    fn batch_inverse_reverses_order() {
+        let mut diagram = diagram_with(&[point(0, 0.5, 0.5)]);
+        let op = EditOp::Batch(vec![
+            EditOp::Add(vec![point(1, 0.2, 0.2)]),
+            EditOp::Move {
+                node_id: 1,
+                from: Vec2d { x: 0.2, y: 0.2 },
+                to: Vec2d { x: 0.3, y: 0.8 },
+            },
+        ]);
+        op.apply(&mut diagram).unwrap();
+        assert_eq!(vec![(0, 0.5, 0.5), (1, 0.3, 0.8)], positions(&diagram));
+        match op.inverse() {
+            EditOp::Batch(ops) => match ops.as_slice() {
+                [EditOp::Move { node_id, from, to }, EditOp::Remove(points)] => {
+                    assert_eq!(1, *node_id);
+                    assert_eq!((0.3, 0.8), (from.x, from.y));
+                    assert_eq!((0.2, 0.2), (to.x, to.y));
+                    assert_eq!(1, points[0].node_id);
+                }
+                _ => panic!("Move then Remove"),
+            },
+            _ => panic!("Batch"),
+        }
+        op.inverse().apply(&mut diagram).unwrap();
+        assert_eq!(vec![(0, 0.5, 0.5)], positions(&diagram));
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut diagram = VoronoiDiagram::new();
+        let mut history = History::new(100);
+        assert_eq!(Ok(false), history.undo(&mut diagram));
+        history
+            .apply(&mut diagram, EditOp::Add(vec![point(0, 0.5, 0.5)]))
+            .unwrap();
+        let op = EditOp::Move {
+            node_id: 0,
+            from: Vec2d { x: 0.5, y: 0.5 },
+            to: Vec2d { x: 0.2, y: 0.4 },
+        };
+        history.apply(&mut diagram, op).unwrap();
+        assert_eq!(2, history.version());
+
+        assert_eq!(Ok(true), history.undo(&mut diagram));
+        assert_eq!(vec![(0, 0.5, 0.5)], positions(&diagram));
+        assert_eq!(Ok(true), history.undo(&mut diagram));
+        assert!(positions(&diagram).is_empty());
+        assert_eq!(Ok(false), history.undo(&mut diagram));
+        assert!(!history.can_undo());
+        assert!(history.can_redo());
+
+        assert_eq!(Ok(true), history.redo(&mut diagram));
+        assert_eq!(Ok(true), history.redo(&mut diagram));
+        assert_eq!(vec![(0, 0.2, 0.4)], positions(&diagram));
+        assert_eq!(Ok(false), history.redo(&mut diagram));
+        assert_eq!(6, history.version());
+
+        // 新しい編集をしたらやり直せない
+        history.undo(&mut diagram).unwrap();
+        history
+            .apply(&mut diagram, EditOp::Add(vec![point(1, 0.8, 0.8)]))
+            .unwrap();
+        assert!(!history.can_redo());
+        assert_eq!(vec![(0, 0.5, 0.5), (1, 0.8, 0.8)], positions(&diagram));
+    }
+
+    #[test]
+    fn failed_undo_keeps_history() {
+        let mut diagram = VoronoiDiagram::new();
+        let mut history = History::new(100);
+        history
+            .apply(&mut diagram, EditOp::Add(vec![point(0, 0.5, 0.5)]))
+            .unwrap();
+        // 履歴の外で消すと、戻すときに消す点が無い
+        diagram.remove_points(&[0]).unwrap();
+        assert_eq!(Err(VoronoiError::NodeNotFound), history.undo(&mut diagram));
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn evicts_oldest_over_max_points() {
+        let mut diagram = VoronoiDiagram::new();
+        let mut history = History::new(3);
+        let add = |node_id: NodeId, x: f64| EditOp::Add(vec![point(node_id, x, 0.5)]);
+        history.apply(&mut diagram, add(0, 0.2)).unwrap();
+        history.apply(&mut diagram, add(1, 0.4)).unwrap();
+        history.apply(&mut diagram, add(2, 0.6)).unwrap();
+        history.apply(&mut diagram, add(3, 0.8)).unwrap();
+        for _ in 0..3 {
+            assert_eq!(Ok(true), history.undo(&mut diagram));
+        }
+        assert_eq!(Ok(false), history.undo(&mut diagram));
+        assert_eq!(vec![(0, 0.2, 0.5)], positions(&diagram));
+
+        // max_points より大きい編集でも、最後の1つは残す
+        let mut history = History::new(1);
+        let op = EditOp::Add(vec![point(4, 0.1, 0.1), point(5, 0.9, 0.9)]);
+        history.apply(&mut diagram, op).unwrap();
+        assert!(history.can_undo());
+    }
+}
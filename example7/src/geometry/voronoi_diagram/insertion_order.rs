@@ -0,0 +1,60 @@
+use crate::geometry::rect::Rect;
+use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// Hilbert曲線の1辺の分割数 (2^HILBERT_ORDER)
+const HILBERT_ORDER: u32 = 16;
+
+/// BRIO (Biased Randomized Insertion Order) で点を並べ替える。
+/// 点をランダムに「ラウンド」に振り分け(小さいラウンドから順に)、各ラウンドの中はHilbert曲線順にする。
+/// 同じ seed なら毎回同じ順序になる。
+pub fn brio_order(points: &mut Vec<VoronoiCenterPoint>, area: &Rect, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    points.shuffle(&mut rng);
+
+    // 各点は 1/2 の確率で最後のラウンド、残りは 1/4 の確率で1つ前のラウンド...
+    let n_rounds = (points.len() as f64).log2().ceil().max(1.) as u32;
+    let mut keyed: Vec<(u32, u64, VoronoiCenterPoint)> = Vec::with_capacity(points.len());
+    for point in points.drain(..) {
+        let mut round = n_rounds;
+        while round > 0 && rng.gen::<bool>() {
+            round -= 1;
+        }
+        let key = hilbert_index(&point, area);
+        keyed.push((round, key, point));
+    }
+    keyed.sort_by_key(|(round, key, _)| (*round, *key));
+    points.extend(keyed.into_iter().map(|(_, _, point)| point));
+}
+
+/// area を 2^HILBERT_ORDER x 2^HILBERT_ORDER に分割したときの Hilbert曲線上の位置
+pub fn hilbert_index(point: &VoronoiCenterPoint, area: &Rect) -> u64 {
+    let n: u64 = 1 << HILBERT_ORDER;
+    let quantize = |v: f64, origin: f64, size: f64| -> u64 {
+        let t = if size > 0. { (v - origin) / size } else { 0. };
+        ((t.clamp(0., 1.) * (n - 1) as f64) as u64).min(n - 1)
+    };
+    let mut x = quantize(point.pos.x, area.pos.x, area.size.x);
+    let mut y = quantize(point.pos.y, area.pos.y, area.size.y);
+
+    // https://en.wikipedia.org/wiki/Hilbert_curve  (xy2d)
+    let mut d: u64 = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = ((x & s) > 0) as u64;
+        let ry = ((y & s) > 0) as u64;
+        d += s * s * ((3 * rx) ^ ry);
+        // rotate
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
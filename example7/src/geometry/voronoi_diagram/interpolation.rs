@@ -0,0 +1,148 @@
+use crate::geometry::line::Line;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{CellId, NodeId};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use std::collections::{HashMap, HashSet};
+
+// Sibson の自然近傍補間 (Natural Neighbour Interpolation)
+// pos に仮想的に点を追加したときに、新しいセルが既存の各セルから奪う面積を重みにする。
+#[allow(dead_code)]
+impl VoronoiDiagram {
+    /// pos に点を仮想的に追加したときの (NodeId, 奪う面積の割合) のリスト。割合の合計は 1。
+    /// pos が外周の外ならば空を返す。
+    pub fn natural_neighbor_weights(&self, pos: Vec2d) -> Vec<(NodeId, f64)> {
+        if self.cells.is_empty() || !self.outbound.polygon.contains(pos) {
+            return vec![];
+        }
+        // 一番近い点のセルに pos は含まれる
+        let nearest = self
+            .cells
+            .iter()
+            .min_by(|a, b| {
+                let da = a.borrow().point.pos.distance_from(pos);
+                let db = b.borrow().point.pos.distance_from(pos);
+                da.partial_cmp(&db).unwrap()
+            })
+            .unwrap()
+            .borrow();
+        if nearest.point.pos.eq(&pos) {
+            return vec![(nearest.point.node_id, 1.)];
+        }
+
+        let mut weights: Vec<(NodeId, f64)> = Vec::new();
+        let mut checked_cell_set: HashSet<CellId> = HashSet::new();
+        let mut queue: Vec<CellId> = vec![nearest.cell_id];
+        checked_cell_set.insert(nearest.cell_id);
+        while let Some(cell_id) = queue.pop() {
+            let cell = self.get_cell(cell_id).borrow();
+            // 垂直二等分線より pos 側の部分が新しいセルに奪われる
+            let bisector = Line::vertical_bisector(pos, cell.point.pos, 1.);
+            let side = bisector.point_side(pos);
+            let stolen = cell.bound.polygon.clip_by_line(&bisector, side).area();
+            if stolen <= 0. {
+                continue;
+            }
+            weights.push((cell.point.node_id, stolen));
+            for neighbor_cell_id in cell.get_neighbor_cells() {
+                if !checked_cell_set.contains(&neighbor_cell_id) {
+                    checked_cell_set.insert(neighbor_cell_id);
+                    queue.push(neighbor_cell_id);
+                }
+            }
+        }
+
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        if total <= 0. {
+            return vec![(nearest.point.node_id, 1.)];
+        }
+        weights.iter().map(|(id, w)| (*id, w / total)).collect()
+    }
+
+    /// 各点(NodeId)の値から pos の値を補間する。
+    /// pos が外周の外、または必要な点の値が values に無い場合は None。
+    pub fn interpolate(&self, values: &HashMap<NodeId, f64>, pos: Vec2d) -> Option<f64> {
+        let weights = self.natural_neighbor_weights(pos);
+        if weights.is_empty() {
+            return None;
+        }
+        let mut ret = 0.;
+        for (node_id, w) in weights.iter() {
+            ret += values.get(node_id)? * w;
+        }
+        Some(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_diagram(rng: &mut StdRng, n: u32) -> VoronoiDiagram {
+        let mut diagram = VoronoiDiagram::new();
+        for node_id in 0..n {
+            let point = VoronoiCenterPoint {
+                node_id,
+                pos: Vec2d {
+                    x: rng.gen(),
+                    y: rng.gen(),
+                },
+                cluster_id: 0,
+            };
+            diagram.add_point(&point).unwrap();
+        }
+        diagram
+    }
+
+    fn random_pos(rng: &mut StdRng) -> Vec2d {
+        Vec2d {
+            x: rng.gen(),
+            y: rng.gen(),
+        }
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let diagram = random_diagram(&mut rng, 50);
+        for _ in 0..100 {
+            let weights = diagram.natural_neighbor_weights(random_pos(&mut rng));
+            assert!(!weights.is_empty());
+            assert!(weights.iter().all(|(_, w)| *w > 0.));
+            let total: f64 = weights.iter().map(|(_, w)| w).sum();
+            assert!((total - 1.).abs() < 1e-9, "{}", total);
+        }
+        let outside = Vec2d { x: 1.5, y: 0.5 };
+        assert!(diagram.natural_neighbor_weights(outside).is_empty());
+    }
+
+    #[test]
+    fn constant_field_is_constant() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let diagram = random_diagram(&mut rng, 50);
+        let values: HashMap<NodeId, f64> = (0..50).map(|node_id| (node_id, 3.5)).collect();
+        for _ in 0..100 {
+            let value = diagram.interpolate(&values, random_pos(&mut rng)).unwrap();
+            assert!((value - 3.5).abs() < 1e-9, "{}", value);
+        }
+    }
+
+    #[test]
+    fn value_at_site() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let diagram = random_diagram(&mut rng, 50);
+        let values: HashMap<NodeId, f64> = (0..50).map(|node_id| (node_id, rng.gen())).collect();
+        for cell in diagram.cells.iter() {
+            let point = &cell.borrow().point;
+            let value = diagram.interpolate(&values, point.pos).unwrap();
+            assert_eq!(values[&point.node_id], value);
+        }
+        // 値が足りなければ None
+        let mut partial = values.clone();
+        partial.remove(&0);
+        let pos = diagram.get_cell_by_node_id(0).unwrap().borrow().point.pos;
+        assert_eq!(None, diagram.interpolate(&partial, pos));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::geometry::line::Line;
+use crate::geometry::polygon::Polygon;
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{
+    ClusterId, NodeId, VoronoiCenterPoint, VoronoiError,
+};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+// 多角形の境界を細かく点に分けて VoronoiDiagram を作ると、多角形の内側に残る辺が中心線(medial axis)に近くなる
+
+pub struct MedialAxisConfig {
+    pub sample_step: f64,     // 境界上の点の間隔
+    pub min_spur_length: f64, // これより短い枝は取り除く
+    pub seed: u64,
+}
+
+impl MedialAxisConfig {
+    pub fn new(sample_step: f64) -> Self {
+        Self {
+            sample_step,
+            min_spur_length: sample_step * 3.,
+            seed: 0,
+        }
+    }
+}
+
+/// 中心線の1辺。radius は端点から境界までの(おおよその)距離
+#[allow(dead_code)]
+pub struct MedialAxisEdge {
+    pub line: Line,
+    pub radius1: f64,
+    pub radius2: f64,
+}
+
+/// polygon (凹でもよい) の中心線を近似する
+pub fn medial_axis(
+    polygon: &Polygon,
+    config: &MedialAxisConfig,
+) -> Result<Vec<MedialAxisEdge>, VoronoiError> {
+    if polygon.points.len() < 3 || config.sample_step <= 0. {
+        return Ok(vec![]);
+    }
+    let samples = sample_boundary(polygon, config);
+    let bound = polygon.bounding_rect();
+    let margin = bound.size.x.max(bound.size.y) * 0.1 + config.sample_step;
+    let mut diagram = VoronoiDiagram::with_bounds(Rect {
+        pos: bound.pos
+            - Vec2d {
+                x: margin,
+                y: margin,
+            },
+        size: bound.size
+            + Vec2d {
+                x: margin * 2.,
+                y: margin * 2.,
+            },
+    });
+    let mut points: Vec<VoronoiCenterPoint> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, pos)| VoronoiCenterPoint {
+            node_id: i as NodeId,
+            pos: *pos,
+            cluster_id: i as ClusterId,
+        })
+        .collect();
+    diagram.calculate_diagram(&mut points, config.seed)?;
+
+    let mut graph = Graph::new();
+    for _cell in diagram.cells.iter() {
+        let cell = _cell.borrow();
+        for line in cell.lines.iter() {
+            match line.another_cell(cell.cell_id) {
+                Some(another_cell_id) if cell.cell_id < another_cell_id => {}
+                _ => continue,
+            }
+            let (p1, p2) = (line.line.p1, line.line.p2);
+            if !is_inside(polygon, &line.line) {
+                continue;
+            }
+            let site = cell.point.pos;
+            graph.add_edge(p1, p1.distance_from(site), p2, p2.distance_from(site));
+        }
+    }
+    graph.prune_spurs(config.min_spur_length);
+    Ok(graph.edges())
+}
+
+// 境界を sample_step 以下の間隔で区切った点。
+// 等間隔に並んだ点はちょうど同じ円に乗りやすく VoronoiDiagram が扱えないので、少しずらす
+fn sample_boundary(polygon: &Polygon, config: &MedialAxisConfig) -> Vec<Vec2d> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let jitter = config.sample_step * 1e-3;
+    let mut samples: Vec<Vec2d> = Vec::new();
+    for line in polygon.lines.iter() {
+        let length = line.p1.distance_from(line.p2);
+        let n = (length / config.sample_step).ceil().max(1.) as usize;
+        for i in 0..n {
+            let t = i as f64 / n as f64;
+            samples.push(Vec2d {
+                x: line.p1.x + (line.p2.x - line.p1.x) * t + (rng.gen::<f64>() - 0.5) * jitter,
+                y: line.p1.y + (line.p2.y - line.p1.y) * t + (rng.gen::<f64>() - 0.5) * jitter,
+            });
+        }
+    }
+    samples
+}
+
+// 中点が内側にあり、境界を横切らない。
+// 境界や角に接するだけの辺(角へ伸びる中心線など)は残す
+fn is_inside(polygon: &Polygon, line: &Line) -> bool {
+    polygon.is_include(Vec2d::middle_point(line.p1, line.p2))
+        && !polygon.lines.iter().any(|l| Line::is_cross(l, line))
+}
+
+// 中心線の頂点と辺。枝を刈るために使う
+struct Graph {
+    points: Vec<Vec2d>,
+    radii: Vec<f64>,
+    edges: Vec<(usize, usize)>,
+    alive: Vec<bool>,
+    edges_of: Vec<Vec<usize>>, // 頂点につながる辺
+    index: HashMap<(i64, i64), usize>,
+}
+
+impl Graph {
+    fn new() -> Self {
+        Graph {
+            points: Vec::new(),
+            radii: Vec::new(),
+            edges: Vec::new(),
+            alive: Vec::new(),
+            edges_of: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn vertex(&mut self, pos: Vec2d, radius: f64) -> usize {
+        let key = ((pos.x * 1e9).round() as i64, (pos.y * 1e9).round() as i64);
+        if let Some(v) = self.index.get(&key) {
+            return *v;
+        }
+        self.points.push(pos);
+        self.radii.push(radius);
+        self.edges_of.push(Vec::new());
+        self.index.insert(key, self.points.len() - 1);
+        self.points.len() - 1
+    }
+
+    fn add_edge(&mut self, p1: Vec2d, r1: f64, p2: Vec2d, r2: f64) {
+        let (v1, v2) = (self.vertex(p1, r1), self.vertex(p2, r2));
+        if v1 == v2 {
+            return;
+        }
+        self.edges.push((v1, v2));
+        self.alive.push(true);
+        self.edges_of[v1].push(self.edges.len() - 1);
+        self.edges_of[v2].push(self.edges.len() - 1);
+    }
+
+    fn alive_edges_of(&self, v: usize) -> Vec<usize> {
+        self.edges_of[v]
+            .iter()
+            .filter(|e| self.alive[**e])
+            .cloned()
+            .collect()
+    }
+
+    fn other_end(&self, e: usize, v: usize) -> usize {
+        let (v1, v2) = self.edges[e];
+        if v1 == v {
+            v2
+        } else {
+            v1
+        }
+    }
+
+    // 端点から分岐点までの枝が min_length より短ければ取り除く。枝が無くなるまで繰り返す
+    fn prune_spurs(&mut self, min_length: f64) {
+        loop {
+            let mut pruned = false;
+            for start in 0..self.points.len() {
+                let first = self.alive_edges_of(start);
+                if first.len() != 1 {
+                    continue;
+                }
+                let mut path = vec![first[0]];
+                let mut length = 0.;
+                let mut v = start;
+                let end = loop {
+                    let e = *path.last().unwrap();
+                    let next = self.other_end(e, v);
+                    length += self.points[v].distance_from(self.points[next]);
+                    let next_edges = self.alive_edges_of(next);
+                    if next_edges.len() != 2 || length >= min_length {
+                        break next;
+                    }
+                    path.push(*next_edges.iter().find(|n| **n != e).unwrap());
+                    v = next;
+                };
+                if length < min_length && self.alive_edges_of(end).len() >= 3 {
+                    for e in path {
+                        self.alive[e] = false;
+                    }
+                    pruned = true;
+                }
+            }
+            if !pruned {
+                break;
+            }
+        }
+    }
+
+    fn edges(&self) -> Vec<MedialAxisEdge> {
+        self.edges
+            .iter()
+            .zip(self.alive.iter())
+            .filter(|(_, alive)| **alive)
+            .map(|((v1, v2), _)| MedialAxisEdge {
+                line: Line::new(self.points[*v1], self.points[*v2]),
+                radius1: self.radii[*v1],
+                radius2: self.radii[*v2],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f64 = 0.05;
+    const TOLERANCE: f64 = STEP;
+
+    fn polygon(points: &[(f64, f64)]) -> Polygon {
+        Polygon::new(points.iter().map(|(x, y)| Vec2d { x: *x, y: *y }).collect())
+    }
+
+    fn segment(x1: f64, y1: f64, x2: f64, y2: f64) -> Line {
+        Line::new(Vec2d { x: x1, y: y1 }, Vec2d { x: x2, y: y2 })
+    }
+
+    fn distance_to_segment(pos: Vec2d, line: &Line) -> f64 {
+        let d = line.p2 - line.p1;
+        let t = ((pos - line.p1).x * d.x + (pos - line.p1).y * d.y) / (d.x * d.x + d.y * d.y);
+        pos.distance_from(line.p1 + d.mul(t.clamp(0., 1.)))
+    }
+
+    fn distance_to_lines(pos: Vec2d, lines: &[Line]) -> f64 {
+        lines
+            .iter()
+            .map(|l| distance_to_segment(pos, l))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    // 近似した中心線が expected (線分で近似した正しい中心線) に沿い、
+    // 角の近く(刈られたり短くなったりする)を除いて expected を覆うこと
+    fn assert_skeleton(polygon: &Polygon, expected: &[Line]) {
+        let edges = medial_axis(polygon, &MedialAxisConfig::new(STEP)).unwrap();
+        let lines: Vec<Line> = edges.iter().map(|e| e.line.clone()).collect();
+        for edge in edges.iter() {
+            for pos in [edge.line.p1, edge.line.p2].iter() {
+                assert!(
+                    distance_to_lines(*pos, expected) < TOLERANCE,
+                    "{} is not on the axis",
+                    pos
+                );
+            }
+        }
+        for line in expected.iter() {
+            for i in 0..=20 {
+                let pos = line.p1 + (line.p2 - line.p1).mul(i as f64 / 20.);
+                if polygon
+                    .points
+                    .iter()
+                    .any(|p| p.distance_from(pos) < STEP * 4.)
+                {
+                    continue;
+                }
+                assert!(
+                    distance_to_lines(pos, &lines) < TOLERANCE,
+                    "{} is not covered",
+                    pos
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rectangle() {
+        let rect = polygon(&[(0., 0.), (4., 0.), (4., 2.), (0., 2.)]);
+        let expected = vec![
+            segment(1., 1., 3., 1.),
+            segment(0., 0., 1., 1.),
+            segment(0., 2., 1., 1.),
+            segment(4., 0., 3., 1.),
+            segment(4., 2., 3., 1.),
+        ];
+        assert_skeleton(&rect, &expected);
+        // 中心線の真ん中は両側の辺から 1 離れている
+        let edges = medial_axis(&rect, &MedialAxisConfig::new(STEP)).unwrap();
+        let center = edges
+            .iter()
+            .find(|e| e.line.p1.distance_from(Vec2d { x: 2., y: 1. }) < STEP)
+            .unwrap();
+        assert!((center.radius1 - 1.).abs() < STEP);
+    }
+
+    #[test]
+    fn l_shape() {
+        let l = polygon(&[(0., 0.), (4., 0.), (4., 1.), (1., 1.), (1., 4.), (0., 4.)]);
+        // 外側の角 (0, 0) からの対角線は、内側の角 (1, 1) と等距離になるところで
+        // 2本の放物線に分かれて、それぞれの腕の中心線につながる
+        let a = 2f64.sqrt() / (1. + 2f64.sqrt());
+        let mut expected = vec![
+            segment(0., 0., a, a),
+            segment(0.5, 1., 0.5, 3.5),
+            segment(1., 0.5, 3.5, 0.5),
+            segment(0.5, 3.5, 0., 4.),
+            segment(0.5, 3.5, 1., 4.),
+            segment(3.5, 0.5, 4., 0.),
+            segment(3.5, 0.5, 4., 1.),
+        ];
+        // x = (1 + (y - 1)^2) / 2 (辺 x = 0 と点 (1, 1) から等距離) と、それを対角線で折り返したもの
+        let n = 10;
+        for i in 0..n {
+            let y = |i: usize| a + (1. - a) * i as f64 / n as f64;
+            let x = |i: usize| (1. + (y(i) - 1.).powi(2)) / 2.;
+            expected.push(segment(x(i), y(i), x(i + 1), y(i + 1)));
+            expected.push(segment(y(i), x(i), y(i + 1), x(i + 1)));
+        }
+        assert_skeleton(&l, &expected);
+    }
+
+    #[test]
+    fn strict_crossing_keeps_touching_edges() {
+        let square = polygon(&[(0., 0.), (1., 0.), (1., 1.), (0., 1.)]);
+        // 角に接する線と辺に沿う線は内側に残る
+        assert!(is_inside(&square, &segment(0., 0., 0.5, 0.5)));
+        assert!(is_inside(&square, &segment(0.5, 0.5, 1., 0.5)));
+        assert!(!is_inside(&square, &segment(0.5, 0.5, 1.5, 0.5)));
+        assert!(!is_inside(&square, &segment(1.2, 0.2, 1.5, 0.5)));
+    }
+}
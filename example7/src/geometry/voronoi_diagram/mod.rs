@@ -1,2 +1,12 @@
+pub mod alpha_shape;
+pub mod cell_key;
+pub mod export;
+pub mod history;
+pub mod insertion_order;
+pub mod interpolation;
+pub mod medial_axis;
+pub mod query;
+pub mod raster;
+pub mod trace;
 pub mod types;
 pub mod voronoi_diagram;
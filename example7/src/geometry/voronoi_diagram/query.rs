@@ -0,0 +1,304 @@
+use crate::geometry::line::Line;
+use crate::geometry::polygon::Polygon;
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::CellId;
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use std::rc::Rc;
+
+const PARAM_EPS: f64 = 1e-9; // 線分上の位置の誤差 (線分の長さに対する割合)
+const PROBE_PARAM: f64 = 1e-6; // 頂点を通った先のセルを調べる位置 (同上)
+
+/// 線分がセルを通過した区間
+#[allow(dead_code)]
+pub struct CellCrossing {
+    pub cell_id: CellId,
+    pub entry: Vec2d, // セルに入った点 (最初のセルは線分の始点)
+    pub exit: Vec2d,  // セルから出た点 (最後のセルは線分の終点)
+}
+
+#[allow(dead_code)]
+impl VoronoiDiagram {
+    /// rect と重なるセル
+    pub fn cells_in_rect(&self, rect: &Rect) -> Vec<CellId> {
+        self.cells_in_polygon(&rect.to_polygon())
+    }
+
+    /// polygon(凸多角形) と重なるセル
+    pub fn cells_in_polygon(&self, polygon: &Polygon) -> Vec<CellId> {
+        let area = polygon.bounding_rect();
+        let mut ret: Vec<CellId> = Vec::new();
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            // 外接矩形が重ならなければ調べなくてよい
+            let bound = cell.bound.polygon.bounding_rect();
+            if !is_overlap_or_touch(&area, &bound) {
+                continue;
+            }
+            if cell.bound.polygon.is_overlap(polygon) {
+                ret.push(cell.cell_id);
+            }
+        }
+        ret
+    }
+
+    /// 線分 line が通過するセルを、始点から順に返す
+    pub fn cells_on_line(&self, line: &Line) -> Vec<CellCrossing> {
+        let mut ret: Vec<CellCrossing> = Vec::new();
+        let line = match self.outbound.polygon.clip_line(line) {
+            Some(l) => l,
+            None => return ret,
+        };
+        let mut current = match self.find_cell_at(line.p1) {
+            Some(cell) => cell,
+            None => return ret,
+        };
+        let direction = line.p2 - line.p1;
+        let length2 = direction.x * direction.x + direction.y * direction.y;
+        // 線分上の位置 (始点が 0、終点が 1)。誤差も線分の長さに対する割合で見る
+        let param = |pt: Vec2d| -> f64 {
+            let d = pt - line.p1;
+            (d.x * direction.x + d.y * direction.y) / length2
+        };
+
+        let mut entry = line.p1;
+        let mut from_t = 0.; // この位置より先の出口を探す
+                             // 凸なセルは1回しか通らないので、セルの数より多く進むことはない
+        for _ in 0..=self.cells.len() {
+            let cell_id = current.borrow().cell_id;
+            // entryより先で、一番遠いセル境界との交点が出口
+            let mut exit: Option<(f64, Vec2d)> = None;
+            let mut next_cells: Vec<Option<CellId>> = Vec::new();
+            for bound_line in current.borrow().lines.iter() {
+                let pt = match Line::intersection(&bound_line.line, &line) {
+                    Some(pt) => pt,
+                    None => continue,
+                };
+                let t = param(pt);
+                if t <= from_t + PARAM_EPS {
+                    continue;
+                }
+                match exit {
+                    // 頂点を通るときは、そこで接する境界線が全部同じ位置で交わる
+                    Some((et, _)) if (t - et).abs() <= PARAM_EPS => {}
+                    Some((et, _)) if t < et => continue,
+                    _ => {
+                        exit = Some((t, pt));
+                        next_cells.clear();
+                    }
+                }
+                next_cells.push(bound_line.another_cell(cell_id));
+            }
+            let (exit_t, pt) = match exit {
+                Some((exit_t, pt)) if length2 > 0. && exit_t < 1. - PARAM_EPS => (exit_t, pt),
+                _ => {
+                    // 終点はこのセルの中(か境界上)
+                    ret.push(CellCrossing {
+                        cell_id,
+                        entry,
+                        exit: line.p2,
+                    });
+                    return ret;
+                }
+            };
+            let next_cell_id = match next_cells.as_slice() {
+                [] | [None] => {
+                    // 外周にぶつかった
+                    ret.push(CellCrossing {
+                        cell_id,
+                        entry,
+                        exit: pt,
+                    });
+                    return ret;
+                }
+                [Some(next_cell_id)] => *next_cell_id,
+                _ => {
+                    // 頂点を通った。その先の線分を含むセル(中心が一番近いセル)に進む
+                    let probe_t = (exit_t + PROBE_PARAM).min((exit_t + 1.) / 2.);
+                    let probe = line.p1 + direction.mul(probe_t);
+                    self.walk_to_nearest_cell(probe, cell_id).borrow().cell_id
+                }
+            };
+            from_t = exit_t;
+            if next_cell_id == cell_id {
+                // 頂点から境界線に沿って進む。まだこのセルの中なので先の出口を探す
+                continue;
+            }
+            ret.push(CellCrossing {
+                cell_id,
+                entry,
+                exit: pt,
+            });
+            entry = pt;
+            current = Rc::clone(self.get_cell(next_cell_id));
+        }
+        panic!("cells_on_line: {} did not reach the end", line);
+    }
+}
+
+#[inline]
+fn is_overlap_or_touch(r1: &Rect, r2: &Rect) -> bool {
+    r1.pos.x <= r2.pos.x + r2.size.x
+        && r2.pos.x <= r1.pos.x + r1.size.x
+        && r1.pos.y <= r2.pos.y + r2.size.y
+        && r2.pos.y <= r1.pos.y + r1.size.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::voronoi_diagram::types::{NodeId, VoronoiCenterPoint};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    fn random_diagram(seed: u64, n: NodeId) -> VoronoiDiagram {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut diagram = VoronoiDiagram::new();
+        for node_id in 0..n {
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d {
+                    x: rng.gen(),
+                    y: rng.gen(),
+                },
+                node_id,
+                cluster_id: 0,
+            };
+            diagram.add_point(&vp).unwrap();
+        }
+        diagram
+    }
+
+    // 中心が一番近いセル (全部のセルと比べる)
+    fn nearest(diagram: &VoronoiDiagram, pos: Vec2d) -> CellId {
+        let distance = |c: &CellId| diagram.get_cell(*c).borrow().point.pos.distance_from(pos);
+        (0..diagram.cells.len() as CellId)
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .unwrap()
+    }
+
+    // polygon の中の格子点を含むセル
+    fn sampled_cells(diagram: &VoronoiDiagram, polygon: &Polygon) -> BTreeSet<CellId> {
+        let bounds = polygon.bounding_rect();
+        let steps = 300;
+        let mut ret = BTreeSet::new();
+        for i in 0..=steps {
+            for j in 0..=steps {
+                let pos = Vec2d {
+                    x: bounds.pos.x + bounds.size.x * i as f64 / steps as f64,
+                    y: bounds.pos.y + bounds.size.y * j as f64 / steps as f64,
+                };
+                if polygon.contains(pos) {
+                    ret.insert(nearest(diagram, pos));
+                }
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn cells_in_rect_match_nearest_sites() {
+        let diagram = random_diagram(5, 60);
+        for (x, y, w, h) in [
+            (0.1, 0.2, 0.3, 0.2),
+            (0.5, 0.5, 0.45, 0.1),
+            (0., 0., 1., 1.),
+        ]
+        .iter()
+        {
+            let rect = Rect {
+                pos: Vec2d { x: *x, y: *y },
+                size: Vec2d { x: *w, y: *h },
+            };
+            let cells: BTreeSet<CellId> = diagram.cells_in_rect(&rect).into_iter().collect();
+            assert_eq!(sampled_cells(&diagram, &rect.to_polygon()), cells);
+        }
+    }
+
+    #[test]
+    fn cells_in_polygon_match_nearest_sites() {
+        let diagram = random_diagram(6, 60);
+        let triangle = Polygon::new(vec![
+            Vec2d { x: 0.1, y: 0.1 },
+            Vec2d { x: 0.8, y: 0.3 },
+            Vec2d { x: 0.4, y: 0.7 },
+        ]);
+        let cells: BTreeSet<CellId> = diagram.cells_in_polygon(&triangle).into_iter().collect();
+        assert_eq!(sampled_cells(&diagram, &triangle), cells);
+    }
+
+    // 線分上の点を含むセルを始点から順に並べたもの
+    fn sampled_path(diagram: &VoronoiDiagram, line: &Line) -> Vec<CellId> {
+        let steps = 5000;
+        let mut ret: Vec<CellId> = Vec::new();
+        for i in 0..=steps {
+            let pos = line.p1 + (line.p2 - line.p1).mul(i as f64 / steps as f64);
+            let cell_id = nearest(diagram, pos);
+            if ret.last() != Some(&cell_id) {
+                ret.push(cell_id);
+            }
+        }
+        ret
+    }
+
+    fn assert_path(diagram: &VoronoiDiagram, line: &Line) {
+        let crossings = diagram.cells_on_line(line);
+        let cell_ids: Vec<CellId> = crossings.iter().map(|c| c.cell_id).collect();
+        assert_eq!(sampled_path(diagram, line), cell_ids, "{}", line);
+        assert!(crossings[0].entry.distance_from(line.p1) < 1e-12);
+        assert!(crossings.last().unwrap().exit.distance_from(line.p2) < 1e-12);
+        for pair in crossings.windows(2) {
+            assert!(pair[0].exit.distance_from(pair[1].entry) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn cells_on_line_match_nearest_sites() {
+        let diagram = random_diagram(7, 60);
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..20 {
+            let mut point = || Vec2d {
+                x: rng.gen(),
+                y: rng.gen(),
+            };
+            let line = Line::new(point(), point());
+            assert_path(&diagram, &line);
+        }
+    }
+
+    // 頂点を通るときは、線分が入っていくセルに進む
+    #[test]
+    fn cells_on_line_through_vertices() {
+        let mut diagram = VoronoiDiagram::new();
+        let sites = [
+            (0.25, 0.25),
+            (0.75, 0.25),
+            (0.25, 0.75),
+            (0.75, 0.75),
+            (0.5, 0.95),
+        ];
+        for (node_id, (x, y)) in sites.iter().enumerate() {
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d { x: *x, y: *y },
+                node_id: node_id as NodeId,
+                cluster_id: 0,
+            };
+            diagram.add_point(&vp).unwrap();
+        }
+        let diagonal = Line::new(Vec2d { x: 0.1, y: 0.1 }, Vec2d { x: 0.9, y: 0.9 });
+        let cell_ids: Vec<CellId> = diagram
+            .cells_on_line(&diagonal)
+            .iter()
+            .map(|c| c.cell_id)
+            .collect();
+        assert_eq!(vec![0, 3], cell_ids);
+        assert_path(&diagram, &diagonal);
+        // 4つのセルが接する頂点で終わる
+        let to_vertex = Line::new(Vec2d { x: 0.3, y: 0.2 }, Vec2d { x: 0.5, y: 0.5 });
+        assert_path(&diagram, &to_vertex);
+        // 境界線に沿って進んでも終点まで届く
+        let along_edge = Line::new(Vec2d { x: 0.5, y: 0.1 }, Vec2d { x: 0.5, y: 0.6 });
+        let crossings = diagram.cells_on_line(&along_edge);
+        assert!(crossings.last().unwrap().exit.distance_from(along_edge.p2) < 1e-12);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::geometry::voronoi_diagram::types::VoronoiCell;
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+
+/// width x height の格子に、各画素(の中心)を含むセルのラベルを並べたもの。
+/// labels は行優先 (labels[y * width + x])。どのセルにも含まれない画素は None。
+#[allow(dead_code)]
+pub struct LabelMap {
+    pub width: usize,
+    pub height: usize,
+    pub labels: Vec<Option<u32>>,
+}
+
+#[allow(dead_code)]
+impl LabelMap {
+    pub fn get(&self, x: usize, y: usize) -> Option<u32> {
+        self.labels[y * self.width + x]
+    }
+}
+
+#[allow(dead_code)]
+impl VoronoiDiagram {
+    /// 外周の外接矩形を width x height に分割して、各画素の CellId を求める
+    pub fn rasterize_cells(&self, width: usize, height: usize) -> LabelMap {
+        self.rasterize(width, height, |cell| cell.cell_id)
+    }
+
+    /// 外周の外接矩形を width x height に分割して、各画素の ClusterId を求める
+    pub fn rasterize_clusters(&self, width: usize, height: usize) -> LabelMap {
+        self.rasterize(width, height, |cell| cell.point.cluster_id)
+    }
+
+    // 画素ごとに全セルを調べるのではなく、セルの多角形を1行ずつ塗りつぶす(scanline)
+    fn rasterize<F>(&self, width: usize, height: usize, label_of: F) -> LabelMap
+    where
+        F: Fn(&VoronoiCell) -> u32,
+    {
+        let mut labels: Vec<Option<u32>> = vec![None; width * height];
+        let area = self.outbound.polygon.bounding_rect();
+        let px = area.size.x / width as f64;
+        let py = area.size.y / height as f64;
+
+        for _cell in self.cells.iter() {
+            let cell = _cell.borrow();
+            let polygon = &cell.bound.polygon;
+            if polygon.points.len() < 3 {
+                continue;
+            }
+            let label = label_of(&cell);
+            let bound = polygon.bounding_rect();
+            // 画素の中心 area.pos.y + (j + 0.5) * py が bound に入る行だけ調べる
+            let j1 = ((bound.pos.y - area.pos.y) / py - 0.5).ceil().max(0.) as usize;
+            let j2 = ((bound.pos.y + bound.size.y - area.pos.y) / py - 0.5).floor();
+            if j2 < 0. {
+                continue;
+            }
+            let j2 = (j2 as usize).min(height - 1);
+            for j in j1..=j2 {
+                let y = area.pos.y + (j as f64 + 0.5) * py;
+                if let Some((x1, x2)) = polygon.x_range_at(y) {
+                    let i1 = ((x1 - area.pos.x) / px - 0.5).ceil().max(0.) as usize;
+                    let i2 = ((x2 - area.pos.x) / px - 0.5).floor();
+                    if i2 < 0. {
+                        continue;
+                    }
+                    let i2 = (i2 as usize).min(width - 1);
+                    for i in i1..=i2 {
+                        labels[j * width + i] = Some(label);
+                    }
+                }
+            }
+        }
+
+        LabelMap {
+            width,
+            height,
+            labels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::rect::Rect;
+    use crate::geometry::vec2d::Vec2d;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // 全部の点との距離を比べて、画素の中心に一番近い点のセルと同じになること
+    fn assert_nearest_site(diagram: &VoronoiDiagram, width: usize, height: usize) {
+        let map = diagram.rasterize_cells(width, height);
+        let area = diagram.outbound.polygon.bounding_rect();
+        let (px, py) = (area.size.x / width as f64, area.size.y / height as f64);
+        let eps = (px + py) * 1e-9;
+        for j in 0..height {
+            for i in 0..width {
+                let pos = Vec2d {
+                    x: area.pos.x + (i as f64 + 0.5) * px,
+                    y: area.pos.y + (j as f64 + 0.5) * py,
+                };
+                let distance = |cell_id: u32| {
+                    diagram
+                        .get_cell(cell_id)
+                        .borrow()
+                        .point
+                        .pos
+                        .distance_from(pos)
+                };
+                let nearest = (0..diagram.cells.len() as u32)
+                    .map(distance)
+                    .fold(f64::INFINITY, f64::min);
+                let label = map.get(i, j).expect("pixel without label");
+                // 境界上の画素はどちらのセルでもよい
+                assert!(
+                    distance(label) - nearest <= eps,
+                    "pixel ({}, {}): Cell({})",
+                    i,
+                    j,
+                    label
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn labels_match_nearest_site() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for (seed, bounds) in [(0., 0., 1., 1.), (-20., 10., 300., 120.)]
+            .iter()
+            .enumerate()
+        {
+            let (x, y, w, h) = *bounds;
+            let mut diagram = VoronoiDiagram::with_bounds(Rect {
+                pos: Vec2d { x, y },
+                size: Vec2d { x: w, y: h },
+            });
+            let mut points: Vec<VoronoiCenterPoint> = (0..100)
+                .map(|node_id| VoronoiCenterPoint {
+                    node_id,
+                    pos: Vec2d {
+                        x: x + rng.gen::<f64>() * w,
+                        y: y + rng.gen::<f64>() * h,
+                    },
+                    cluster_id: 0,
+                })
+                .collect();
+            diagram.calculate_diagram(&mut points, seed as u64).unwrap();
+            assert_nearest_site(&diagram, 64, 48);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::geometry::line::Line;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::CellId;
+use std::cell::RefCell;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+#[allow(unused_imports)]
+use crate::log;
+
+/// add_point の途中経過 (VoronoiDiagram::trace_sink に送られる)
+#[derive(Clone)]
+pub enum TraceEvent {
+    /// 最初のセル(外周全体)を作った
+    InitCell { cell_id: CellId, point: Vec2d },
+    /// 新しい点 point を含むセルが見つかった
+    Locate { point: Vec2d, cell_id: CellId },
+    /// cell_id の中心と新しい点の垂直二等分線
+    Bisector {
+        cell_id: CellId,
+        site: Vec2d,
+        polygon: Vec<Vec2d>, // 分割前のセルの形
+        line: Line,
+    },
+    /// 分割前のセルの頂点を、垂直二等分線のどちら側にあるかで分けた (ぶつかった点は両方に入る)
+    BoundPoints {
+        cell_id: CellId,
+        remain_points: Vec<Vec2d>,    // 元のセルに残る点
+        separated_points: Vec<Vec2d>, // 新しいセルにわたす点
+    },
+    /// 垂直二等分線でセルを分割して、セルの辺を更新した
+    CellSplit {
+        cell_id: CellId,
+        middle_line: Line,    // 新しいセルとの境界
+        cut_lines: Vec<Line>, // 垂直二等分線がぶつかって、残った線分
+        polygon: Vec<Vec2d>,  // 分割後のセルの形
+    },
+    /// from_cell_id を分割したので、隣の cell_id も調べることにした
+    NeighborEnqueued {
+        cell_id: CellId,
+        from_cell_id: CellId,
+    },
+    /// 次に調べる隣接セルの候補
+    Queue { neighbor_cells: Vec<CellId> },
+    /// 新しいセルの外周上の辺を追加した
+    BoundaryLineAdded { line: Line },
+    /// 新しいセルを追加した
+    AddCell {
+        cell_id: CellId,
+        polygon: Vec<Vec2d>,
+    },
+    /// セルを削除した (polygon は削除する前の形)
+    RemoveCell {
+        cell_id: CellId,
+        polygon: Vec<Vec2d>,
+    },
+    /// 削除した from_cell_id の領域の一部を cell_id が引き取った
+    CellMerged {
+        cell_id: CellId,
+        from_cell_id: CellId,
+        polygon: Vec<Vec2d>, // 引き取った後のセルの形
+    },
+    /// 外周を変えたり点を削除したりしたので、points 個の点でセルを作り直す
+    Rebuild { points: usize },
+}
+
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TraceEvent::InitCell { cell_id, point } => {
+                write!(f, "Cell({}) を作成: 中心 {}", cell_id, point)
+            }
+            TraceEvent::Locate { point, cell_id } => {
+                write!(f, "{} を含むセル: Cell({})", point, cell_id)
+            }
+            TraceEvent::Bisector { cell_id, line, .. } => {
+                write!(f, "Cell({}) の中心との垂直二等分線: {}", cell_id, line)
+            }
+            TraceEvent::BoundPoints {
+                cell_id,
+                remain_points,
+                separated_points,
+            } => write!(
+                f,
+                "Cell({}) の頂点: 残す {}個 / わたす {}個",
+                cell_id,
+                remain_points.len(),
+                separated_points.len()
+            ),
+            TraceEvent::CellSplit {
+                cell_id,
+                middle_line,
+                cut_lines,
+                ..
+            } => write!(
+                f,
+                "Cell({}) を分割: 境界 {} / 切られた辺 {}本",
+                cell_id,
+                middle_line,
+                cut_lines.len()
+            ),
+            TraceEvent::NeighborEnqueued {
+                cell_id,
+                from_cell_id,
+            } => write!(f, "Cell({}) の隣 Cell({}) を追加", from_cell_id, cell_id),
+            TraceEvent::Queue { neighbor_cells } => {
+                write!(f, "次に調べるセル: {:?}", neighbor_cells)
+            }
+            TraceEvent::BoundaryLineAdded { line } => write!(f, "外周の辺を追加: {}", line),
+            TraceEvent::AddCell { cell_id, .. } => write!(f, "Cell({}) を追加", cell_id),
+            TraceEvent::RemoveCell { cell_id, .. } => write!(f, "Cell({}) を削除", cell_id),
+            TraceEvent::CellMerged {
+                cell_id,
+                from_cell_id,
+                ..
+            } => write!(
+                f,
+                "Cell({}) が Cell({}) の領域を引き取る",
+                cell_id, from_cell_id
+            ),
+            TraceEvent::Rebuild { points } => write!(f, "{} 個の点で作り直し", points),
+        }
+    }
+}
+
+/// TraceEvent の受け取り先
+pub trait TraceSink {
+    fn on_event(&mut self, event: &TraceEvent);
+}
+
+/// console.log に出す (wasm用)
+pub struct ConsoleTraceSink;
+
+impl TraceSink for ConsoleTraceSink {
+    fn on_event(&mut self, event: &TraceEvent) {
+        log!("{}", event);
+    }
+}
+
+/// TraceEvent を貯めておく (テストやステップ実行用)。
+/// events() で取り出した参照は、diagram に渡した後も読める。
+pub struct CollectingTraceSink {
+    events: Rc<RefCell<Vec<TraceEvent>>>,
+}
+
+impl CollectingTraceSink {
+    pub fn new() -> Self {
+        Self {
+            events: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn events(&self) -> Rc<RefCell<Vec<TraceEvent>>> {
+        Rc::clone(&self.events)
+    }
+}
+
+impl TraceSink for CollectingTraceSink {
+    fn on_event(&mut self, event: &TraceEvent) {
+        self.events.borrow_mut().push(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::voronoi_diagram::types::VoronoiCenterPoint;
+    use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+
+    fn center_point(node_id: u32, x: f64, y: f64) -> VoronoiCenterPoint {
+        VoronoiCenterPoint {
+            node_id,
+            pos: Vec2d { x, y },
+            cluster_id: 0,
+        }
+    }
+
+    fn kind(event: &TraceEvent) -> &'static str {
+        match event {
+            TraceEvent::InitCell { .. } => "InitCell",
+            TraceEvent::Locate { .. } => "Locate",
+            TraceEvent::Bisector { .. } => "Bisector",
+            TraceEvent::BoundPoints { .. } => "BoundPoints",
+            TraceEvent::CellSplit { .. } => "CellSplit",
+            TraceEvent::NeighborEnqueued { .. } => "NeighborEnqueued",
+            TraceEvent::Queue { .. } => "Queue",
+            TraceEvent::BoundaryLineAdded { .. } => "BoundaryLineAdded",
+            TraceEvent::AddCell { .. } => "AddCell",
+            TraceEvent::RemoveCell { .. } => "RemoveCell",
+            TraceEvent::CellMerged { .. } => "CellMerged",
+            TraceEvent::Rebuild { .. } => "Rebuild",
+        }
+    }
+
+    #[test]
+    fn events_of_small_insertion() {
+        let sink = CollectingTraceSink::new();
+        let events = sink.events();
+        let mut diagram = VoronoiDiagram::new();
+        diagram.trace_sink = Some(Box::new(sink));
+        diagram.add_point(&center_point(0, 0.25, 0.5)).unwrap();
+        diagram.add_point(&center_point(1, 0.75, 0.5)).unwrap();
+        events.borrow_mut().clear();
+        diagram.add_point(&center_point(2, 0.6, 0.9)).unwrap();
+
+        // 右のセルを見つけて分割し、隣の左のセルも分割してから、外周の辺で閉じる
+        let events = events.borrow();
+        let kinds: Vec<&str> = events.iter().map(kind).collect();
+        assert_eq!(
+            vec![
+                "Locate",
+                "Bisector",
+                "BoundPoints",
+                "NeighborEnqueued",
+                "CellSplit",
+                "Queue",
+                "Bisector",
+                "BoundPoints",
+                "CellSplit",
+                "Queue",
+                "BoundaryLineAdded",
+                "BoundaryLineAdded",
+                "AddCell",
+            ],
+            kinds
+        );
+        let cell_ids: Vec<CellId> = events
+            .iter()
+            .filter_map(|e| match e {
+                TraceEvent::Locate { cell_id, .. }
+                | TraceEvent::CellSplit { cell_id, .. }
+                | TraceEvent::AddCell { cell_id, .. } => Some(*cell_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![1, 1, 0, 2], cell_ids);
+        match &events[3] {
+            TraceEvent::NeighborEnqueued {
+                cell_id,
+                from_cell_id,
+            } => assert_eq!((0, 1), (*cell_id, *from_cell_id)),
+            e => panic!("{}", e),
+        }
+    }
+}
@@ -4,18 +4,55 @@ use crate::geometry::vec2d::Vec2d;
 use core::cmp::Ordering;
 use core::f64::consts::PI;
 use core::mem::swap;
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::fmt::{self, Display, Formatter};
 use std::iter::FromIterator;
 
-#[allow(unused_imports)]
-use crate::log;
-
 //////////////////////////////
 
 pub type ClusterId = u32;
 pub type NodeId = u32;
 
+// 分割線がセルの頂点を通るとみなす距離(分割線の長さに対する比率)
+const SNAP_EPS: f64 = 1e-10;
+
+//////////////////////////////
+
+/// 外周上・外周の外に点を追加しようとしたときの扱い
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BoundaryPolicy {
+    Reject, // エラーにする
+    Clamp,  // 外周の少し内側に移動する
+    Expand, // 外周を広げて、既存のセルを作り直す
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VoronoiError {
+    OutOfBounds,      // 外周上または外周の外の点
+    SamePosition,     // 既存の点と同じ位置
+    CellNotFound,     // 点を含むセルが見つからない
+    NodeNotFound,     // 指定した NodeId の点が無い
+    DuplicatedNodeId, // 既に同じ NodeId の点がある
+    FarCircumcenter,  // Delaunay 三角形の外接円の中心が、外周を広げても外に出る
+}
+
+impl Display for VoronoiError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VoronoiError::OutOfBounds => write!(f, "point is on or outside the boundary"),
+            VoronoiError::SamePosition => write!(f, "can not add same position points"),
+            VoronoiError::CellNotFound => write!(f, "including cell not found"),
+            VoronoiError::NodeNotFound => write!(f, "node not found"),
+            VoronoiError::DuplicatedNodeId => write!(f, "node id is already used"),
+            VoronoiError::FarCircumcenter => {
+                write!(f, "circumcenter of a delaunay triangle is too far")
+            }
+        }
+    }
+}
+
 //////////////////////////////
 
 #[derive(Clone)]
@@ -29,7 +66,7 @@ pub struct VoronoiCenterPoint {
 pub struct VoronoiPoint {
     pub pos: Vec2d,
     pub is_corner: bool,
-    pub outbounds: HashSet<u32>, // 外周ID
+    pub outbounds: BTreeSet<u32>, // 外周ID
 }
 
 impl VoronoiPoint {
@@ -37,7 +74,7 @@ impl VoronoiPoint {
         Self {
             pos,
             is_corner: false,
-            outbounds: HashSet::new(),
+            outbounds: BTreeSet::new(),
         }
     }
 
@@ -45,7 +82,7 @@ impl VoronoiPoint {
         Self {
             pos,
             is_corner: true,
-            outbounds: HashSet::from_iter(outbounds),
+            outbounds: BTreeSet::from_iter(outbounds),
         }
     }
 
@@ -84,7 +121,7 @@ pub struct VoronoiLine {
     pub p1: VoronoiPoint,
     pub p2: VoronoiPoint,
     pub line: Line,
-    pub cells: HashSet<CellId>, // 隣接するセル
+    pub cells: BTreeSet<CellId>, // 隣接するセル
 }
 
 impl VoronoiLine {
@@ -93,7 +130,7 @@ impl VoronoiLine {
             line: Line::new(p1.pos.clone(), p2.pos.clone()),
             p1,
             p2,
-            cells: HashSet::new(),
+            cells: BTreeSet::new(),
         }
     }
     pub fn another_cell(&self, my_cell_id: CellId) -> Option<CellId> {
@@ -109,6 +146,12 @@ impl VoronoiLine {
         self.p1.on_outbound() && self.p2.on_outbound()
     }
 
+    /// 端点が同じ(向きは問わない)
+    pub fn is_same_segment(&self, other: &VoronoiLine) -> bool {
+        (self.p1.pos.eq(&other.p1.pos) && self.p2.pos.eq(&other.p2.pos))
+            || (self.p1.pos.eq(&other.p2.pos) && self.p2.pos.eq(&other.p1.pos))
+    }
+
     // #[inline]
     // pub fn is_include(l1: &VoronoiLine, l2: &VoronoiLine) -> bool {
     //     // l1 is on l2, or , l2 is on l1
@@ -162,6 +205,7 @@ impl VoronoiPolygon {
         VoronoiPolygon::new(ps)
     }
 
+    #[allow(dead_code)]
     pub fn is_include(&self, point: Vec2d) -> bool {
         self.polygon.is_include(point)
     }
@@ -196,19 +240,21 @@ impl VoronoiCell {
         }
     }
 
+    #[allow(dead_code)]
     pub fn is_include(&self, point: &VoronoiCenterPoint) -> bool {
         self.bound.is_include(point.pos)
     }
 
-    pub fn get_neighbor_cells(&self) -> HashSet<CellId> {
-        let mut cells: HashSet<CellId> = HashSet::new();
+    pub fn get_neighbor_cells(&self) -> BTreeSet<CellId> {
+        let mut cells: BTreeSet<CellId> = BTreeSet::new();
         for line in self.lines.iter() {
             cells.extend(line.cells.iter());
         }
         cells
     }
 
-    pub fn intersect_with_bounds(&self, line: &Line, next_cell_id: CellId) -> DivideInfo {
+    /// 垂直二等分線 line でセルを分ける。line がセルを横切らない(頂点に触れるだけの)ときは None
+    pub fn intersect_with_bounds(&self, line: &Line, next_cell_id: CellId) -> Option<DivideInfo> {
         // 垂直二等分線がぶつかって、残った境界線分
         let mut cut_lines: Vec<VoronoiLine> = Vec::new();
         // 既存セルに残る点
@@ -218,41 +264,63 @@ impl VoronoiCell {
         // 衝突したセル境界上の座標
         let mut break_points: Vec<VoronoiPoint> = Vec::new();
         // 新しいセルが隣接するセル
-        let mut new_neighbors: HashSet<CellId> = HashSet::new();
+        let mut new_neighbors: BTreeSet<CellId> = BTreeSet::new();
         // 中心点の側(-1. or 1.)
         let my_side = line.point_side(self.point.pos); // 中央の側 符号
+                                                       // これより近い点は同じ点とみなす
+        let eps = line.p1.distance_from(line.p2) * SNAP_EPS;
 
         for bound_line in self.lines.iter() {
             if let Some(pt) = Line::intersection(&bound_line.line, line) {
-                let mut vpt = VoronoiPoint::new(pt);
-                if bound_line.is_outbound() {
-                    let ob = bound_line
-                        .p1
-                        .outbounds
-                        .intersection(&bound_line.p2.outbounds);
-                    for bound in ob {
-                        vpt.outbounds.insert(*bound);
+                // 頂点を通るときは、隣り合う2本の辺の両方とぶつかるので頂点そのものにする
+                let vpt = if bound_line.p1.pos.distance_from(pt) <= eps {
+                    bound_line.p1.clone()
+                } else if bound_line.p2.pos.distance_from(pt) <= eps {
+                    bound_line.p2.clone()
+                } else {
+                    let mut vpt = VoronoiPoint::new(pt);
+                    if bound_line.is_outbound() {
+                        let ob = bound_line
+                            .p1
+                            .outbounds
+                            .intersection(&bound_line.p2.outbounds);
+                        for bound in ob {
+                            vpt.outbounds.insert(*bound);
+                        }
                     }
-                }
-                // cut line
+                    vpt
+                };
+                // cut line (中心点の側に遠い方の端点を残す。頂点で触れただけなら残る線分は無い)
                 {
-                    let s1 = line.point_side(bound_line.p1.pos);
-                    let mut cut_line = if s1 == my_side {
-                        VoronoiLine::new(bound_line.p1.clone(), vpt.clone())
+                    let v1 = line.point_side_value(bound_line.p1.pos) * my_side;
+                    let v2 = line.point_side_value(bound_line.p2.pos) * my_side;
+                    let end = if v1 >= v2 {
+                        &bound_line.p1
                     } else {
-                        VoronoiLine::new(bound_line.p2.clone(), vpt.clone())
+                        &bound_line.p2
                     };
-                    cut_line.cells.extend(bound_line.cells.iter());
-                    cut_lines.push(cut_line);
+                    if end.pos.distance_from(vpt.pos) > eps {
+                        let mut cut_line = VoronoiLine::new(end.clone(), vpt.clone());
+                        cut_line.cells.extend(bound_line.cells.iter());
+                        cut_lines.push(cut_line);
+                    }
+                }
+                if break_points
+                    .iter()
+                    .all(|p| p.pos.distance_from(vpt.pos) > eps)
+                {
+                    break_points.push(vpt.clone());
+                    remain_points.push(vpt.clone());
+                    separated_points.push(vpt.clone());
                 }
-                break_points.push(vpt.clone());
-                remain_points.push(vpt.clone());
-                separated_points.push(vpt.clone());
                 if let Some(another_cell_id) = bound_line.another_cell(self.cell_id) {
                     new_neighbors.insert(another_cell_id);
                 }
             }
         }
+        if break_points.len() < 2 {
+            return None;
+        }
         assert_eq!(2, break_points.len());
 
         let mut middle_line = VoronoiLine::new(break_points[0].clone(), break_points[1].clone());
@@ -261,7 +329,6 @@ impl VoronoiCell {
 
         let my_side = line.point_side(self.point.pos);
         for point in self.bound.points.iter() {
-            // log!("bound_points: {}", point.pos);
             if my_side == line.point_side(point.pos) {
                 // myCellならば全部残す
                 remain_points.push(point.clone());
@@ -275,13 +342,13 @@ impl VoronoiCell {
 
         assert!(remain_points.len() >= 3);
 
-        DivideInfo {
+        Some(DivideInfo {
             cut_lines,
             remain_points,
             separated_points,
             middle_line,
             new_neighbors: Vec::from_iter(new_neighbors),
-        }
+        })
     }
 
     pub fn update(&mut self, divide_info: &DivideInfo) {
@@ -298,12 +365,16 @@ impl VoronoiCell {
             }
         }
 
-        v_lines.extend_from_slice(&divide_info.cut_lines.clone());
+        for cut_line in divide_info.cut_lines.iter() {
+            // 頂点を分割線が通るときは、残した辺と cut line が同じになることがある
+            if !v_lines.iter().any(|l| l.is_same_segment(cut_line)) {
+                v_lines.push(cut_line.clone());
+            }
+        }
         v_lines.push(divide_info.middle_line.clone());
 
         self.bound = VoronoiPolygon::from_lines(&self.point, &v_lines);
         self.lines = v_lines;
-        //log!("update cell: {}", self);
     }
 }
 
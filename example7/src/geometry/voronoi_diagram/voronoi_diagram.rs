@@ -1,49 +1,317 @@
 use crate::geometry::line::Line;
+use crate::geometry::rect::Rect;
 use crate::geometry::vec2d::Vec2d;
 
-#[allow(unused_imports)]
+use crate::geometry::voronoi_diagram::cell_key::{CellKey, CellKeys};
+use crate::geometry::voronoi_diagram::insertion_order::brio_order;
+use crate::geometry::voronoi_diagram::trace::{TraceEvent, TraceSink};
 use crate::geometry::voronoi_diagram::types::{
-    vec_to_s, CellId, VoronoiCell, VoronoiCenterPoint, VoronoiLine, VoronoiPoint, VoronoiPolygon,
+    BoundaryPolicy, CellId, NodeId, VoronoiCell, VoronoiCenterPoint, VoronoiError, VoronoiLine,
+    VoronoiPoint, VoronoiPolygon,
 };
+#[allow(unused_imports)]
 use crate::log;
-use rand::seq::SliceRandom;
+use core::cmp::Ordering;
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::rc::Rc;
 
 pub type VoronoiCellRef = Rc<RefCell<VoronoiCell>>;
 
+// Clampするときに外周からどれだけ内側に入れるか(外周の大きさに対する比率)
+const CLAMP_MARGIN: f64 = 1e-6;
+// Expandするときに、点の外側にどれだけ余白をとるか(外周の大きさに対する比率)
+const EXPAND_MARGIN: f64 = 0.05;
+// セルを削除するときに同じ点とみなす距離(外周の大きさに対する比率)
+const MERGE_EPS: f64 = 1e-9;
+
 pub struct VoronoiDiagram {
     pub outbound: VoronoiPolygon,
     pub cells: Vec<VoronoiCellRef>,
+    pub boundary_policy: BoundaryPolicy,
+    pub trace_sink: Option<Box<dyn TraceSink>>, // add_point の途中経過を送る先
+    keys: CellKeys,
+    last_cell_id: Option<CellId>, // 点の位置を探し始めるセル(最後に追加したセル)
 }
 
 impl VoronoiDiagram {
     pub fn new() -> Self {
+        VoronoiDiagram::with_bounds(Rect {
+            pos: Vec2d { x: 0., y: 0. },
+            size: Vec2d { x: 1., y: 1. },
+        })
+    }
+
+    /// 外周を矩形 bounds にする
+    pub fn with_bounds(bounds: Rect) -> Self {
+        let (p1, p2) = (bounds.pos, bounds.pos + bounds.size);
         VoronoiDiagram {
             outbound: VoronoiPolygon::new(vec![
-                VoronoiPoint::corner(Vec2d { x: 0., y: 0. }, vec![0, 3]),
-                VoronoiPoint::corner(Vec2d { x: 1., y: 0. }, vec![0, 1]),
-                VoronoiPoint::corner(Vec2d { x: 1., y: 1. }, vec![1, 2]),
-                VoronoiPoint::corner(Vec2d { x: 0., y: 1. }, vec![2, 3]),
+                VoronoiPoint::corner(Vec2d { x: p1.x, y: p1.y }, vec![0, 3]),
+                VoronoiPoint::corner(Vec2d { x: p2.x, y: p1.y }, vec![0, 1]),
+                VoronoiPoint::corner(Vec2d { x: p2.x, y: p2.y }, vec![1, 2]),
+                VoronoiPoint::corner(Vec2d { x: p1.x, y: p2.y }, vec![2, 3]),
             ]),
             cells: Vec::new(),
+            boundary_policy: BoundaryPolicy::Reject,
+            trace_sink: None,
+            keys: CellKeys::new(),
+            last_cell_id: None,
         }
     }
 
     // https://www.jaist.ac.jp/~uehara/course/2014/i481f/pdf/ppt-6.pdf
+    // 同じ seed なら同じ順に追加するので、結果も毎回同じになる
     #[allow(dead_code)]
-    pub fn calculate_diagram(&mut self, points: &mut Vec<VoronoiCenterPoint>) {
-        let mut rng = rand::thread_rng();
-        points.shuffle(&mut rng);
+    pub fn calculate_diagram(
+        &mut self,
+        points: &mut Vec<VoronoiCenterPoint>,
+        seed: u64,
+    ) -> Result<(), VoronoiError> {
+        brio_order(points, &self.outbound.polygon.bounding_rect(), seed);
 
         for point in points.iter() {
-            self.add_point(point);
+            self.add_point(point)?;
+        }
+        Ok(())
+    }
+
+    /// boundary_policy に従って点を追加して、追加したセルの CellKey を返す
+    pub fn add_point(&mut self, point: &VoronoiCenterPoint) -> Result<CellKey, VoronoiError> {
+        if self.keys.key_of_node(point.node_id).is_some() {
+            return Err(VoronoiError::DuplicatedNodeId);
+        }
+        let (pos, bounds) = self.place(point.pos)?;
+        // 外周を広げてから追加に失敗したら、広げる前の外周と点に戻す
+        let saved = match bounds {
+            Some(bounds) => {
+                let saved = (self.outbound.polygon.bounding_rect(), self.keyed_points());
+                self.rebuild_with_bounds(bounds)?;
+                Some(saved)
+            }
+            None => None,
+        };
+        let mut point = point.clone();
+        point.pos = pos;
+        let cell_id = match self.insert_point(&point) {
+            Ok(cell_id) => cell_id,
+            Err(e) => {
+                if let Some((bounds, points)) = saved {
+                    self.rebuild(bounds, points)?;
+                }
+                return Err(e);
+            }
+        };
+        Ok(self.keys.insert(point.node_id, cell_id))
+    }
+
+    /// 点を削除する。セルは作り直すので CellId は変わるが、他のセルの CellKey は変わらない。
+    #[allow(dead_code)]
+    pub fn remove_point(&mut self, node_id: NodeId) -> Result<(), VoronoiError> {
+        self.remove_points(&[node_id])
+    }
+
+    /// 複数の点をまとめて削除する(作り直しは1回)。1つでも無い点があれば何も変えない。
+    pub fn remove_points(&mut self, node_ids: &[NodeId]) -> Result<(), VoronoiError> {
+        let keys: Vec<CellKey> = node_ids
+            .iter()
+            .map(|node_id| self.keys.key_of_node(*node_id))
+            .collect::<Option<Vec<CellKey>>>()
+            .ok_or(VoronoiError::NodeNotFound)?;
+        let removed: HashSet<CellKey> = keys.iter().cloned().collect();
+        let mut points = self.keyed_points();
+        points.retain(|(k, _)| !removed.contains(k));
+        let bounds = self.outbound.polygon.bounding_rect();
+        self.rebuild(bounds, points)?;
+        for (key, node_id) in keys.iter().zip(node_ids.iter()) {
+            self.keys.remove(*key, *node_id);
+        }
+        Ok(())
+    }
+
+    /// 点を pos に移動する (boundary_policy に従う)。CellKey は変わらない。
+    #[allow(dead_code)]
+    pub fn move_point(&mut self, node_id: NodeId, pos: Vec2d) -> Result<(), VoronoiError> {
+        let key = self
+            .keys
+            .key_of_node(node_id)
+            .ok_or(VoronoiError::NodeNotFound)?;
+        let (pos, bounds) = self.place(pos)?;
+        let mut points = self.keyed_points();
+        for (k, point) in points.iter_mut() {
+            if *k == key {
+                point.pos = pos;
+            }
         }
+        let bounds = bounds.unwrap_or(self.outbound.polygon.bounding_rect());
+        self.rebuild(bounds, points)
+    }
+
+    /// 点を pos に移動する。全体は作り直さずに、元のセルを隣のセルに分け与えてから pos に追加し直す。
+    /// CellKey は変わらないが、移動した点と最後のセルの CellId は変わる。
+    /// 外周を広げる必要があるときは move_point と同じく作り直す。
+    #[allow(dead_code)]
+    pub fn move_point_incremental(
+        &mut self,
+        node_id: NodeId,
+        pos: Vec2d,
+    ) -> Result<(), VoronoiError> {
+        let key = self
+            .keys
+            .key_of_node(node_id)
+            .ok_or(VoronoiError::NodeNotFound)?;
+        let (pos, bounds) = self.place(pos)?;
+        if bounds.is_some() {
+            return self.move_point(node_id, pos);
+        }
+        let cell_id = self.keys.cell_id(key).unwrap();
+        // 移動先に別の点があれば何も変えない
+        if let Some(cell) = self.find_cell_at(pos) {
+            let cell = cell.borrow();
+            if cell.point.pos.eq(&pos) {
+                return if cell.cell_id == cell_id {
+                    Ok(())
+                } else {
+                    Err(VoronoiError::SamePosition)
+                };
+            }
+        }
+
+        // 失敗したときに元に戻すための点
+        let points = self.keyed_points();
+        let bounds = self.outbound.polygon.bounding_rect();
+
+        let mut point = self.get_cell(cell_id).borrow().point.clone();
+        point.pos = pos;
+        self.remove_cell(cell_id);
+        match self.insert_point(&point) {
+            Ok(new_cell_id) => {
+                // remove_cell で最後のセルが cell_id に移り、追加したセルが最後になる
+                self.keys.swap_cells(cell_id, new_cell_id);
+                Ok(())
+            }
+            Err(e) => {
+                // 途中まで変えたセルが残っているので、移動する前の点で作り直す
+                self.rebuild(bounds, points)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// 外周を bounds に変えて、既存の点を同じ順に追加し直す(CellId, CellKey は変わらない)
+    pub fn rebuild_with_bounds(&mut self, bounds: Rect) -> Result<(), VoronoiError> {
+        let points = self.keyed_points();
+        self.rebuild(bounds, points)
+    }
+
+    /// 全ての中心点 (CellId の順)
+    pub fn points(&self) -> Vec<VoronoiCenterPoint> {
+        self.cells
+            .iter()
+            .map(|c| c.borrow().point.clone())
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cell_by_key(&self, key: CellKey) -> Option<&VoronoiCellRef> {
+        let cell_id = self.keys.cell_id(key)?;
+        self.cells.get(cell_id as usize)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cell_by_node_id(&self, node_id: NodeId) -> Option<&VoronoiCellRef> {
+        self.get_cell_by_key(self.keys.key_of_node(node_id)?)
+    }
+
+    #[allow(dead_code)]
+    pub fn key_of_cell(&self, cell_id: CellId) -> Option<CellKey> {
+        self.keys.key_of_cell(cell_id)
+    }
+
+    #[allow(dead_code)]
+    pub fn key_of_node(&self, node_id: NodeId) -> Option<CellKey> {
+        self.keys.key_of_node(node_id)
+    }
+
+    // boundary_policy に従って、点を置く位置と(外周を広げる場合は)新しい外周を決める
+    fn place(&self, pos: Vec2d) -> Result<(Vec2d, Option<Rect>), VoronoiError> {
+        let bounds = self.outbound.polygon.bounding_rect();
+        if is_strictly_inside(&bounds, pos) {
+            return Ok((pos, None));
+        }
+        match self.boundary_policy {
+            BoundaryPolicy::Reject => Err(VoronoiError::OutOfBounds),
+            BoundaryPolicy::Clamp => {
+                let margin = bounds.size.x.max(bounds.size.y) * CLAMP_MARGIN;
+                let clamped = Vec2d {
+                    x: pos
+                        .x
+                        .clamp(bounds.pos.x + margin, bounds.pos.x + bounds.size.x - margin),
+                    y: pos
+                        .y
+                        .clamp(bounds.pos.y + margin, bounds.pos.y + bounds.size.y - margin),
+                };
+                Ok((clamped, None))
+            }
+            BoundaryPolicy::Expand => {
+                let margin = bounds.size.x.max(bounds.size.y) * EXPAND_MARGIN;
+                let p1 = Vec2d {
+                    x: bounds.pos.x.min(pos.x - margin),
+                    y: bounds.pos.y.min(pos.y - margin),
+                };
+                let p2 = Vec2d {
+                    x: (bounds.pos.x + bounds.size.x).max(pos.x + margin),
+                    y: (bounds.pos.y + bounds.size.y).max(pos.y + margin),
+                };
+                Ok((
+                    pos,
+                    Some(Rect {
+                        pos: p1,
+                        size: p2 - p1,
+                    }),
+                ))
+            }
+        }
+    }
+
+    fn keyed_points(&self) -> Vec<(CellKey, VoronoiCenterPoint)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(cell_id, c)| {
+                let key = self.keys.key_of_cell(cell_id as CellId).unwrap();
+                (key, c.borrow().point.clone())
+            })
+            .collect()
+    }
+
+    // 外周を bounds にして points を順に追加し直す。失敗したら何も変えない。
+    fn rebuild(
+        &mut self,
+        bounds: Rect,
+        points: Vec<(CellKey, VoronoiCenterPoint)>,
+    ) -> Result<(), VoronoiError> {
+        let mut diagram = VoronoiDiagram::with_bounds(bounds);
+        diagram.boundary_policy = self.boundary_policy;
+        diagram.trace_sink = self.trace_sink.take();
+        diagram.trace(|| TraceEvent::Rebuild {
+            points: points.len(),
+        });
+        let mut keys: Vec<CellKey> = Vec::with_capacity(points.len());
+        for (key, point) in points.iter() {
+            if let Err(e) = diagram.insert_point(point) {
+                self.trace_sink = diagram.trace_sink.take();
+                return Err(e);
+            }
+            keys.push(*key);
+        }
+        diagram.keys = std::mem::replace(&mut self.keys, CellKeys::new());
+        diagram.keys.remap(keys);
+        *self = diagram;
+        Ok(())
     }
 
     pub fn init_cell(&mut self, point: &VoronoiCenterPoint) {
-        //log!("init cell: ({},{})", point.pos.x, point.pos.y);
         let mut lines: Vec<VoronoiLine> = Vec::new();
         let mut around_points = self.outbound.points.clone();
         let mut last_point = around_points.remove(0);
@@ -52,26 +320,32 @@ impl VoronoiDiagram {
             lines.push(VoronoiLine::new(last_point.clone(), pt.clone()));
             last_point = pt.clone();
         }
-        self.add_cell(point, lines);
-        return;
+        let new_cell = self.add_cell(point, lines);
+        self.trace(|| TraceEvent::InitCell {
+            cell_id: new_cell.borrow().cell_id,
+            point: point.pos,
+        });
     }
 
-    // 現状のCellsに新しい点を追加する
-    pub fn add_point(&mut self, point: &VoronoiCenterPoint) {
+    // 現状のCellsに新しい点を追加する (pointは外周の内側にあること)
+    fn insert_point(&mut self, point: &VoronoiCenterPoint) -> Result<CellId, VoronoiError> {
+        let next_cell_id = self.cells.len() as CellId;
         if self.cells.is_empty() {
-            return self.init_cell(point);
+            self.init_cell(point);
+            return Ok(next_cell_id);
         }
 
-        //log!("add_point: ({},{})", point.pos.x, point.pos.y);
-        let next_cell_id = self.cells.len() as CellId;
         let mut _current_cell = self
-            .find_including_cell(point)
-            .expect("Including Cell Not Found");
+            .find_cell_at(point.pos)
+            .ok_or(VoronoiError::CellNotFound)?;
 
         if _current_cell.as_ref().borrow().point.pos.eq(&point.pos) {
-            log!("can not add same position points");
-            return;
+            return Err(VoronoiError::SamePosition);
         }
+        self.trace(|| TraceEvent::Locate {
+            point: point.pos,
+            cell_id: _current_cell.borrow().cell_id,
+        });
 
         // 既に処理したセル
         let mut checked_cell_set: HashSet<CellId> = HashSet::new();
@@ -80,46 +354,91 @@ impl VoronoiDiagram {
         // 新しいcellの線分のリスト
         let mut new_cell_lines: Vec<VoronoiLine> = Vec::new();
         let mut new_cell_points: Vec<VoronoiPoint> = Vec::new();
+        // 垂直二等分線は外周のどこからどこまでも横切れる長さにする
+        let bounds = self.outbound.polygon.bounding_rect();
+        let bisector_length = 2. * (bounds.size.x.powi(2) + bounds.size.y.powi(2)).sqrt();
         loop {
             {
                 let mut current_cell = _current_cell.borrow_mut();
                 checked_cell_set.insert(current_cell.cell_id);
-                //log!("current_cell_id={}", current_cell.cell_id);
 
                 // 垂直二等分線をもとめる -> (2)
-                let middle_line = Line::vertical_bisector(point.pos, current_cell.point.pos, 5.);
-                //log!("middle: {}", middle_line);
+                let middle_line =
+                    Line::vertical_bisector(point.pos, current_cell.point.pos, bisector_length);
+                self.trace(|| TraceEvent::Bisector {
+                    cell_id: current_cell.cell_id,
+                    site: current_cell.point.pos,
+                    polygon: current_cell.bound.polygon.points.clone(),
+                    line: middle_line.clone(),
+                });
                 // (2)が交差する current_cellの辺(どれか) を求める -> (3)
-                let divide_info = current_cell.intersect_with_bounds(&middle_line, next_cell_id);
-
-                // 次のセルを探す
-                for another_cell_id in divide_info.new_neighbors.iter() {
-                    if !checked_cell_set.contains(another_cell_id) {
-                        neighbor_cells.push(*another_cell_id);
+                // 頂点に触れるだけなら、このセルは変わらない
+                if let Some(divide_info) =
+                    current_cell.intersect_with_bounds(&middle_line, next_cell_id)
+                {
+                    self.trace(|| TraceEvent::BoundPoints {
+                        cell_id: current_cell.cell_id,
+                        remain_points: divide_info.remain_points.iter().map(|p| p.pos).collect(),
+                        separated_points: divide_info
+                            .separated_points
+                            .iter()
+                            .map(|p| p.pos)
+                            .collect(),
+                    });
+                    // 次のセルを探す
+                    for another_cell_id in divide_info.new_neighbors.iter() {
+                        if !checked_cell_set.contains(another_cell_id) {
+                            neighbor_cells.push(*another_cell_id);
+                            self.trace(|| TraceEvent::NeighborEnqueued {
+                                cell_id: *another_cell_id,
+                                from_cell_id: current_cell.cell_id,
+                            });
+                        }
                     }
-                }
 
-                // 新Cellの線分追加
-                new_cell_lines.push(divide_info.middle_line.clone());
-                for pt in divide_info.separated_points.iter() {
-                    if pt.is_corner {
-                        new_cell_points.push(pt.clone());
+                    // 新Cellの線分追加
+                    new_cell_lines.push(divide_info.middle_line.clone());
+                    for pt in divide_info.separated_points.iter() {
+                        if pt.is_corner {
+                            new_cell_points.push(pt.clone());
+                        }
                     }
-                }
 
-                // current_cellの更新
-                {
-                    let mut old_neighbors = current_cell.get_neighbor_cells();
-                    current_cell.update(&divide_info);
-                    let new_neighbors = current_cell.get_neighbor_cells();
-                    old_neighbors.retain(|x| !new_neighbors.contains(x));
-                    for neighbor_cell_id in old_neighbors {
-                        if !checked_cell_set.contains(&neighbor_cell_id) {
-                            neighbor_cells.push(neighbor_cell_id);
+                    // current_cellの更新
+                    {
+                        let mut old_neighbors = current_cell.get_neighbor_cells();
+                        current_cell.update(&divide_info);
+                        let new_neighbors = current_cell.get_neighbor_cells();
+                        old_neighbors.retain(|x| !new_neighbors.contains(x));
+                        for neighbor_cell_id in old_neighbors {
+                            if !checked_cell_set.contains(&neighbor_cell_id) {
+                                neighbor_cells.push(neighbor_cell_id);
+                                self.trace(|| TraceEvent::NeighborEnqueued {
+                                    cell_id: neighbor_cell_id,
+                                    from_cell_id: current_cell.cell_id,
+                                });
+                            }
                         }
                     }
+                    self.trace(|| TraceEvent::CellSplit {
+                        cell_id: current_cell.cell_id,
+                        middle_line: divide_info.middle_line.line.clone(),
+                        cut_lines: divide_info
+                            .cut_lines
+                            .iter()
+                            .map(|l| l.line.clone())
+                            .collect(),
+                        polygon: current_cell.bound.polygon.points.clone(),
+                    });
                 }
             }
+            self.trace(|| TraceEvent::Queue {
+                neighbor_cells: neighbor_cells
+                    .iter()
+                    .filter(|c| **c != next_cell_id)
+                    .cloned()
+                    .collect(),
+            });
 
             if neighbor_cells.is_empty() {
                 break;
@@ -153,33 +472,179 @@ impl VoronoiDiagram {
                 out_point.push(point.clone());
             }
         }
+        let out_point = VoronoiPoint::uniq_points(&out_point);
+        // 凸なセルが外周の1辺と接するのは1つの線分なので、その辺の上にある点のうち両端をつなぐ。
+        // (隣り合う点同士を順番につなぐと、四隅をいくつも含むときに辺が抜けることがある)
+        let mut outbounds: Vec<u32> = out_point
+            .iter()
+            .flat_map(|p| p.outbounds.iter().cloned())
+            .collect();
+        outbounds.sort();
+        outbounds.dedup();
+        for outbound in outbounds {
+            let mut on_bound: Vec<&VoronoiPoint> = out_point
+                .iter()
+                .filter(|p| p.outbounds.contains(&outbound))
+                .collect();
+            if on_bound.len() < 2 {
+                continue;
+            }
+            // 同じ直線上の点なので (x, y) の辞書順が直線上の順になる
+            on_bound.sort_by(|a, b| {
+                (a.pos.x, a.pos.y)
+                    .partial_cmp(&(b.pos.x, b.pos.y))
+                    .unwrap_or(Ordering::Equal)
+            });
+            let vl = VoronoiLine::new(on_bound[0].clone(), on_bound[on_bound.len() - 1].clone());
+            self.trace(|| TraceEvent::BoundaryLineAdded {
+                line: vl.line.clone(),
+            });
+            new_cell_lines.push(vl);
+        }
+        let new_cell = self.add_cell(point, new_cell_lines);
+        self.trace(|| TraceEvent::AddCell {
+            cell_id: next_cell_id,
+            polygon: new_cell.borrow().bound.polygon.points.clone(),
+        });
+        /////////////////////////////////////////////////
+        Ok(next_cell_id)
+    }
+
+    // セルを削除して、その領域を隣のセルに分け与える。最後のセルが cell_id に移る。
+    // 削除したセルの領域は、隣のセル(自然近傍)だけで分け合うことになる。
+    fn remove_cell(&mut self, cell_id: CellId) {
+        let removed = Rc::clone(self.get_cell(cell_id));
+        let removed = removed.borrow();
+        self.trace(|| TraceEvent::RemoveCell {
+            cell_id,
+            polygon: removed.bound.polygon.points.clone(),
+        });
+        let mut neighbors: Vec<(CellId, Vec2d)> = removed
+            .get_neighbor_cells()
+            .into_iter()
+            .filter(|c| *c != cell_id)
+            .map(|c| (c, self.get_cell(c).borrow().point.pos))
+            .collect();
+        neighbors.sort_by_key(|(c, _)| *c);
+        let bounds = self.outbound.polygon.bounding_rect();
+        let eps = (bounds.size.x + bounds.size.y) * MERGE_EPS;
+
+        // 削除するセルの外形。辺は次の頂点への辺の種類
+        let points = &removed.bound.points;
+        let region: Vec<(VoronoiPoint, EdgeKind)> = (0..points.len())
+            .map(|i| {
+                let (p1, p2) = (&points[i], &points[(i + 1) % points.len()]);
+                let kind = if p1.outbounds.intersection(&p2.outbounds).next().is_some() {
+                    EdgeKind::Outbound
+                } else {
+                    EdgeKind::Inner
+                };
+                (p1.clone(), kind)
+            })
+            .collect();
 
-        out_point.reverse();
-        //log!("out_point: {}", vec_to_s(&out_point, ","));
-        while !out_point.is_empty() {
-            let tp = out_point.pop().unwrap();
-            new_cell_points.retain(|p| !p.eq(&tp));
-            out_point.retain(|p| !p.eq(&tp));
-            for point in new_cell_points.clone().iter() {
-                if tp.outbounds.intersection(&point.outbounds).count() > 0 {
-                    let vl = VoronoiLine::new(tp.clone(), point.clone());
-                    //log!("add bound line: {}", vl.line);
-                    new_cell_lines.push(vl);
-                    break;
+        for (neighbor_cell_id, site) in neighbors.iter() {
+            // 隣のセルがもらう部分 = 他のどの隣のセルよりも近い部分
+            let mut part = region.clone();
+            for (other_cell_id, other) in neighbors.iter() {
+                if other_cell_id != neighbor_cell_id {
+                    part = clip_region(&part, *site, *other, *other_cell_id);
                 }
             }
+            let part = dedup_region(part, eps);
+
+            let mut neighbor = self.get_cell(*neighbor_cell_id).borrow_mut();
+            let mut lines: Vec<VoronoiLine> = neighbor
+                .lines
+                .iter()
+                .filter(|l| !l.cells.contains(&cell_id))
+                .cloned()
+                .collect();
+            if part.len() >= 3 {
+                for i in 0..part.len() {
+                    let (p1, kind) = &part[i];
+                    let p2 = &part[(i + 1) % part.len()].0;
+                    let mut line = VoronoiLine::new(p1.clone(), p2.clone());
+                    match kind {
+                        // 削除するセルと他のセルの境界は、もらう部分の辺にはならない
+                        EdgeKind::Inner => continue,
+                        EdgeKind::Outbound => {}
+                        EdgeKind::Bisector(other_cell_id) => {
+                            line.cells.insert(*other_cell_id);
+                        }
+                    }
+                    line.cells.insert(*neighbor_cell_id);
+                    merge_line(&mut lines, line, eps);
+                }
+            }
+            neighbor.bound = VoronoiPolygon::from_lines(&neighbor.point, &lines);
+            neighbor.lines = lines;
+            let polygon = neighbor.bound.polygon.points.clone();
+            drop(neighbor);
+            self.trace(|| TraceEvent::CellMerged {
+                cell_id: *neighbor_cell_id,
+                from_cell_id: cell_id,
+                polygon,
+            });
         }
-        self.add_cell(point, new_cell_lines);
-        /////////////////////////////////////////////////
+        drop(removed);
+
+        let last_cell_id = self.cells.len() as CellId - 1;
+        self.cells.swap_remove(cell_id as usize);
+        if cell_id != last_cell_id {
+            // 最後のセルを cell_id に付け替える
+            let moved = Rc::clone(self.get_cell(cell_id));
+            let mut moved = moved.borrow_mut();
+            moved.cell_id = cell_id;
+            relabel_lines(&mut moved.lines, last_cell_id, cell_id);
+            for neighbor_cell_id in moved.get_neighbor_cells() {
+                if neighbor_cell_id != cell_id {
+                    let mut neighbor = self.get_cell(neighbor_cell_id).borrow_mut();
+                    relabel_lines(&mut neighbor.lines, last_cell_id, cell_id);
+                }
+            }
+        }
+        self.last_cell_id = neighbors
+            .first()
+            .map(|(c, _)| if *c == last_cell_id { cell_id } else { *c });
     }
 
-    fn find_including_cell(&self, point: &VoronoiCenterPoint) -> Option<VoronoiCellRef> {
-        for cell in self.cells.iter() {
-            if cell.borrow().is_include(point) {
-                return Some(Rc::clone(cell));
+    /// pos を含むセル (外周の外なら None)
+    pub fn find_cell_at(&self, pos: Vec2d) -> Option<VoronoiCellRef> {
+        if !self.outbound.polygon.contains(pos) {
+            return None;
+        }
+        // 中心が一番近いセルがposを含む (セルの境界上の点でも見つかる)
+        let start_cell_id = self.last_cell_id?;
+        Some(self.walk_to_nearest_cell(pos, start_cell_id))
+    }
+
+    // 隣のセルのうち、中心がposにより近いものへ移動していく。
+    // 移動できなくなったセルの中心が一番近い点なので、そのセルがposを含む。
+    pub fn walk_to_nearest_cell(&self, pos: Vec2d, start_cell_id: CellId) -> VoronoiCellRef {
+        let mut current = Rc::clone(self.get_cell(start_cell_id));
+        loop {
+            let mut next: Option<VoronoiCellRef> = None;
+            {
+                let cell = current.borrow();
+                let mut distance = cell.point.pos.distance_from(pos);
+                for neighbor_cell_id in cell.get_neighbor_cells() {
+                    if neighbor_cell_id == cell.cell_id {
+                        continue;
+                    }
+                    let neighbor = self.get_cell(neighbor_cell_id);
+                    let d = neighbor.borrow().point.pos.distance_from(pos);
+                    if d < distance {
+                        distance = d;
+                        next = Some(Rc::clone(neighbor));
+                    }
+                }
+            }
+            match next {
+                Some(cell) => current = cell,
+                None => return current,
             }
         }
-        None
     }
 
     fn add_cell(&mut self, point: &VoronoiCenterPoint, lines: Vec<VoronoiLine>) -> VoronoiCellRef {
@@ -190,15 +655,476 @@ impl VoronoiDiagram {
             line.cells.insert(cell_id);
         }
 
-        ///////////////////////
-        //log!("=============== Add\n{}", new_cell.as_ref().borrow());
-        ///////////////////////
-
         self.cells.push(Rc::clone(&new_cell));
+        self.last_cell_id = Some(cell_id);
         new_cell
     }
 
-    fn get_cell(&self, cell_id: CellId) -> &VoronoiCellRef {
+    // trace_sink が無いときは event を作らない
+    fn trace<F: FnOnce() -> TraceEvent>(&mut self, event: F) {
+        if let Some(sink) = self.trace_sink.as_mut() {
+            sink.on_event(&event());
+        }
+    }
+
+    pub fn get_cell(&self, cell_id: CellId) -> &VoronoiCellRef {
         self.cells.get(cell_id as usize).unwrap()
     }
 }
+
+#[inline]
+fn is_strictly_inside(bounds: &Rect, pos: Vec2d) -> bool {
+    bounds.pos.x < pos.x
+        && pos.x < bounds.pos.x + bounds.size.x
+        && bounds.pos.y < pos.y
+        && pos.y < bounds.pos.y + bounds.size.y
+}
+
+// remove_cell で切り分ける多角形の辺の種類
+#[derive(Clone, Copy)]
+enum EdgeKind {
+    Inner,            // 削除するセルと他のセルの境界
+    Outbound,         // 外周
+    Bisector(CellId), // そのセルとの垂直二等分線
+}
+
+// region のうち other より site に近い側を残す (Sutherland-Hodgman)
+fn clip_region(
+    region: &[(VoronoiPoint, EdgeKind)],
+    site: Vec2d,
+    other: Vec2d,
+    other_cell_id: CellId,
+) -> Vec<(VoronoiPoint, EdgeKind)> {
+    let mid = Vec2d::middle_point(site, other);
+    let dir = other - site;
+    let side = |p: Vec2d| (p.x - mid.x) * dir.x + (p.y - mid.y) * dir.y;
+    let n = region.len();
+    let mut ret: Vec<(VoronoiPoint, EdgeKind)> = Vec::with_capacity(n + 1);
+    for i in 0..n {
+        let (p1, kind) = &region[i];
+        let p2 = &region[(i + 1) % n].0;
+        let (v1, v2) = (side(p1.pos), side(p2.pos));
+        let cross = || {
+            let t = v1 / (v1 - v2);
+            let mut vp = VoronoiPoint::new(p1.pos + (p2.pos - p1.pos).mul(t));
+            if let EdgeKind::Outbound = kind {
+                vp.outbounds = p1.outbounds.intersection(&p2.outbounds).cloned().collect();
+            }
+            vp
+        };
+        if v1 <= 0. {
+            if v2 <= 0. {
+                ret.push((p1.clone(), *kind));
+            } else if v1 < 0. {
+                ret.push((p1.clone(), *kind));
+                ret.push((cross(), EdgeKind::Bisector(other_cell_id)));
+            } else {
+                ret.push((p1.clone(), EdgeKind::Bisector(other_cell_id)));
+            }
+        } else if v2 < 0. {
+            ret.push((cross(), *kind));
+        }
+    }
+    ret
+}
+
+// 長さ eps 以下の辺をつぶす
+fn dedup_region(region: Vec<(VoronoiPoint, EdgeKind)>, eps: f64) -> Vec<(VoronoiPoint, EdgeKind)> {
+    let mut ret: Vec<(VoronoiPoint, EdgeKind)> = Vec::with_capacity(region.len());
+    for (point, kind) in region {
+        match ret.last_mut() {
+            Some(last) if last.0.pos.distance_from(point.pos) <= eps => {
+                merge_point(&mut last.0, &point);
+                last.1 = kind;
+            }
+            _ => ret.push((point, kind)),
+        }
+    }
+    while ret.len() > 1 && ret[0].0.pos.distance_from(ret[ret.len() - 1].0.pos) <= eps {
+        let (point, _) = ret.pop().unwrap();
+        merge_point(&mut ret[0].0, &point);
+    }
+    ret
+}
+
+fn merge_point(point: &mut VoronoiPoint, other: &VoronoiPoint) {
+    point.is_corner |= other.is_corner;
+    point.outbounds.extend(other.outbounds.iter());
+}
+
+// line と同じセルの間で、端点を共有する線分があれば1本につなげる(同じ直線上にある)
+fn merge_line(lines: &mut Vec<VoronoiLine>, line: VoronoiLine, eps: f64) {
+    let mut line = line;
+    loop {
+        let found = lines.iter().enumerate().find_map(|(i, l)| {
+            if l.cells != line.cells {
+                return None;
+            }
+            // 外周ならば同じ辺の上にあること
+            if line.cells.len() == 1 {
+                let ob1: BTreeSet<u32> =
+                    l.p1.outbounds
+                        .intersection(&l.p2.outbounds)
+                        .cloned()
+                        .collect();
+                if ob1.is_disjoint(
+                    &line
+                        .p1
+                        .outbounds
+                        .intersection(&line.p2.outbounds)
+                        .cloned()
+                        .collect(),
+                ) {
+                    return None;
+                }
+            }
+            let near = |a: &VoronoiPoint, b: &VoronoiPoint| a.pos.distance_from(b.pos) <= eps;
+            if near(&l.p1, &line.p1) {
+                Some((i, l.p2.clone(), line.p2.clone()))
+            } else if near(&l.p1, &line.p2) {
+                Some((i, l.p2.clone(), line.p1.clone()))
+            } else if near(&l.p2, &line.p1) {
+                Some((i, l.p1.clone(), line.p2.clone()))
+            } else if near(&l.p2, &line.p2) {
+                Some((i, l.p1.clone(), line.p1.clone()))
+            } else {
+                None
+            }
+        });
+        match found {
+            Some((i, p1, p2)) => {
+                let cells = lines.remove(i).cells;
+                line = VoronoiLine::new(p1, p2);
+                line.cells = cells;
+            }
+            None => {
+                lines.push(line);
+                return;
+            }
+        }
+    }
+}
+
+fn relabel_lines(lines: &mut [VoronoiLine], from: CellId, to: CellId) {
+    for line in lines.iter_mut() {
+        if line.cells.remove(&from) {
+            line.cells.insert(to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::voronoi_diagram::trace::CollectingTraceSink;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    fn rect(x: f64, y: f64, w: f64, h: f64) -> Rect {
+        Rect {
+            pos: Vec2d { x, y },
+            size: Vec2d { x: w, y: h },
+        }
+    }
+
+    fn center_point(node_id: NodeId, x: f64, y: f64) -> VoronoiCenterPoint {
+        VoronoiCenterPoint {
+            node_id,
+            pos: Vec2d { x, y },
+            cluster_id: 0,
+        }
+    }
+
+    // セルが外周をすき間なく覆い、各セルが自分の中心を含むこと
+    fn assert_valid(diagram: &VoronoiDiagram) {
+        let bounds = diagram.outbound.polygon.bounding_rect();
+        let total: f64 = diagram
+            .cells
+            .iter()
+            .map(|c| c.borrow().bound.polygon.area())
+            .sum();
+        let area = bounds.size.x * bounds.size.y;
+        assert!(
+            (total - area).abs() <= area * 1e-6,
+            "total {} != {}",
+            total,
+            area
+        );
+        for cell in diagram.cells.iter() {
+            let cell = cell.borrow();
+            assert!(cell.bound.polygon.contains(cell.point.pos));
+        }
+    }
+
+    #[test]
+    fn random_points_in_large_bounds() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut diagram = VoronoiDiagram::with_bounds(rect(0., 0., 100., 100.));
+        for node_id in 0..200 {
+            let (x, y) = (rng.gen_range(0., 100.), rng.gen_range(0., 100.));
+            diagram.add_point(&center_point(node_id, x, y)).unwrap();
+        }
+        assert_eq!(200, diagram.cells.len());
+        assert_valid(&diagram);
+    }
+
+    #[test]
+    fn same_seed_same_diagram() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let points: Vec<VoronoiCenterPoint> = (0..300)
+            .map(|node_id| center_point(node_id, rng.gen(), rng.gen()))
+            .collect();
+        let dots: Vec<String> = (0..5)
+            .map(|_| {
+                let mut diagram = VoronoiDiagram::new();
+                diagram.calculate_diagram(&mut points.clone(), 7).unwrap();
+                diagram.to_dot()
+            })
+            .collect();
+        for dot in dots.iter().skip(1) {
+            assert_eq!(&dots[0], dot);
+        }
+    }
+
+    // NodeId ごとのセルの面積と、隣接する点の NodeId
+    fn cell_summary(diagram: &VoronoiDiagram) -> BTreeMap<NodeId, (f64, BTreeSet<NodeId>)> {
+        let node_id = |cell_id: CellId| diagram.get_cell(cell_id).borrow().point.node_id;
+        let mut summary: BTreeMap<NodeId, (f64, BTreeSet<NodeId>)> = diagram
+            .cells
+            .iter()
+            .map(|c| {
+                let cell = c.borrow();
+                let area = cell.bound.polygon.area();
+                (cell.point.node_id, (area, BTreeSet::new()))
+            })
+            .collect();
+        // 長さが 0 の辺は、作り方によって残ったり残らなかったりする
+        for edge in diagram.adjacency_edges().iter().filter(|e| e.length > 1e-9) {
+            let (node1, node2) = (node_id(edge.cell1), node_id(edge.cell2));
+            summary.get_mut(&node1).unwrap().1.insert(node2);
+            summary.get_mut(&node2).unwrap().1.insert(node1);
+        }
+        summary
+    }
+
+    fn assert_same_cells(expected: &VoronoiDiagram, actual: &VoronoiDiagram) {
+        let (expected, actual) = (cell_summary(expected), cell_summary(actual));
+        assert_eq!(
+            expected.keys().collect::<Vec<_>>(),
+            actual.keys().collect::<Vec<_>>()
+        );
+        for (node_id, (area, neighbors)) in expected.iter() {
+            let (actual_area, actual_neighbors) = &actual[node_id];
+            assert!((area - actual_area).abs() < 1e-9, "node {}", node_id);
+            assert_eq!(neighbors, actual_neighbors, "node {}", node_id);
+        }
+    }
+
+    // CellId と CellKey, NodeId の対応が合っていること
+    fn assert_keys(diagram: &VoronoiDiagram) {
+        for (cell_id, cell) in diagram.cells.iter().enumerate() {
+            let cell = cell.borrow();
+            assert_eq!(cell_id as CellId, cell.cell_id);
+            let by_node = diagram.get_cell_by_node_id(cell.point.node_id).unwrap();
+            assert_eq!(cell.cell_id, by_node.borrow().cell_id);
+        }
+    }
+
+    #[test]
+    fn move_point_incremental_matches_rebuild() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut incremental = VoronoiDiagram::new();
+        let mut rebuilt = VoronoiDiagram::new();
+        for node_id in 0..50 {
+            let p = center_point(node_id, rng.gen(), rng.gen());
+            incremental.add_point(&p).unwrap();
+            rebuilt.add_point(&p).unwrap();
+        }
+        for _ in 0..200 {
+            let node_id = rng.gen_range(0, 50);
+            let pos = Vec2d {
+                x: rng.gen(),
+                y: rng.gen(),
+            };
+            incremental.move_point_incremental(node_id, pos).unwrap();
+            rebuilt.move_point(node_id, pos).unwrap();
+            assert_keys(&incremental);
+            assert_valid(&incremental);
+            assert_same_cells(&rebuilt, &incremental);
+        }
+    }
+
+    #[test]
+    fn move_point_incremental_errors_keep_diagram() {
+        let mut diagram = VoronoiDiagram::new();
+        for (node_id, (x, y)) in [(0.2, 0.2), (0.8, 0.3), (0.5, 0.9)].iter().enumerate() {
+            diagram
+                .add_point(&center_point(node_id as NodeId, *x, *y))
+                .unwrap();
+        }
+        let before = cell_summary(&diagram);
+        assert_eq!(
+            Err(VoronoiError::SamePosition),
+            diagram.move_point_incremental(0, Vec2d { x: 0.8, y: 0.3 })
+        );
+        assert_eq!(
+            Err(VoronoiError::NodeNotFound),
+            diagram.move_point_incremental(9, Vec2d { x: 0.5, y: 0.5 })
+        );
+        assert_eq!(
+            Err(VoronoiError::OutOfBounds),
+            diagram.move_point_incremental(1, Vec2d { x: 1.5, y: 0.5 })
+        );
+        assert_keys(&diagram);
+        assert_eq!(before.len(), cell_summary(&diagram).len());
+        for (node_id, (area, _)) in before.iter() {
+            assert!((area - cell_summary(&diagram)[node_id].0).abs() < 1e-12);
+        }
+        // 同じ位置への移動は何も変えない
+        diagram
+            .move_point_incremental(2, Vec2d { x: 0.5, y: 0.9 })
+            .unwrap();
+        assert_keys(&diagram);
+    }
+
+    #[test]
+    fn remove_cell_traces_merged_neighbors() {
+        let mut diagram = VoronoiDiagram::new();
+        for (node_id, (x, y)) in [(0.2, 0.2), (0.8, 0.3), (0.5, 0.9), (0.5, 0.5)]
+            .iter()
+            .enumerate()
+        {
+            diagram
+                .add_point(&center_point(node_id as NodeId, *x, *y))
+                .unwrap();
+        }
+        let sink = CollectingTraceSink::new();
+        let events = sink.events();
+        diagram.trace_sink = Some(Box::new(sink));
+        diagram
+            .move_point_incremental(3, Vec2d { x: 0.4, y: 0.4 })
+            .unwrap();
+        let events = events.borrow();
+        match &events[0] {
+            TraceEvent::RemoveCell { cell_id, .. } => assert_eq!(3, *cell_id),
+            _ => panic!("{}", events[0]),
+        }
+        let merged: Vec<CellId> = events
+            .iter()
+            .filter_map(|e| match e {
+                TraceEvent::CellMerged {
+                    cell_id,
+                    from_cell_id,
+                    ..
+                } => {
+                    assert_eq!(3, *from_cell_id);
+                    Some(*cell_id)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![0, 1, 2], merged);
+        match events.last() {
+            Some(TraceEvent::AddCell { cell_id, .. }) => assert_eq!(3, *cell_id),
+            _ => panic!("AddCell"),
+        }
+    }
+
+    #[test]
+    fn reject_out_of_bounds() {
+        let mut diagram = VoronoiDiagram::new();
+        diagram.add_point(&center_point(0, 0.5, 0.5)).unwrap();
+        assert_eq!(
+            Err(VoronoiError::OutOfBounds),
+            diagram.add_point(&center_point(1, 1.5, 0.5)).map(|_| ())
+        );
+        assert_eq!(
+            Err(VoronoiError::OutOfBounds),
+            diagram.add_point(&center_point(2, 0., 0.)).map(|_| ())
+        );
+        assert_eq!(1, diagram.cells.len());
+        assert_valid(&diagram);
+    }
+
+    #[test]
+    fn clamp_to_corners() {
+        let mut diagram = VoronoiDiagram::new();
+        diagram.boundary_policy = BoundaryPolicy::Clamp;
+        diagram.add_point(&center_point(0, 0., 0.)).unwrap();
+        diagram.add_point(&center_point(1, 1., 1.)).unwrap();
+        diagram.add_point(&center_point(2, -3., 2.)).unwrap();
+        diagram.add_point(&center_point(3, 5., -1.)).unwrap();
+        diagram.add_point(&center_point(4, 0.5, 0.5)).unwrap();
+        assert_eq!(5, diagram.cells.len());
+        assert_valid(&diagram);
+        let pos = diagram.get_cell_by_node_id(1).unwrap().borrow().point.pos;
+        assert!(is_strictly_inside(&rect(0., 0., 1., 1.), pos));
+    }
+
+    #[test]
+    fn clamp_random_points_outside() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut diagram = VoronoiDiagram::with_bounds(rect(0., 0., 10., 10.));
+        diagram.boundary_policy = BoundaryPolicy::Clamp;
+        let mut added = 0;
+        for node_id in 0..100 {
+            let (x, y) = (rng.gen_range(-5., 15.), rng.gen_range(-5., 15.));
+            // 同じ角に寄せた点は SamePosition になる
+            match diagram.add_point(&center_point(node_id, x, y)) {
+                Ok(_) => added += 1,
+                Err(e) => assert_eq!(VoronoiError::SamePosition, e),
+            }
+        }
+        assert_eq!(added, diagram.cells.len());
+        assert_valid(&diagram);
+    }
+
+    #[test]
+    fn expand_to_far_point() {
+        let mut diagram = VoronoiDiagram::new();
+        diagram.boundary_policy = BoundaryPolicy::Expand;
+        diagram.add_point(&center_point(0, 0.5, 0.5)).unwrap();
+        diagram.add_point(&center_point(1, 10., 0.5)).unwrap();
+        diagram.add_point(&center_point(2, 0.2, 0.7)).unwrap();
+        diagram.add_point(&center_point(3, -4., 8.)).unwrap();
+        assert_eq!(4, diagram.cells.len());
+        assert_valid(&diagram);
+        let bounds = diagram.outbound.polygon.bounding_rect();
+        assert!(is_strictly_inside(&bounds, Vec2d { x: 10., y: 0.5 }));
+        assert!(is_strictly_inside(&bounds, Vec2d { x: -4., y: 8. }));
+    }
+
+    // 外周を広げた後で追加に失敗したら、外周も元に戻す
+    #[test]
+    fn failed_expand_keeps_bounds() {
+        let mut diagram = VoronoiDiagram::new();
+        diagram.boundary_policy = BoundaryPolicy::Expand;
+        for (node_id, (x, y)) in [(0.2, 0.2), (0.8, 0.3), (0.5, 0.9)].iter().enumerate() {
+            diagram
+                .add_point(&center_point(node_id as NodeId, *x, *y))
+                .unwrap();
+        }
+        let key = diagram.key_of_node(1).unwrap();
+        let before = cell_summary(&diagram);
+        // x は外周を広げるが、y が NaN なので入るセルが見つからない
+        assert_eq!(
+            Err(VoronoiError::CellNotFound),
+            diagram
+                .add_point(&center_point(3, 5., f64::NAN))
+                .map(|_| ())
+        );
+        let bounds = diagram.outbound.polygon.bounding_rect();
+        assert_eq!(
+            (0., 0., 1., 1.),
+            (bounds.pos.x, bounds.pos.y, bounds.size.x, bounds.size.y)
+        );
+        assert_valid(&diagram);
+        assert_keys(&diagram);
+        assert_eq!(Some(key), diagram.key_of_node(1));
+        assert_eq!(None, diagram.key_of_node(3));
+        for (node_id, (area, _)) in before.iter() {
+            assert!((area - cell_summary(&diagram)[node_id].0).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::export::PolygonBuffers;
+use crate::geometry::voronoi_diagram::types::{
+    BoundaryPolicy, ClusterId, NodeId, VoronoiCenterPoint, VoronoiError,
+};
+use crate::geometry::voronoi_diagram::voronoi_diagram::{VoronoiCellRef, VoronoiDiagram};
+use std::collections::HashSet;
+use wasm_bindgen::prelude::*;
+
+// デモの画面(MyApp)を使わずに、JS から VoronoiDiagram を直接使うためのクラス。
+// 点は NodeId で指定する (CellId は点を削除・移動すると変わるので外には出さない)。
+// 座標の配列は [x0, y0, x1, y1, ...] の Float64Array。
+
+#[wasm_bindgen(js_name = VoronoiDiagram)]
+pub struct JsVoronoiDiagram {
+    diagram: VoronoiDiagram,
+}
+
+fn to_js_error(e: VoronoiError) -> JsValue {
+    js_sys::Error::new(&e.to_string()).into()
+}
+
+#[wasm_bindgen(js_class = VoronoiDiagram)]
+impl JsVoronoiDiagram {
+    /// 外周が (0, 0)-(1, 1) の図
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            diagram: VoronoiDiagram::new(),
+        }
+    }
+
+    pub fn with_bounds(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            diagram: VoronoiDiagram::with_bounds(Rect {
+                pos: Vec2d { x, y },
+                size: Vec2d {
+                    x: width,
+                    y: height,
+                },
+            }),
+        }
+    }
+
+    /// "reject" | "clamp" | "expand"
+    pub fn set_boundary_policy(&mut self, policy: &str) -> Result<(), JsValue> {
+        self.diagram.boundary_policy = match policy {
+            "reject" => BoundaryPolicy::Reject,
+            "clamp" => BoundaryPolicy::Clamp,
+            "expand" => BoundaryPolicy::Expand,
+            _ => {
+                return Err(js_sys::Error::new(&format!("unknown policy: {}", policy)).into());
+            }
+        };
+        Ok(())
+    }
+
+    pub fn add_point(
+        &mut self,
+        node_id: NodeId,
+        x: f64,
+        y: f64,
+        cluster_id: ClusterId,
+    ) -> Result<(), JsValue> {
+        let point = VoronoiCenterPoint {
+            node_id,
+            pos: Vec2d { x, y },
+            cluster_id,
+        };
+        self.diagram.add_point(&point).map_err(to_js_error)?;
+        Ok(())
+    }
+
+    pub fn remove_point(&mut self, node_id: NodeId) -> Result<(), JsValue> {
+        self.diagram.remove_point(node_id).map_err(to_js_error)
+    }
+
+    pub fn move_point(&mut self, node_id: NodeId, x: f64, y: f64) -> Result<(), JsValue> {
+        self.diagram
+            .move_point(node_id, Vec2d { x, y })
+            .map_err(to_js_error)
+    }
+
+    /// 全ての点を消す (外周と boundary_policy はそのまま)
+    pub fn clear(&mut self) -> Result<(), JsValue> {
+        let node_ids = self.node_ids();
+        self.diagram.remove_points(&node_ids).map_err(to_js_error)
+    }
+
+    pub fn size(&self) -> usize {
+        self.diagram.cells.len()
+    }
+
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        self.diagram.points().iter().map(|p| p.node_id).collect()
+    }
+
+    /// (x, y) を含むセルの NodeId。外周の外なら undefined
+    pub fn find_node_at(&self, x: f64, y: f64) -> Option<NodeId> {
+        self.diagram
+            .find_cell_at(Vec2d { x, y })
+            .map(|c| c.borrow().point.node_id)
+    }
+
+    /// 中心点の [x, y]
+    pub fn site(&self, node_id: NodeId) -> Result<Vec<f64>, JsValue> {
+        let cell = self.cell(node_id)?.borrow();
+        Ok(vec![cell.point.pos.x, cell.point.pos.y])
+    }
+
+    pub fn cluster_id(&self, node_id: NodeId) -> Result<ClusterId, JsValue> {
+        Ok(self.cell(node_id)?.borrow().point.cluster_id)
+    }
+
+    /// セルの多角形の頂点 [x0, y0, x1, y1, ...]
+    pub fn polygon(&self, node_id: NodeId) -> Result<Vec<f64>, JsValue> {
+        let cell = self.cell(node_id)?.borrow();
+        Ok(cell
+            .bound
+            .polygon
+            .points
+            .iter()
+            .flat_map(|p| vec![p.x, p.y])
+            .collect())
+    }
+
+    pub fn area(&self, node_id: NodeId) -> Result<f64, JsValue> {
+        Ok(self.cell(node_id)?.borrow().bound.polygon.area())
+    }
+
+    /// 隣接するセルの NodeId
+    pub fn neighbors(&self, node_id: NodeId) -> Result<Vec<NodeId>, JsValue> {
+        let neighbor_cells = self.cell(node_id)?.borrow().get_neighbor_cells();
+        let mut ret: Vec<NodeId> = neighbor_cells
+            .iter()
+            .map(|c| self.diagram.get_cell(*c).borrow().point.node_id)
+            .filter(|n| *n != node_id)
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    /// 矩形と重なるセルの NodeId
+    pub fn nodes_in_rect(&self, x: f64, y: f64, width: f64, height: f64) -> Vec<NodeId> {
+        let rect = Rect {
+            pos: Vec2d { x, y },
+            size: Vec2d {
+                x: width,
+                y: height,
+            },
+        };
+        self.diagram
+            .cells_in_rect(&rect)
+            .iter()
+            .map(|c| self.diagram.get_cell(*c).borrow().point.node_id)
+            .collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.diagram.to_dot()
+    }
+
+    pub fn to_graphml(&self) -> String {
+        self.diagram.to_graphml()
+    }
+
+    /// 中心点の座標 [x0, y0, x1, y1, ...] と NodeId をまとめて追加する。
+    /// 途中で失敗したら、この呼び出しで追加した点は全部取り消す
+    pub fn add_sites(&mut self, coords: &[f64], node_ids: &[NodeId]) -> Result<(), JsValue> {
+        if coords.len() != node_ids.len() * 2 {
+            return Err(js_sys::Error::new("coords.length must be node_ids.length * 2").into());
+        }
+        let mut seen: HashSet<NodeId> = HashSet::new();
+        for node_id in node_ids.iter() {
+            if !seen.insert(*node_id) || self.diagram.key_of_node(*node_id).is_some() {
+                return Err(to_js_error(VoronoiError::DuplicatedNodeId));
+            }
+        }
+        let mut points: Vec<VoronoiCenterPoint> = node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, node_id)| VoronoiCenterPoint {
+                node_id: *node_id,
+                pos: Vec2d {
+                    x: coords[i * 2],
+                    y: coords[i * 2 + 1],
+                },
+                cluster_id: *node_id as ClusterId,
+            })
+            .collect();
+        let bounds = self.diagram.outbound.polygon.bounding_rect();
+        if let Err(e) = self.diagram.calculate_diagram(&mut points, 0) {
+            let added: Vec<NodeId> = node_ids
+                .iter()
+                .cloned()
+                .filter(|n| self.diagram.key_of_node(*n).is_some())
+                .collect();
+            self.diagram.remove_points(&added).map_err(to_js_error)?;
+            // Expand で広げた外周も戻す
+            if self.diagram.outbound.polygon.bounding_rect() != bounds {
+                self.diagram
+                    .rebuild_with_bounds(bounds)
+                    .map_err(to_js_error)?;
+            }
+            return Err(to_js_error(e));
+        }
+        Ok(())
+    }
+
+    /// 全セルの多角形を型付き配列にまとめて返す
+    pub fn polygon_buffers(&self) -> JsPolygonBuffers {
+        JsPolygonBuffers(self.diagram.polygon_buffers())
+    }
+
+    fn cell(&self, node_id: NodeId) -> Result<&VoronoiCellRef, JsValue> {
+        self.diagram
+            .get_cell_by_node_id(node_id)
+            .ok_or_else(|| to_js_error(VoronoiError::NodeNotFound))
+    }
+}
+
+/// VoronoiDiagram.polygon_buffers() の結果。各メソッドは型付き配列のコピーを返す
+#[wasm_bindgen(js_name = PolygonBuffers)]
+pub struct JsPolygonBuffers(PolygonBuffers);
+
+#[wasm_bindgen(js_class = PolygonBuffers)]
+impl JsPolygonBuffers {
+    /// Float64Array [x0, y0, x1, y1, ...]
+    pub fn coords(&self) -> Vec<f64> {
+        self.0.coords.clone()
+    }
+
+    /// Uint32Array。i番目のセルの頂点は offsets[i] から offsets[i + 1] の手前まで
+    pub fn offsets(&self) -> Vec<u32> {
+        self.0.offsets.clone()
+    }
+
+    pub fn cell_ids(&self) -> Vec<u32> {
+        self.0.cell_ids.clone()
+    }
+
+    pub fn node_ids(&self) -> Vec<u32> {
+        self.0.node_ids.clone()
+    }
+
+    /// Uint32Array。頂点の番号を3つずつ並べた三角形 (gl.drawElements 用)
+    pub fn triangles(&self) -> Vec<u32> {
+        self.0.triangles.clone()
+    }
+}
@@ -0,0 +1,87 @@
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{NodeId, VoronoiError};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use core::f64::consts::PI;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+// 外周からどれだけ内側で跳ね返るか(外周の大きさに対する比率)
+const BOUNCE_MARGIN: f64 = 1e-3;
+// 速度の大きさの範囲 (speed に対する比率)
+const MIN_VELOCITY: f64 = 0.2;
+const MAX_VELOCITY: f64 = 1.;
+
+/// 点に速度を持たせて動かす。速度の無い点には乱数で速度を与え、外周で跳ね返る。
+pub struct Kinetic {
+    pub speed: f64,                     // 1秒あたりの移動量(外周の大きさに対する比率)
+    velocities: HashMap<NodeId, Vec2d>, // speed に対する比率
+    rng: StdRng,
+}
+
+impl Kinetic {
+    pub fn new(speed: f64, seed: u64) -> Self {
+        Self {
+            speed,
+            velocities: HashMap::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// 全ての点を dt 秒だけ進める。セルは作り直さずに1点ずつ更新する。
+    pub fn step(&mut self, diagram: &mut VoronoiDiagram, dt: f64) -> Result<(), VoronoiError> {
+        let bounds = diagram.outbound.polygon.bounding_rect();
+        let scale = bounds.size.x.max(bounds.size.y);
+        let margin = scale * BOUNCE_MARGIN;
+        let min = bounds.pos
+            + Vec2d {
+                x: margin,
+                y: margin,
+            };
+        let max = bounds.pos + bounds.size
+            - Vec2d {
+                x: margin,
+                y: margin,
+            };
+        let distance = self.speed * scale * dt;
+
+        let points = diagram.points();
+        // 削除された点の速度は捨てる
+        let node_ids: HashSet<NodeId> = points.iter().map(|p| p.node_id).collect();
+        self.velocities
+            .retain(|node_id, _| node_ids.contains(node_id));
+
+        for point in points.iter() {
+            let rng = &mut self.rng;
+            let v = self.velocities.entry(point.node_id).or_insert_with(|| {
+                let arg = rng.gen_range(0., 2. * PI);
+                let r = rng.gen_range(MIN_VELOCITY, MAX_VELOCITY);
+                Vec2d {
+                    x: r * arg.cos(),
+                    y: r * arg.sin(),
+                }
+            });
+            let mut pos = point.pos + v.mul(distance);
+            bounce(&mut pos.x, &mut v.x, min.x, max.x);
+            bounce(&mut pos.y, &mut v.y, min.y, max.y);
+            match diagram.move_point_incremental(point.node_id, pos) {
+                Ok(()) => {}
+                // 他の点と重なるときは、今回は動かさない
+                Err(VoronoiError::SamePosition) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// [min, max] からはみ出したら折り返して、速度の向きを変える
+fn bounce(p: &mut f64, v: &mut f64, min: f64, max: f64) {
+    if *p < min {
+        *p = (2. * min - *p).min(max);
+        *v = v.abs();
+    } else if *p > max {
+        *p = (2. * max - *p).max(min);
+        *v = -v.abs();
+    }
+}
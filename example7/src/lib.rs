@@ -1,10 +1,50 @@
+mod app;
+mod coloring;
+#[path = "../../shared/dom_util.rs"]
+mod dom_util;
 mod geometry;
+#[path = "../../shared/input.rs"]
+mod input;
+mod js_api;
+mod kinetic;
+mod map_generator;
+mod recorder;
+mod session;
+mod site_loader;
+mod stippling;
+mod storage;
+mod viewport;
 
+use crate::app::coloring::draw_legend;
+use crate::app::kinetic::Animation;
+use crate::app::loader::Loading;
+use crate::app::pointer::PointerState;
+use crate::app::recorder::Recording;
+use crate::app::resize::{observe_resize, CanvasSize};
+use crate::app::sessions::{open_storage, Sessions};
+use crate::app::steps::Steps;
+use crate::app::viewport::Pan;
+use crate::dom_util::{context2d, document, get_element_by_id, window};
+use crate::geometry::line::Line;
 use crate::geometry::vec2d::Vec2d;
-use crate::geometry::voronoi_diagram::types::{ClusterId, NodeId, VoronoiCenterPoint};
+use crate::geometry::voronoi_diagram::history::{EditOp, History};
+use crate::geometry::voronoi_diagram::medial_axis::MedialAxisEdge;
+use crate::geometry::voronoi_diagram::trace::ConsoleTraceSink;
+use crate::geometry::voronoi_diagram::types::{
+    BoundaryPolicy, CellId, ClusterId, NodeId, VoronoiCenterPoint, VoronoiError,
+};
 use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use crate::map_generator::VoronoiMap;
+use crate::stippling::Stipple;
+use crate::storage::Storage;
+use crate::viewport::Viewport;
 use core::f64::consts::PI;
 use js_sys::Math::random;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
@@ -15,7 +55,7 @@ macro_rules! log {
         web_sys::console::log_1(&format!( $( $t )* ).into());
     }
 }
-const SIZE: f64 = 698.;
+const INITIAL_PADDING: f64 = 1.; // 最初は外周全体をこの余白(px)で表示する
 
 static mut MY_APP: Option<Box<MyApp>> = None;
 
@@ -40,142 +80,484 @@ pub fn start() {
 
     my_app_mut().setup();
 
-    // setup onClick canvas
-    {
-        let c = Closure::wrap(Box::new(move |e| {
-            my_app_mut().on_click(e);
-        }) as Box<dyn FnMut(JsValue)>);
-        let elem: web_sys::HtmlCanvasElement = get_element_by_id("canvas");
-        elem.add_event_listener_with_callback("click", c.as_ref().unchecked_ref())
-            .unwrap();
-        c.forget(); // c を Rustのメモリ管理から外して JSのGCにわたす
-    }
+    add_event_listener("canvas", "pointerdown", MyApp::on_pointer_down);
+    add_event_listener("canvas", "pointermove", MyApp::on_pointer_move);
+    add_event_listener("canvas", "pointerup", MyApp::on_pointer_up);
+    add_event_listener("canvas", "pointercancel", MyApp::on_pointer_cancel);
+    add_event_listener("canvas", "pointerleave", MyApp::on_pointer_leave);
+    add_event_listener("canvas", "contextmenu", MyApp::on_context_menu);
+    add_event_listener("canvas", "wheel", MyApp::on_wheel);
+    add_event_listener("fit", "click", MyApp::on_fit);
+    add_event_listener("clear", "click", MyApp::on_clear);
+    add_event_listener("undo", "click", MyApp::on_undo);
+    add_event_listener("redo", "click", MyApp::on_redo);
+    listen(&document(), "document", "keydown", MyApp::on_key_down);
+    add_event_listener("add", "click", MyApp::on_add_points);
+    add_event_listener("generate_map", "click", MyApp::on_generate_map);
+    add_event_listener("stipple", "click", MyApp::on_stipple);
+    add_event_listener("step_mode", "change", MyApp::on_step_mode);
+    add_event_listener("export_dot", "click", MyApp::on_export_dot);
+    add_event_listener("export_graphml", "click", MyApp::on_export_graphml);
+    add_event_listener("trace", "change", MyApp::on_trace);
+    add_event_listener("outline", "change", MyApp::on_outline);
+    add_event_listener("color_by", "change", MyApp::on_outline);
+    add_event_listener("palette", "change", MyApp::on_outline);
+    add_event_listener("labels", "change", MyApp::on_outline);
+    add_event_listener("alpha", "change", MyApp::on_outline);
+    add_event_listener("skeleton", "click", MyApp::on_skeleton);
+    add_event_listener("prev_step", "click", MyApp::on_prev_step);
+    add_event_listener("next_step", "click", MyApp::on_next_step);
+    add_event_listener("animate", "click", MyApp::on_animate);
+    add_event_listener("step_frame", "click", MyApp::on_step_frame);
+    add_event_listener("speed", "change", MyApp::on_speed);
+    add_event_listener("load_sites", "click", MyApp::on_load_sites);
+    add_event_listener("save_snapshot", "click", MyApp::on_save_snapshot);
+    add_event_listener("load_snapshot", "click", MyApp::on_load_snapshot);
+    add_event_listener("delete_snapshot", "click", MyApp::on_delete_snapshot);
+    add_event_listener("record", "click", MyApp::on_record);
+    add_event_listener("replay", "click", MyApp::on_replay);
+    observe_resize("canvas", MyApp::on_resize);
 
-    // setup onClick add
-    {
-        let c = Closure::wrap(Box::new(move |e| {
-            my_app_mut().on_add_points(e);
-        }) as Box<dyn FnMut(JsValue)>);
-        let elem: web_sys::HtmlElement = get_element_by_id("add");
-        elem.add_event_listener_with_callback("click", c.as_ref().unchecked_ref())
-            .unwrap();
-        c.forget(); // c を Rustのメモリ管理から外して JSのGCにわたす
-    }
+    // フォームの値の変化は、要素のハンドラより先に(キャプチャで)記録する
+    let c = Closure::wrap(Box::new(move |e: JsValue| {
+        my_app_mut().record_control(&e);
+    }) as Box<dyn FnMut(JsValue)>);
+    document()
+        .add_event_listener_with_callback_and_bool("change", c.as_ref().unchecked_ref(), true)
+        .unwrap();
+    c.forget();
+
+    // requestAnimationFrame のループ。止めている間は何もしない
+    let closure_captured = Rc::new(RefCell::new(None));
+    let closure_cloned = Rc::clone(&closure_captured);
+    closure_cloned.replace(Some(Closure::wrap(Box::new(move |time: f64| {
+        my_app_mut().on_animation_frame(time);
+        request_animation_frame(closure_captured.borrow().as_ref().unwrap());
+    }) as Box<dyn FnMut(f64)>)));
+    request_animation_frame(closure_cloned.borrow().as_ref().unwrap());
+}
+
+// JS から呼ぶ元に戻す/やり直す。変化が無ければ false
+#[wasm_bindgen]
+pub fn undo() -> bool {
+    my_app_mut().undo()
+}
+
+#[wasm_bindgen]
+pub fn redo() -> bool {
+    my_app_mut().redo()
+}
+
+#[wasm_bindgen]
+pub fn can_undo() -> bool {
+    my_app().history.can_undo()
+}
+
+#[wasm_bindgen]
+pub fn can_redo() -> bool {
+    my_app().history.can_redo()
+}
+
+type Handler = fn(&mut MyApp, JsValue);
+
+// id の要素に event が起きたら MyApp の handler を呼ぶ
+fn add_event_listener(id: &str, event: &str, handler: Handler) {
+    let elem: web_sys::HtmlElement = get_element_by_id(id);
+    listen(&elem, id, event, handler);
 }
 
+// name は記録と再生で handler を探すときの名前
+fn listen(target: &web_sys::EventTarget, name: &str, event: &str, handler: Handler) {
+    let key = (name.to_string(), event.to_string());
+    my_app_mut().recording.add_handler(key.clone(), handler);
+    let c = Closure::wrap(Box::new(move |e| {
+        let app = my_app_mut();
+        app.record_event(&key.0, &key.1, &e);
+        handler(app, e);
+    }) as Box<dyn FnMut(JsValue)>);
+    target
+        .add_event_listener_with_callback(event, c.as_ref().unchecked_ref())
+        .unwrap();
+    c.forget(); // c を Rustのメモリ管理から外して JSのGCにわたす
+}
+
+const HISTORY_MAX_POINTS: usize = 100_000; // 履歴に持つ点の数の上限
+
 pub struct MyApp {
     diagram: VoronoiDiagram,
+    next_node_id: NodeId,
+    steps: Steps,
+    map: Option<VoronoiMap>, // 地図を生成したときだけ。点を追加すると消える
+    stipples: Option<Vec<Stipple>>, // Canvas の絵から作った点描。何か操作すると消える
+    skeleton: Option<Vec<MedialAxisEdge>>, // alpha shape の中心線。点を動かすと消える
+    history: History,
+    pointer: PointerState,
+    hover_cell: Option<CellId>,
+    viewport: Viewport,
+    canvas: CanvasSize,
+    pan: Option<Pan>,
+    cell_values: HashMap<NodeId, f64>, // 色分けに使う値
+    animation: Animation,
+    loading: Option<Loading>, // 読み込んだ点から図を作っている途中
+    sessions: Sessions,
+    rng: StdRng, // 点の追加などに使う乱数 (再生で同じ結果になるように種を記録する)
+    recording: Recording,
 }
 
 impl MyApp {
     pub fn new() -> MyApp {
+        MyApp::with_storage(open_storage(), (random() * 1e9) as u64)
+    }
+
+    // DOM を使わずに作る (ブラウザの外で試すときにも使う)
+    pub fn with_storage(storage: Box<dyn Storage>, seed: u64) -> MyApp {
+        let mut diagram = VoronoiDiagram::new();
+        // Canvasの端をクリックすると 0 や 1 ちょうどの位置になるので、内側に寄せる
+        diagram.boundary_policy = BoundaryPolicy::Clamp;
         MyApp {
-            diagram: VoronoiDiagram::new(),
+            diagram,
+            next_node_id: 0,
+            steps: Steps::default(),
+            map: None,
+            stipples: None,
+            skeleton: None,
+            history: History::new(HISTORY_MAX_POINTS),
+            pointer: PointerState::default(),
+            hover_cell: None,
+            viewport: Viewport::new(1., Vec2d { x: 0., y: 0. }),
+            canvas: CanvasSize::default(),
+            pan: None,
+            cell_values: HashMap::new(),
+            animation: Animation::new(0.05),
+            loading: None,
+            sessions: Sessions::new(storage),
+            rng: StdRng::seed_from_u64(seed),
+            recording: Recording::default(),
         }
     }
 
     pub fn setup(&mut self) {
+        self.resize_canvas();
+        let bounds = self.diagram.outbound.polygon.bounding_rect();
+        self.viewport
+            .fit(&bounds, self.canvas.size, INITIAL_PADDING);
+        self.restore_autosave();
         self.draw();
     }
 
-    pub fn on_click(&mut self, _event: JsValue) {
-        let cx = get_nested_property(&_event, vec!["offsetX"])
-            .unwrap()
-            .as_f64()
-            .unwrap();
-        let cy = get_nested_property(&_event, vec!["offsetY"])
-            .unwrap()
-            .as_f64()
-            .unwrap();
-        let x = (cx - 1.) / SIZE;
-        let y = (cy - 1.) / SIZE;
-        let id = self.diagram.cells.len();
-        let vp = VoronoiCenterPoint {
-            pos: Vec2d { x, y },
-            node_id: id as NodeId,
-            cluster_id: id as ClusterId,
+    // c: 全部消す, z (Ctrl/Cmd+z も): 元に戻す, Shift+z か y: やり直す, f: 全体表示
+    pub fn on_key_down(&mut self, e: JsValue) {
+        let tag = get_nested_property(&e, vec!["target", "tagName"]).and_then(|t| t.as_string());
+        if tag == Some(String::from("INPUT")) || tag == Some(String::from("TEXTAREA")) {
+            return;
+        }
+        match get_nested_property(&e, vec!["key"])
+            .and_then(|k| k.as_string())
+            .as_deref()
+        {
+            Some("c") if !has_modifier(&e) => self.on_clear(e),
+            Some("z") => self.on_undo(e),
+            Some("Z") | Some("y") => self.on_redo(e),
+            Some("f") if !has_modifier(&e) => self.on_fit(e),
+            _ => {}
+        }
+    }
+
+    pub fn on_clear(&mut self, _e: JsValue) {
+        if self.diagram.cells.is_empty() {
+            return;
+        }
+        let op = EditOp::clear(&self.diagram);
+        self.edit(op);
+    }
+
+    pub fn on_undo(&mut self, _e: JsValue) {
+        self.undo();
+    }
+
+    pub fn on_redo(&mut self, _e: JsValue) {
+        self.redo();
+    }
+
+    /// 元に戻す編集が無ければ false
+    pub fn undo(&mut self) -> bool {
+        let result = self.history.undo(&mut self.diagram);
+        self.after_history(result)
+    }
+
+    /// やり直す編集が無ければ false
+    pub fn redo(&mut self) -> bool {
+        let result = self.history.redo(&mut self.diagram);
+        self.after_history(result)
+    }
+
+    fn after_history(&mut self, result: Result<bool, VoronoiError>) -> bool {
+        let changed = match result {
+            Ok(changed) => changed,
+            Err(e) => {
+                log!("history: {}", e);
+                false
+            }
+        };
+        if changed {
+            self.clear_layers();
+            self.hover_cell = None;
+            self.draw();
+        }
+        changed
+    }
+
+    // pos を含むセルの点を消す。消したら true (描き直すのは呼んだ側)
+    fn delete_at(&mut self, pos: Vec2d) -> bool {
+        let point = match self.diagram.find_cell_at(pos) {
+            Some(cell) => cell.borrow().point.clone(),
+            None => return false,
         };
-        self.diagram.add_point(&vp);
+        self.apply_edit(EditOp::Remove(vec![point]));
+        true
+    }
+
+    // op を履歴に積みながら適用して描き直す
+    fn edit(&mut self, op: EditOp) {
+        self.apply_edit(op);
+        self.draw();
+    }
+
+    fn apply_edit(&mut self, op: EditOp) {
+        self.clear_layers();
+        if let Err(e) = self.history.apply(&mut self.diagram, op) {
+            log!("edit: {}", e);
+        }
+        self.hover_cell = None;
+    }
+
+    // 別に作った図と入れ替えて、元に戻せるように履歴を積む
+    fn replace_diagram(&mut self, mut diagram: VoronoiDiagram) {
+        self.history
+            .record(EditOp::replace_all(&self.diagram, diagram.points()));
+        self.clear_layers();
+        self.hover_cell = None;
+        diagram.boundary_policy = BoundaryPolicy::Clamp;
+        diagram.trace_sink = self.diagram.trace_sink.take();
+        self.diagram = diagram;
+    }
+
+    // 点が変わると地図や点描は合わなくなるので消す
+    fn clear_layers(&mut self) {
+        self.map = None;
+        self.stipples = None;
+        self.skeleton = None;
+        self.steps.clear();
+    }
+
+    pub fn on_animation_frame(&mut self, time: f64) {
+        self.autosave();
+        if self.loading.is_some() {
+            self.build_sites();
+        }
+        self.animate_frame(time);
+    }
+
+    pub fn on_export_dot(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlTextAreaElement = get_element_by_id("export");
+        elem.set_value(&self.diagram.to_dot());
+    }
+
+    pub fn on_export_graphml(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlTextAreaElement = get_element_by_id("export");
+        elem.set_value(&self.diagram.to_graphml());
+    }
+
+    pub fn on_trace(&mut self, _e: JsValue) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("trace");
+        self.diagram.trace_sink = if elem.checked() {
+            Some(Box::new(ConsoleTraceSink))
+        } else {
+            None
+        };
+    }
+
+    // 表示の設定が変わったら描き直す
+    pub fn on_outline(&mut self, _e: JsValue) {
         self.draw();
     }
 
     pub fn on_add_points(&mut self, _e: JsValue) {
+        self.clear_layers();
+        let mut points: Vec<VoronoiCenterPoint> = Vec::new();
         for _idx in 0..100 {
-            let x = random() * 0.8 + 0.1;
-            let y = random() * 0.8 + 0.1;
-            let id = self.diagram.cells.len();
-            let vp = VoronoiCenterPoint {
-                pos: Vec2d { x, y },
-                node_id: id as NodeId,
-                cluster_id: id as ClusterId,
-            };
-            self.diagram.add_point(&vp);
+            let x = self.rng.gen::<f64>() * 0.8 + 0.1;
+            let y = self.rng.gen::<f64>() * 0.8 + 0.1;
+            let vp = self.new_center_point(Vec2d { x, y });
+            match self.diagram.add_point(&vp) {
+                Ok(_) => points.push(vp),
+                Err(e) => {
+                    log!("add_point: {}", e);
+                }
+            }
             self.draw();
         }
+        // 1つも追加できなければ、戻しても何も変わらない履歴になるので積まない
+        if !points.is_empty() {
+            self.history.record(EditOp::Add(points));
+        }
+    }
+
+    // NodeId はセルの数ではなく専用のカウンタから振る(削除しても同じIDを使い回さない)
+    fn new_center_point(&mut self, pos: Vec2d) -> VoronoiCenterPoint {
+        let node_id = self.next_node_id;
+        self.next_node_id += 1;
+        VoronoiCenterPoint {
+            pos,
+            node_id,
+            cluster_id: node_id as ClusterId,
+        }
+    }
+
+    fn diagram_lines(&self) -> Vec<Line> {
+        let mut lines: Vec<Line> = Vec::new();
+        for cell in self.diagram.cells.iter() {
+            for line in cell.borrow().lines.iter() {
+                lines.push(line.line.clone());
+            }
+        }
+        lines
     }
 
     pub fn draw(&mut self) {
-        let context = get_context2d_by_id(&String::from("canvas"));
+        let context = context2d("canvas");
+        if self.draw_stipples(&context) {
+            return;
+        }
+        // 描画バッファは pixel_ratio 倍なので、CSS px の座標で描けるように拡大しておく
+        context
+            .set_transform(
+                self.canvas.pixel_ratio,
+                0.,
+                0.,
+                self.canvas.pixel_ratio,
+                0.,
+                0.,
+            )
+            .unwrap();
         context.set_fill_style(&JsValue::from(format!("rgb(0, 0, 0, 1)")));
-        context.fill_rect(0., 0., SIZE + 2., SIZE + 2.);
+        context.fill_rect(0., 0., self.canvas.size.x, self.canvas.size.y);
         context.fill();
 
+        // 外周
+        context.set_stroke_style(&JsValue::from("rgb(128, 128, 128, 1)"));
+        draw_lines(
+            &context,
+            &self.viewport,
+            &self.diagram.outbound.polygon.lines,
+        );
+
+        self.draw_map(&context);
+        let legend = self.draw_cell_colors(&context);
+
+        if let Some(cell_id) = self.hover_cell {
+            let cell = self.diagram.get_cell(cell_id).borrow();
+            context.set_fill_style(&JsValue::from("rgba(255, 255, 255, 0.3)"));
+            fill_polygon(&context, &self.viewport, &cell.bound.polygon.points);
+        }
+
         context.set_fill_style(&JsValue::from(format!("rgb(255, 0, 0, 1)")));
         context.set_stroke_style(&JsValue::from(format!("rgb(0, 255, 0, 1)")));
 
-        context.begin_path();
-        for cell in self.diagram.cells.iter() {
-            for line in cell.borrow().lines.iter() {
-                context.move_to(line.line.p1.x * SIZE + 1., line.line.p1.y * SIZE + 1.);
-                context.line_to(line.line.p2.x * SIZE + 1., line.line.p2.y * SIZE + 1.)
-            }
-        }
-        context.stroke();
+        draw_lines(&context, &self.viewport, &self.step_lines());
 
         for _cell in self.diagram.cells.iter() {
             let cell = _cell.borrow();
-            context.begin_path();
-            context
-                .arc(
-                    cell.point.pos.x * SIZE + 1.,
-                    cell.point.pos.y * SIZE + 1.,
-                    2.,
-                    0.,
-                    PI * 2.,
-                )
-                .unwrap();
-            context.fill();
+            draw_point(&context, &self.viewport, cell.point.pos, 2.);
+        }
+
+        self.draw_labels(&context);
+        self.draw_outline(&context);
+        self.draw_skeleton(&context);
+        self.draw_step(&context);
+        if let Some(legend) = legend {
+            draw_legend(&context, &legend, self.canvas.size.x);
         }
     }
+
+    // 凸包と alpha shape
+    fn draw_outline(&self, context: &web_sys::CanvasRenderingContext2d) {
+        let elem: web_sys::HtmlInputElement = get_element_by_id("outline");
+        if !elem.checked() {
+            return;
+        }
+        let elem: web_sys::HtmlInputElement = get_element_by_id("alpha");
+        let alpha = elem.value().parse::<f64>().unwrap_or(0.05);
+
+        context.set_stroke_style(&JsValue::from("rgb(255, 255, 255, 1)"));
+        draw_lines(context, &self.viewport, &self.diagram.convex_hull().lines);
+        context.set_line_width(2.);
+        context.set_stroke_style(&JsValue::from("rgb(255, 255, 0, 1)"));
+        match self.diagram.alpha_shape(alpha) {
+            Ok(polygons) => {
+                for polygon in polygons.iter() {
+                    draw_lines(context, &self.viewport, &polygon.lines);
+                }
+            }
+            Err(e) => {
+                log!("alpha_shape: {}", e);
+            }
+        }
+        context.set_line_width(1.);
+    }
 }
 
-fn window() -> web_sys::Window {
-    web_sys::window().expect("no global `window` exists")
+fn has_modifier(e: &JsValue) -> bool {
+    ["shiftKey", "ctrlKey", "metaKey"]
+        .iter()
+        .any(|name| get_nested_property(e, vec![name]).and_then(|v| v.as_bool()) == Some(true))
 }
 
-pub fn document() -> web_sys::Document {
-    window()
-        .document()
-        .expect("should have a document on window")
+fn draw_point(
+    context: &web_sys::CanvasRenderingContext2d,
+    viewport: &Viewport,
+    pos: Vec2d,
+    radius: f64,
+) {
+    let p = viewport.to_screen(pos);
+    context.begin_path();
+    context.arc(p.x, p.y, radius, 0., PI * 2.).unwrap();
+    context.fill();
 }
 
-pub fn get_context2d_by_id(canvas_id: &String) -> web_sys::CanvasRenderingContext2d {
-    let canvas: web_sys::HtmlCanvasElement = get_element_by_id(canvas_id.as_str());
-    canvas
-        .get_context("2d")
-        .unwrap()
-        .unwrap()
-        .dyn_into::<web_sys::CanvasRenderingContext2d>()
-        .unwrap()
+fn draw_lines(context: &web_sys::CanvasRenderingContext2d, viewport: &Viewport, lines: &[Line]) {
+    context.begin_path();
+    for line in lines.iter() {
+        let (p1, p2) = (viewport.to_screen(line.p1), viewport.to_screen(line.p2));
+        context.move_to(p1.x, p1.y);
+        context.line_to(p2.x, p2.y)
+    }
+    context.stroke();
 }
 
-pub fn get_element_by_id<T: JsCast>(id: impl AsRef<str>) -> T {
-    document()
-        .get_element_by_id(id.as_ref())
-        .expect(&format!("not found: {}", id.as_ref()))
-        .dyn_into::<T>()
-        .map_err(|_| ())
-        .unwrap()
+fn fill_polygon(
+    context: &web_sys::CanvasRenderingContext2d,
+    viewport: &Viewport,
+    points: &[Vec2d],
+) {
+    if points.is_empty() {
+        return;
+    }
+    context.begin_path();
+    let p = viewport.to_screen(points[0]);
+    context.move_to(p.x, p.y);
+    for p in points.iter().skip(1) {
+        let p = viewport.to_screen(*p);
+        context.line_to(p.x, p.y);
+    }
+    context.close_path();
+    context.fill();
+}
+
+fn request_animation_frame(f: &Closure<dyn FnMut(f64)>) {
+    window()
+        .request_animation_frame(f.as_ref().unchecked_ref())
+        .expect("should register `requestAnimationFrame` OK");
 }
 
 pub fn get_nested_property(e: &JsValue, names: Vec<&str>) -> Option<JsValue> {
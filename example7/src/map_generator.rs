@@ -0,0 +1,482 @@
+use crate::geometry::line::Line;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{
+    BoundaryPolicy, CellId, ClusterId, NodeId, VoronoiCenterPoint, VoronoiError,
+};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// VoronoiDiagram のセルを土地の単位にした地図を作る
+// http://www-cs-students.stanford.edu/~amitp/game-programming/polygon-map-generation/
+
+pub struct MapConfig {
+    pub n_sites: usize,
+    pub relax_iterations: usize, // Lloyd緩和の回数
+    pub water_level: f64,        // 標高がこれより低いセルは水
+    pub n_rivers: usize,
+    pub seed: u64,
+}
+
+impl MapConfig {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            n_sites: 800,
+            relax_iterations: 2,
+            water_level: 0.4,
+            n_rivers: 20,
+            seed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Biome {
+    Ocean,
+    Lake,
+    Beach,
+    Desert,
+    Grassland,
+    Forest,
+    RainForest,
+    Tundra,
+    Snow,
+}
+
+impl Biome {
+    pub fn color(&self) -> &'static str {
+        match self {
+            Biome::Ocean => "rgb(54, 54, 97)",
+            Biome::Lake => "rgb(51, 102, 153)",
+            Biome::Beach => "rgb(160, 144, 119)",
+            Biome::Desert => "rgb(201, 210, 155)",
+            Biome::Grassland => "rgb(136, 170, 85)",
+            Biome::Forest => "rgb(103, 148, 89)",
+            Biome::RainForest => "rgb(68, 136, 85)",
+            Biome::Tundra => "rgb(187, 187, 170)",
+            Biome::Snow => "rgb(248, 248, 248)",
+        }
+    }
+}
+
+pub struct MapCell {
+    pub elevation: f64, // 0~1
+    pub moisture: f64,  // 0~1
+    pub is_water: bool,
+    pub is_ocean: bool,
+    pub biome: Biome,
+}
+
+/// セルの辺に沿って流れる川の1区間。flow はこの辺を通る川の数。
+pub struct RiverSegment {
+    pub line: Line,
+    pub flow: u32,
+}
+
+/// VoronoiDiagram の各セル(CellIdの順)の属性と川
+pub struct VoronoiMap {
+    pub cells: Vec<MapCell>,
+    pub rivers: Vec<RiverSegment>,
+}
+
+pub fn generate_map(config: &MapConfig) -> Result<(VoronoiDiagram, VoronoiMap), VoronoiError> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    // 点をばらまいて、Lloyd緩和でならす
+    let mut sites: Vec<Vec2d> = (0..config.n_sites)
+        .map(|_| Vec2d {
+            x: rng.gen_range(0.01, 0.99),
+            y: rng.gen_range(0.01, 0.99),
+        })
+        .collect();
+    let mut diagram = build_diagram(&sites, config.seed)?;
+    for _ in 0..config.relax_iterations {
+        for cell in diagram.cells.iter() {
+            let cell = cell.borrow();
+            sites[cell.point.node_id as usize] = cell.bound.polygon.centroid();
+        }
+        diagram = build_diagram(&sites, config.seed)?;
+    }
+
+    let mut cells = assign_elevation(&diagram, config);
+    assign_ocean(&diagram, &mut cells);
+    let corners = Corners::new(&diagram, &cells);
+    let rivers = corners.make_rivers(config, &mut rng);
+    assign_moisture(&diagram, &mut cells, &corners, &rivers);
+    for cell in cells.iter_mut() {
+        cell.biome = biome(cell, config.water_level);
+    }
+    // 海に接する低い土地は砂浜
+    for _cell in diagram.cells.iter() {
+        let cell = _cell.borrow();
+        let c = &cells[cell.cell_id as usize];
+        if c.is_water || c.elevation > config.water_level + 0.05 {
+            continue;
+        }
+        let near_ocean = neighbors(&diagram, cell.cell_id)
+            .iter()
+            .any(|n| cells[*n as usize].is_ocean);
+        if near_ocean {
+            cells[cell.cell_id as usize].biome = Biome::Beach;
+        }
+    }
+
+    Ok((diagram, VoronoiMap { cells, rivers }))
+}
+
+fn build_diagram(sites: &[Vec2d], seed: u64) -> Result<VoronoiDiagram, VoronoiError> {
+    let mut points: Vec<VoronoiCenterPoint> = sites
+        .iter()
+        .enumerate()
+        .map(|(i, pos)| VoronoiCenterPoint {
+            node_id: i as NodeId,
+            pos: *pos,
+            cluster_id: i as ClusterId,
+        })
+        .collect();
+    let mut diagram = VoronoiDiagram::new();
+    diagram.boundary_policy = BoundaryPolicy::Clamp;
+    diagram.calculate_diagram(&mut points, seed)?;
+    Ok(diagram)
+}
+
+fn neighbors(diagram: &VoronoiDiagram, cell_id: CellId) -> Vec<CellId> {
+    let mut ret: Vec<CellId> = diagram
+        .get_cell(cell_id)
+        .borrow()
+        .get_neighbor_cells()
+        .into_iter()
+        .filter(|c| *c != cell_id)
+        .collect();
+    ret.sort();
+    ret
+}
+
+// 標高 = ノイズ と 外周からの距離 を混ぜたもの (外周に近いほど低い)
+fn assign_elevation(diagram: &VoronoiDiagram, config: &MapConfig) -> Vec<MapCell> {
+    let bounds = diagram.outbound.polygon.bounding_rect();
+    let half = bounds.size.x.min(bounds.size.y) / 2.;
+    diagram
+        .cells
+        .iter()
+        .map(|cell| {
+            let pos = cell.borrow().point.pos;
+            let from_bound = (pos.x - bounds.pos.x)
+                .min(bounds.pos.x + bounds.size.x - pos.x)
+                .min(pos.y - bounds.pos.y)
+                .min(bounds.pos.y + bounds.size.y - pos.y);
+            let noise = fractal_noise(pos.mul(4.), config.seed);
+            let elevation = 0.5 * noise + 0.5 * (from_bound / half).max(0.).sqrt();
+            MapCell {
+                elevation,
+                moisture: 0.,
+                is_water: elevation < config.water_level,
+                is_ocean: false,
+                biome: Biome::Ocean,
+            }
+        })
+        .collect()
+}
+
+// 外周に接するセルは水にして、そこからつながっている水を海にする。残りの水は湖。
+fn assign_ocean(diagram: &VoronoiDiagram, cells: &mut [MapCell]) {
+    let mut queue: VecDeque<CellId> = VecDeque::new();
+    for _cell in diagram.cells.iter() {
+        let cell = _cell.borrow();
+        if cell.lines.iter().any(|l| l.is_outbound()) {
+            let c = &mut cells[cell.cell_id as usize];
+            c.is_water = true;
+            c.is_ocean = true;
+            queue.push_back(cell.cell_id);
+        }
+    }
+    while let Some(cell_id) = queue.pop_front() {
+        for n in neighbors(diagram, cell_id) {
+            let c = &mut cells[n as usize];
+            if c.is_water && !c.is_ocean {
+                c.is_ocean = true;
+                queue.push_back(n);
+            }
+        }
+    }
+}
+
+// セルの頂点 (川はここを通る)
+struct Corners {
+    positions: Vec<Vec2d>,
+    elevations: Vec<f64>,
+    touches_water: Vec<bool>,
+    adjacent: Vec<Vec<usize>>,
+    cells: Vec<Vec<CellId>>,
+}
+
+impl Corners {
+    fn new(diagram: &VoronoiDiagram, map_cells: &[MapCell]) -> Self {
+        let mut corners = Corners {
+            positions: Vec::new(),
+            elevations: Vec::new(),
+            touches_water: Vec::new(),
+            adjacent: Vec::new(),
+            cells: Vec::new(),
+        };
+        // 座標が同じ頂点は同じものとみなす
+        let mut index: HashMap<(i64, i64), usize> = HashMap::new();
+        let mut corner_of = |corners: &mut Corners, pos: Vec2d| -> usize {
+            let key = ((pos.x * 1e9).round() as i64, (pos.y * 1e9).round() as i64);
+            *index.entry(key).or_insert_with(|| {
+                corners.positions.push(pos);
+                corners.adjacent.push(Vec::new());
+                corners.cells.push(Vec::new());
+                corners.positions.len() - 1
+            })
+        };
+        for _cell in diagram.cells.iter() {
+            let cell = _cell.borrow();
+            for line in cell.lines.iter() {
+                let c1 = corner_of(&mut corners, line.line.p1);
+                let c2 = corner_of(&mut corners, line.line.p2);
+                if c1 == c2 {
+                    continue;
+                }
+                for (a, b) in [(c1, c2), (c2, c1)].iter() {
+                    if !corners.adjacent[*a].contains(b) {
+                        corners.adjacent[*a].push(*b);
+                    }
+                    if !corners.cells[*a].contains(&cell.cell_id) {
+                        corners.cells[*a].push(cell.cell_id);
+                    }
+                }
+            }
+        }
+        for cell_ids in corners.cells.iter() {
+            let sum: f64 = cell_ids
+                .iter()
+                .map(|c| map_cells[*c as usize].elevation)
+                .sum();
+            corners.elevations.push(sum / cell_ids.len() as f64);
+            corners
+                .touches_water
+                .push(cell_ids.iter().any(|c| map_cells[*c as usize].is_water));
+        }
+        corners
+    }
+
+    // 高い頂点から、一番低い隣の頂点へ水に着くまで流す
+    fn make_rivers(&self, config: &MapConfig, rng: &mut StdRng) -> Vec<RiverSegment> {
+        // HashMap だと川の順番が毎回変わるので BTreeMap
+        let mut flows: BTreeMap<(usize, usize), u32> = BTreeMap::new();
+        if self.positions.is_empty() {
+            return vec![];
+        }
+        for _ in 0..config.n_rivers {
+            let mut current = rng.gen_range(0, self.positions.len());
+            if self.touches_water[current] || self.elevations[current] < config.water_level + 0.1 {
+                continue;
+            }
+            for _ in 0..self.positions.len() {
+                if self.touches_water[current] {
+                    break;
+                }
+                let next = *self.adjacent[current]
+                    .iter()
+                    .min_by(|a, b| {
+                        self.elevations[**a]
+                            .partial_cmp(&self.elevations[**b])
+                            .unwrap()
+                    })
+                    .unwrap();
+                if self.elevations[next] >= self.elevations[current] {
+                    break; // くぼみ
+                }
+                let key = (current.min(next), current.max(next));
+                *flows.entry(key).or_insert(0) += 1;
+                current = next;
+            }
+        }
+        flows
+            .into_iter()
+            .map(|((c1, c2), flow)| RiverSegment {
+                line: Line::new(self.positions[c1], self.positions[c2]),
+                flow,
+            })
+            .collect()
+    }
+}
+
+// 湿度 = 淡水(湖・川)からのセルの距離で減っていく
+fn assign_moisture(
+    diagram: &VoronoiDiagram,
+    cells: &mut [MapCell],
+    corners: &Corners,
+    rivers: &[RiverSegment],
+) {
+    let mut distance: Vec<Option<u32>> = vec![None; cells.len()];
+    let mut queue: VecDeque<CellId> = VecDeque::new();
+    for (cell_id, c) in cells.iter().enumerate() {
+        if c.is_water && !c.is_ocean {
+            distance[cell_id] = Some(0);
+            queue.push_back(cell_id as CellId);
+        }
+    }
+    for river in rivers.iter() {
+        for (i, pos) in corners.positions.iter().enumerate() {
+            if *pos == river.line.p1 || *pos == river.line.p2 {
+                for cell_id in corners.cells[i].iter() {
+                    if distance[*cell_id as usize].is_none() {
+                        distance[*cell_id as usize] = Some(0);
+                        queue.push_back(*cell_id);
+                    }
+                }
+            }
+        }
+    }
+    while let Some(cell_id) = queue.pop_front() {
+        let d = distance[cell_id as usize].unwrap();
+        for n in neighbors(diagram, cell_id) {
+            if distance[n as usize].is_none() {
+                distance[n as usize] = Some(d + 1);
+                queue.push_back(n);
+            }
+        }
+    }
+    for (cell_id, c) in cells.iter_mut().enumerate() {
+        c.moisture = match distance[cell_id] {
+            Some(d) => 0.7f64.powi(d as i32),
+            None => 0.,
+        };
+    }
+}
+
+fn biome(cell: &MapCell, water_level: f64) -> Biome {
+    if cell.is_ocean {
+        return Biome::Ocean;
+    }
+    if cell.is_water {
+        return Biome::Lake;
+    }
+    let e = (cell.elevation - water_level) / (1. - water_level);
+    let m = cell.moisture;
+    if e > 0.6 {
+        if m > 0.5 {
+            Biome::Snow
+        } else {
+            Biome::Tundra
+        }
+    } else if e > 0.3 {
+        if m > 0.5 {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    } else if m > 0.7 {
+        Biome::RainForest
+    } else if m > 0.45 {
+        Biome::Forest
+    } else if m > 0.2 {
+        Biome::Grassland
+    } else {
+        Biome::Desert
+    }
+}
+
+// 格子点に乱数を置いて補間する value noise を、周波数を変えて重ねる。0~1
+fn fractal_noise(pos: Vec2d, seed: u64) -> f64 {
+    let mut sum = 0.;
+    let mut amplitude = 1.;
+    let mut total = 0.;
+    let mut p = pos;
+    for octave in 0..4 {
+        sum += value_noise(p, seed.wrapping_add(octave)) * amplitude;
+        total += amplitude;
+        amplitude /= 2.;
+        p = p.mul(2.);
+    }
+    sum / total
+}
+
+fn value_noise(pos: Vec2d, seed: u64) -> f64 {
+    let (ix, iy) = (pos.x.floor(), pos.y.floor());
+    let (fx, fy) = (pos.x - ix, pos.y - iy);
+    let (ix, iy) = (ix as i64, iy as i64);
+    let smooth = |t: f64| t * t * (3. - 2. * t);
+    let (sx, sy) = (smooth(fx), smooth(fy));
+    let v00 = lattice(ix, iy, seed);
+    let v10 = lattice(ix + 1, iy, seed);
+    let v01 = lattice(ix, iy + 1, seed);
+    let v11 = lattice(ix + 1, iy + 1, seed);
+    let v0 = v00 + (v10 - v00) * sx;
+    let v1 = v01 + (v11 - v01) * sx;
+    v0 + (v1 - v0) * sy
+}
+
+// 格子点ごとの 0~1 の乱数 (splitmix64)
+fn lattice(ix: i64, iy: i64, seed: u64) -> f64 {
+    let mut h = (ix as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (iy as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ seed;
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(seed: u64) -> MapConfig {
+        let mut config = MapConfig::new(seed);
+        config.n_sites = 200;
+        config
+    }
+
+    // 比べやすいように、点と各セルの属性と川を文字列にする
+    fn summary(diagram: &VoronoiDiagram, map: &VoronoiMap) -> String {
+        let mut s = diagram.to_dot();
+        for cell in map.cells.iter() {
+            s += &format!(
+                "{} {} {} {} {:?}\n",
+                cell.elevation, cell.moisture, cell.is_water, cell.is_ocean, cell.biome
+            );
+        }
+        for river in map.rivers.iter() {
+            s += &format!("{} {}\n", river.line, river.flow);
+        }
+        s
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        let (diagram1, map1) = generate_map(&config(5)).unwrap();
+        let (diagram2, map2) = generate_map(&config(5)).unwrap();
+        assert_eq!(summary(&diagram1, &map1), summary(&diagram2, &map2));
+        let (diagram3, map3) = generate_map(&config(6)).unwrap();
+        assert_ne!(summary(&diagram1, &map1), summary(&diagram3, &map3));
+    }
+
+    #[test]
+    fn map_stays_in_bounds() {
+        let (diagram, map) = generate_map(&config(5)).unwrap();
+        assert_eq!(200, diagram.cells.len());
+        assert_eq!(diagram.cells.len(), map.cells.len());
+        let inside = |p: Vec2d| 0. <= p.x && p.x <= 1. && 0. <= p.y && p.y <= 1.;
+        for cell in diagram.cells.iter() {
+            let cell = cell.borrow();
+            assert!(inside(cell.point.pos));
+            assert!(cell.bound.polygon.points.iter().all(|p| inside(*p)));
+        }
+        for cell in map.cells.iter() {
+            assert!((0. ..=1.).contains(&cell.elevation));
+            assert!((0. ..=1.).contains(&cell.moisture));
+            assert!(!cell.is_ocean || cell.is_water);
+        }
+        assert!(!map.rivers.is_empty());
+        for river in map.rivers.iter() {
+            assert!(inside(river.line.p1) && inside(river.line.p2));
+            assert!(river.flow >= 1);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::input::{Modifiers, PointerInput, PointerType, POINTER_EVENTS};
+use crate::session::Session;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+const INPUT_LOG_VERSION: u32 = 2;
+// 再生した結果と比べるときに許す位置のずれ (地図や点描はセルの計算順で誤差が出る)
+const POSITION_EPS: f64 = 1e-9;
+// 記録するキーの状態
+const MODIFIERS: [&str; 4] = ["shiftKey", "ctrlKey", "metaKey", "altKey"];
+
+/// ハンドラが読むイベントの値
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct EventData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<(f64, f64)>, // Canvas 上の位置 (offsetX, offsetY)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pos: Option<(f64, f64)>, // offset を図の座標にしたもの (読む人のため。再生には使わない)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub button: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer_id: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_y: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>, // target.tagName
+}
+
+impl EventData {
+    pub fn from_event(e: &JsValue) -> Self {
+        let number = |name: &str| get(e, name).and_then(|v| v.as_f64());
+        let offset = match (number("offsetX"), number("offsetY")) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None,
+        };
+        Self {
+            offset,
+            pos: None,
+            button: number("button"),
+            pointer_id: number("pointerId"),
+            pointer_type: get(e, "pointerType").and_then(|v| v.as_string()),
+            delta_y: number("deltaY"),
+            key: get(e, "key").and_then(|v| v.as_string()),
+            modifiers: MODIFIERS
+                .iter()
+                .filter(|name| get(e, name).and_then(|v| v.as_bool()) == Some(true))
+                .map(|name| name.to_string())
+                .collect(),
+            tag: get(e, "target")
+                .and_then(|t| get(&t, "tagName"))
+                .and_then(|v| v.as_string()),
+        }
+    }
+
+    /// ハンドラに渡すイベントの代わりになる JS のオブジェクト
+    pub fn to_event(&self) -> JsValue {
+        let e = js_sys::Object::new();
+        let set = |target: &js_sys::Object, name: &str, value: JsValue| {
+            js_sys::Reflect::set(target, &JsValue::from(name), &value).unwrap();
+        };
+        if let Some((x, y)) = self.offset {
+            set(&e, "offsetX", JsValue::from(x));
+            set(&e, "offsetY", JsValue::from(y));
+        }
+        if let Some(button) = self.button {
+            set(&e, "button", JsValue::from(button));
+        }
+        if let Some(pointer_id) = self.pointer_id {
+            set(&e, "pointerId", JsValue::from(pointer_id));
+        }
+        if let Some(pointer_type) = &self.pointer_type {
+            set(&e, "pointerType", JsValue::from(pointer_type.as_str()));
+        }
+        if let Some(delta_y) = self.delta_y {
+            set(&e, "deltaY", JsValue::from(delta_y));
+        }
+        if let Some(key) = &self.key {
+            set(&e, "key", JsValue::from(key.as_str()));
+        }
+        for name in MODIFIERS.iter() {
+            let on = self.modifiers.iter().any(|m| m == name);
+            set(&e, name, JsValue::from(on));
+        }
+        let target = js_sys::Object::new();
+        if let Some(tag) = &self.tag {
+            set(&target, "tagName", JsValue::from(tag.as_str()));
+        }
+        set(&e, "target", target.into());
+        set(
+            &e,
+            "preventDefault",
+            js_sys::Function::new_no_args("").into(),
+        );
+        e.into()
+    }
+
+    /// Canvas の Pointer Events なら、JS のイベントを作らずに PointerInput にする。
+    /// PointerInput::from_event と同じ値になる
+    pub fn to_pointer_input(&self, event: &str) -> Option<PointerInput> {
+        let phase = POINTER_EVENTS.iter().find(|(name, _)| *name == event)?.1;
+        let pointer_type = match self.pointer_type.as_deref() {
+            Some("touch") => PointerType::Touch,
+            Some("pen") => PointerType::Pen,
+            _ => PointerType::Mouse,
+        };
+        let flag = |name: &str| self.modifiers.iter().any(|m| m == name);
+        Some(PointerInput {
+            phase,
+            pointer_id: self.pointer_id.unwrap_or(0.) as i32,
+            pointer_type,
+            pos: self.offset?,
+            button: self.button.unwrap_or(-1.) as i16,
+            modifiers: Modifiers {
+                shift: flag("shiftKey"),
+                ctrl: flag("ctrlKey"),
+                meta: flag("metaKey"),
+                alt: flag("altKey"),
+            },
+        })
+    }
+}
+
+fn get(e: &JsValue, name: &str) -> Option<JsValue> {
+    js_sys::Reflect::get(e, &JsValue::from(name))
+        .ok()
+        .filter(|v| !v.is_undefined())
+}
+
+/// 入力操作の記録の1件
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputRecord {
+    // フォームの値が変わった (ハンドラより先に記録する)
+    Control {
+        time: f64,
+        id: String,
+        value: String,
+        checked: bool,
+    },
+    // target (要素の id か "document") の event のハンドラが呼ばれた
+    Event {
+        time: f64,
+        target: String,
+        event: String,
+        data: EventData,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ControlValue {
+    pub id: String,
+    pub value: String,
+    pub checked: bool,
+}
+
+/// 入力操作の記録。始めたときの状態と、その後の入力を持つ。
+/// 同じ状態から同じ順にハンドラを呼び直せば同じ操作になる。
+/// ただし、アニメーションと点の読み込みは時間によって結果が変わり、JS から直接呼んだ関数は記録しないので再現しない。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InputLog {
+    pub version: u32,
+    pub seed: u64, // 点の追加などに使う乱数の種
+    pub session: Session,
+    pub viewport: (f64, f64, f64), // (scale, offset.x, offset.y)
+    pub controls: Vec<ControlValue>,
+    pub records: Vec<InputRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Session>, // 記録を止めたときの状態
+}
+
+impl InputLog {
+    pub fn new(
+        seed: u64,
+        session: Session,
+        viewport: (f64, f64, f64),
+        controls: Vec<ControlValue>,
+    ) -> Self {
+        Self {
+            version: INPUT_LOG_VERSION,
+            seed,
+            session,
+            viewport,
+            controls,
+            records: Vec::new(),
+            expected: None,
+        }
+    }
+
+    /// 再生した結果が記録したときと同じか調べる。違えば最初の違いを返す
+    pub fn check(&self, actual: &Session) -> Result<(), String> {
+        let expected = match &self.expected {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+        if expected.sites.len() != actual.sites.len() {
+            return Err(format!(
+                "{} sites, expected {}",
+                actual.sites.len(),
+                expected.sites.len()
+            ));
+        }
+        for (i, (e, a)) in expected.sites.iter().zip(actual.sites.iter()).enumerate() {
+            if e.node_id != a.node_id
+                || e.cluster_id != a.cluster_id
+                || (e.x - a.x).abs() > POSITION_EPS
+                || (e.y - a.y).abs() > POSITION_EPS
+            {
+                return Err(format!("site {}: {:?}, expected {:?}", i, a, e));
+            }
+        }
+        if expected.next_node_id != actual.next_node_id {
+            return Err(format!(
+                "next_node_id {}, expected {}",
+                actual.next_node_id, expected.next_node_id
+            ));
+        }
+        if expected.cell_values != actual.cell_values {
+            return Err(String::from("cell values differ"));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let log: InputLog = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if log.version != INPUT_LOG_VERSION {
+            return Err(format!("unknown input log version {}", log.version));
+        }
+        Ok(log)
+    }
+}
+
+/// 記録中の入力操作
+pub struct Recorder {
+    pub log: InputLog,
+    start_time: f64,
+}
+
+impl Recorder {
+    /// now は今の時刻(ms)
+    pub fn new(log: InputLog, now: f64) -> Self {
+        Self {
+            log,
+            start_time: now,
+        }
+    }
+
+    pub fn control(&mut self, now: f64, control: ControlValue) {
+        self.log.records.push(InputRecord::Control {
+            time: now - self.start_time,
+            id: control.id,
+            value: control.value,
+            checked: control.checked,
+        });
+    }
+
+    pub fn event(&mut self, now: f64, target: &str, event: &str, data: EventData) {
+        self.log.records.push(InputRecord::Event {
+            time: now - self.start_time,
+            target: target.to_string(),
+            event: event.to_string(),
+            data,
+        });
+    }
+}
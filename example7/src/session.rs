@@ -0,0 +1,243 @@
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{ClusterId, NodeId, VoronoiCenterPoint};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const SESSION_VERSION: u32 = 1;
+const AUTOSAVE_KEY: &str = "voronoi.autosave";
+const SNAPSHOT_PREFIX: &str = "voronoi.snapshot.";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SessionSite {
+    pub node_id: NodeId,
+    pub x: f64,
+    pub y: f64,
+    pub cluster_id: ClusterId,
+}
+
+/// 保存しておく図の状態。点は CellId の順なので、同じ順に追加すれば同じ図になる。
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Session {
+    pub version: u32,
+    pub sites: Vec<SessionSite>,
+    pub next_node_id: NodeId,
+    #[serde(default)]
+    pub cell_values: Vec<(NodeId, f64)>, // 色分けに使う値
+}
+
+impl Session {
+    pub fn new(
+        diagram: &VoronoiDiagram,
+        next_node_id: NodeId,
+        cell_values: &HashMap<NodeId, f64>,
+    ) -> Self {
+        let sites = diagram
+            .points()
+            .iter()
+            .map(|p| SessionSite {
+                node_id: p.node_id,
+                x: p.pos.x,
+                y: p.pos.y,
+                cluster_id: p.cluster_id,
+            })
+            .collect();
+        let mut cell_values: Vec<(NodeId, f64)> =
+            cell_values.iter().map(|(k, v)| (*k, *v)).collect();
+        cell_values.sort_by_key(|(k, _)| *k);
+        Self {
+            version: SESSION_VERSION,
+            sites,
+            next_node_id,
+            cell_values,
+        }
+    }
+
+    pub fn points(&self) -> Vec<VoronoiCenterPoint> {
+        self.sites
+            .iter()
+            .map(|s| VoronoiCenterPoint {
+                node_id: s.node_id,
+                pos: Vec2d { x: s.x, y: s.y },
+                cluster_id: s.cluster_id,
+            })
+            .collect()
+    }
+
+    pub fn cell_values(&self) -> HashMap<NodeId, f64> {
+        self.cell_values.iter().cloned().collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let session: Session = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if session.version != SESSION_VERSION {
+            return Err(format!("unknown session version {}", session.version));
+        }
+        Ok(session)
+    }
+}
+
+/// Session を Storage に保存する。自動保存が1つと、名前をつけたスナップショットがいくつか。
+pub struct SessionStore {
+    storage: Box<dyn Storage>,
+}
+
+impl SessionStore {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    pub fn autosave(&mut self, session: &Session) -> Result<(), String> {
+        self.storage.set(AUTOSAVE_KEY, &session.to_json())
+    }
+
+    /// 自動保存が無ければ None
+    pub fn load_autosave(&self) -> Result<Option<Session>, String> {
+        self.load(AUTOSAVE_KEY)
+    }
+
+    pub fn save_snapshot(&mut self, name: &str, session: &Session) -> Result<(), String> {
+        if name.is_empty() {
+            return Err(String::from("snapshot name is empty"));
+        }
+        self.storage.set(&snapshot_key(name), &session.to_json())
+    }
+
+    pub fn load_snapshot(&self, name: &str) -> Result<Session, String> {
+        self.load(&snapshot_key(name))?
+            .ok_or(format!("snapshot not found: {}", name))
+    }
+
+    pub fn delete_snapshot(&mut self, name: &str) -> Result<(), String> {
+        self.storage.remove(&snapshot_key(name))
+    }
+
+    /// スナップショットの名前 (名前順)
+    pub fn snapshot_names(&self) -> Result<Vec<String>, String> {
+        let mut names: Vec<String> = self
+            .storage
+            .keys()?
+            .iter()
+            .filter(|key| key.starts_with(SNAPSHOT_PREFIX))
+            .map(|key| key[SNAPSHOT_PREFIX.len()..].to_string())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn load(&self, key: &str) -> Result<Option<Session>, String> {
+        match self.storage.get(key)? {
+            Some(json) => Session::from_json(&json).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn snapshot_key(name: &str) -> String {
+    format!("{}{}", SNAPSHOT_PREFIX, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn session(next_node_id: NodeId) -> Session {
+        let mut diagram = VoronoiDiagram::new();
+        for node_id in 0..next_node_id {
+            let vp = VoronoiCenterPoint {
+                pos: Vec2d {
+                    x: 0.1 + 0.8 * node_id as f64 / next_node_id as f64,
+                    y: 0.3 + 0.1 * node_id as f64,
+                },
+                node_id,
+                cluster_id: node_id % 2,
+            };
+            diagram.add_point(&vp).unwrap();
+        }
+        let cell_values: HashMap<NodeId, f64> = vec![(1, 0.5), (0, 2.)].into_iter().collect();
+        Session::new(&diagram, next_node_id, &cell_values)
+    }
+
+    fn store_with(items: &[(&str, &str)]) -> SessionStore {
+        let mut storage = MemoryStorage::new();
+        for (key, value) in items.iter() {
+            storage.set(key, value).unwrap();
+        }
+        SessionStore::new(Box::new(storage))
+    }
+
+    #[test]
+    fn autosave_round_trip() {
+        let mut store = store_with(&[]);
+        assert_eq!(Ok(None), store.load_autosave());
+        let saved = session(3);
+        store.autosave(&saved).unwrap();
+        let loaded = store.load_autosave().unwrap().unwrap();
+        assert_eq!(saved, loaded);
+        assert_eq!(vec![(0, 2.), (1, 0.5)], loaded.cell_values);
+        let points = loaded.points();
+        assert_eq!(3, points.len());
+        assert_eq!(1, points[1].cluster_id);
+        // 自動保存はスナップショットに出てこない
+        assert_eq!(Ok(vec![]), store.snapshot_names());
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut store = store_with(&[("other", "x")]);
+        let (a, b) = (session(2), session(4));
+        store.save_snapshot("b", &b).unwrap();
+        store.save_snapshot("a", &a).unwrap();
+        assert!(store.save_snapshot("", &a).is_err());
+        assert_eq!(
+            Ok(vec![String::from("a"), String::from("b")]),
+            store.snapshot_names()
+        );
+        assert_eq!(Ok(b.clone()), store.load_snapshot("b"));
+
+        // 同じ名前なら上書き
+        store.save_snapshot("a", &b).unwrap();
+        assert_eq!(Ok(b), store.load_snapshot("a"));
+
+        store.delete_snapshot("a").unwrap();
+        assert_eq!(Ok(vec![String::from("b")]), store.snapshot_names());
+        assert_eq!(
+            Err(String::from("snapshot not found: a")),
+            store.load_snapshot("a")
+        );
+    }
+
+    #[test]
+    fn corrupt_entries() {
+        let snapshot = format!("{}broken", SNAPSHOT_PREFIX);
+        let store = store_with(&[(AUTOSAVE_KEY, "{not json"), (&snapshot, "[]")]);
+        assert!(store.load_autosave().is_err());
+        assert!(store.load_snapshot("broken").is_err());
+        // 読めないスナップショットも名前は出す (選んで消せるように)
+        assert_eq!(Ok(vec![String::from("broken")]), store.snapshot_names());
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let mut json = session(2).to_json();
+        json = json.replace(
+            &format!("\"version\":{}", SESSION_VERSION),
+            "\"version\":99",
+        );
+        let store = store_with(&[(AUTOSAVE_KEY, &json)]);
+        assert_eq!(
+            Err(String::from("unknown session version 99")),
+            store.load_autosave()
+        );
+        // 古い形式で cell_values が無くても読める
+        let old = r#"{"version":1,"sites":[{"node_id":0,"x":0.5,"y":0.5,"cluster_id":0}],"next_node_id":1}"#;
+        let session = Session::from_json(old).unwrap();
+        assert!(session.cell_values.is_empty());
+    }
+}
@@ -0,0 +1,409 @@
+use crate::geometry::polygon::Polygon;
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::insertion_order::brio_order;
+use crate::geometry::voronoi_diagram::types::{
+    ClusterId, NodeId, VoronoiCenterPoint, VoronoiError,
+};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// 正規化するときに外周の内側にとる余白
+const NORMALIZE_MARGIN: f64 = 0.05;
+// 追加するときに点をずらす大きさ(外周の大きさに対する比率)
+const JITTER: f64 = 1e-7;
+
+/// ファイルから読んだ点 (座標は元のまま)
+#[derive(Clone, Debug, PartialEq)]
+pub struct SiteRecord {
+    pub x: f64,
+    pub y: f64,
+    pub cluster_id: Option<ClusterId>,
+    pub weight: Option<f64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SiteFormat {
+    Json,
+    Csv,
+}
+
+impl SiteFormat {
+    /// URL の拡張子、無ければ中身の最初の文字で決める
+    pub fn detect(url: &str, text: &str) -> Self {
+        let path = url.split(&['?', '#'][..]).next().unwrap_or("");
+        let path = path.to_lowercase();
+        if path.ends_with(".csv") {
+            SiteFormat::Csv
+        } else if path.ends_with(".json") {
+            SiteFormat::Json
+        } else {
+            match text.trim_start().chars().next() {
+                Some('[') | Some('{') => SiteFormat::Json,
+                _ => SiteFormat::Csv,
+            }
+        }
+    }
+}
+
+/// 点のリストを読む。エラーは行番号付きのメッセージ。
+pub fn parse_sites(text: &str, format: SiteFormat) -> Result<Vec<SiteRecord>, String> {
+    let records = match format {
+        SiteFormat::Json => parse_json(text)?,
+        SiteFormat::Csv => parse_csv(text)?,
+    };
+    if records.is_empty() {
+        return Err(String::from("no sites"));
+    }
+    Ok(records)
+}
+
+// [{...}, ...] と {"sites": [{...}, ...]} のどちらでもよい。
+// 型が違うときにどの点のどの値かわかるように、1つずつ読む
+fn parse_json(text: &str) -> Result<Vec<SiteRecord>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let sites = match value.get("sites").unwrap_or(&value) {
+        Value::Array(sites) => sites,
+        _ => {
+            return Err(String::from(
+                "expected [{\"x\": .., \"y\": ..}, ..] or {\"sites\": [..]}",
+            ))
+        }
+    };
+    let mut records = Vec::with_capacity(sites.len());
+    for (i, site) in sites.iter().enumerate() {
+        let record = json_record(site).map_err(|e| format!("site {}: {}", i, e))?;
+        check_record(&record).map_err(|e| format!("site {}: {}", i, e))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn json_record(site: &Value) -> Result<SiteRecord, String> {
+    if !site.is_object() {
+        return Err(format!("expected an object: {}", site));
+    }
+    // 無いか null なら None
+    let field = |name: &str| site.get(name).filter(|v| !v.is_null());
+    let number = |name: &str| -> Result<Option<f64>, String> {
+        match field(name) {
+            Some(v) => v
+                .as_f64()
+                .map(Some)
+                .ok_or(format!("{} is not a number: {}", name, v)),
+            None => Ok(None),
+        }
+    };
+    let cluster_id = match field("cluster_id") {
+        Some(v) => Some(
+            v.as_u64()
+                .filter(|id| *id <= ClusterId::MAX as u64)
+                .ok_or(format!("cluster_id is not an integer: {}", v))? as ClusterId,
+        ),
+        None => None,
+    };
+    Ok(SiteRecord {
+        x: number("x")?.ok_or("no x")?,
+        y: number("y")?.ok_or("no y")?,
+        cluster_id,
+        weight: number("weight")?,
+    })
+}
+
+// 見出し行があれば列名 (x, y, cluster_id, weight) で、無ければ x,y[,cluster_id[,weight]] の順で読む。
+// 空行と # で始まる行は読み飛ばす。
+fn parse_csv(text: &str) -> Result<Vec<SiteRecord>, String> {
+    let mut columns: Option<HashMap<String, usize>> = None;
+    let mut records = Vec::new();
+    let mut first = true;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if first {
+            first = false;
+            // 空の列は省略した値なので、数値かどうかは空でない列で判断する
+            if fields
+                .iter()
+                .any(|f| !f.is_empty() && f.parse::<f64>().is_err())
+            {
+                let header: HashMap<String, usize> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, f)| (f.to_lowercase(), i))
+                    .collect();
+                for name in ["x", "y"].iter() {
+                    if !header.contains_key(*name) {
+                        return Err(format!("line {}: no \"{}\" column", line_no, name));
+                    }
+                }
+                columns = Some(header);
+                continue;
+            }
+        }
+        let index = |name: &str, default: usize| match &columns {
+            Some(header) => header.get(name).cloned(),
+            None => Some(default),
+        };
+        let field = |name: &str, default: usize| -> Option<&str> {
+            match index(name, default).and_then(|i| fields.get(i)) {
+                Some(f) if !f.is_empty() => Some(*f),
+                _ => None,
+            }
+        };
+        let number = |name: &str, default: usize| -> Result<Option<f64>, String> {
+            match field(name, default) {
+                Some(f) => f
+                    .parse::<f64>()
+                    .map(Some)
+                    .map_err(|_| format!("line {}: {} is not a number: {}", line_no, name, f)),
+                None => Ok(None),
+            }
+        };
+        let x = number("x", 0)?.ok_or(format!("line {}: no x", line_no))?;
+        let y = number("y", 1)?.ok_or(format!("line {}: no y", line_no))?;
+        let cluster_id =
+            match field("cluster_id", 2) {
+                Some(f) => Some(f.parse::<ClusterId>().map_err(|_| {
+                    format!("line {}: cluster_id is not an integer: {}", line_no, f)
+                })?),
+                None => None,
+            };
+        let weight = number("weight", 3)?;
+        let record = SiteRecord {
+            x,
+            y,
+            cluster_id,
+            weight,
+        };
+        check_record(&record).map_err(|e| format!("line {}: {}", line_no, e))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn check_record(record: &SiteRecord) -> Result<(), String> {
+    if !record.x.is_finite() || !record.y.is_finite() {
+        return Err(format!("invalid position ({}, {})", record.x, record.y));
+    }
+    if let Some(weight) = record.weight {
+        if !weight.is_finite() {
+            return Err(format!("invalid weight {}", weight));
+        }
+    }
+    Ok(())
+}
+
+/// 点全体の外接矩形を、縦横比を保って外周 bounds の中央に収める
+pub fn normalize(records: &[SiteRecord], bounds: &Rect) -> Vec<Vec2d> {
+    let positions: Vec<Vec2d> = records.iter().map(|r| Vec2d { x: r.x, y: r.y }).collect();
+    if positions.is_empty() {
+        return positions;
+    }
+    let rect = Polygon::new(positions.clone()).bounding_rect();
+    let inner = bounds.size.mul(1. - NORMALIZE_MARGIN * 2.);
+    let size = rect.size.x.max(rect.size.y);
+    // 1点しかない、または全部同じ位置なら中央に置く
+    let scale = if size > 0. {
+        inner.x.min(inner.y) / size
+    } else {
+        0.
+    };
+    let center = bounds.pos + bounds.size.mul(0.5);
+    let rect_center = rect.pos + rect.size.mul(0.5);
+    positions
+        .iter()
+        .map(|p| center + (*p - rect_center).mul(scale))
+        .collect()
+}
+
+/// 正規化した点に first_node_id から順に NodeId をつける。weight があれば NodeId ごとの値も返す。
+pub fn to_center_points(
+    records: &[SiteRecord],
+    bounds: &Rect,
+    first_node_id: NodeId,
+) -> (Vec<VoronoiCenterPoint>, HashMap<NodeId, f64>) {
+    let mut points = Vec::with_capacity(records.len());
+    let mut weights = HashMap::new();
+    for (i, (record, pos)) in records.iter().zip(normalize(records, bounds)).enumerate() {
+        let node_id = first_node_id + i as NodeId;
+        points.push(VoronoiCenterPoint {
+            node_id,
+            pos,
+            cluster_id: record.cluster_id.unwrap_or(0),
+        });
+        if let Some(weight) = record.weight {
+            weights.insert(node_id, weight);
+        }
+    }
+    (points, weights)
+}
+
+/// 点を少しずつ追加して図を作る (多いと1回では画面が止まるので、何回かに分ける)
+pub struct SiteBuilder {
+    pub diagram: VoronoiDiagram,
+    points: Vec<VoronoiCenterPoint>,
+    index: usize,
+    pub skipped: usize, // 他の点と同じ位置で追加できなかった点の数
+}
+
+impl SiteBuilder {
+    pub fn new(bounds: Rect, points: Vec<VoronoiCenterPoint>, seed: u64) -> Self {
+        let mut points = points;
+        // 格子状に並んだ点は4点以上が同じ円周上に乗ってセルを分割できないので、少しだけずらす
+        let mut rng = StdRng::seed_from_u64(seed);
+        for point in points.iter_mut() {
+            point.pos.x += (rng.gen::<f64>() - 0.5) * bounds.size.x * JITTER;
+            point.pos.y += (rng.gen::<f64>() - 0.5) * bounds.size.y * JITTER;
+        }
+        // 追加する順番で速さが大きく変わるので BRIO の順にする
+        brio_order(&mut points, &bounds, seed);
+        Self {
+            diagram: VoronoiDiagram::with_bounds(bounds),
+            points,
+            index: 0,
+            skipped: 0,
+        }
+    }
+
+    /// 最大 n 点を追加する。全部追加し終えたら true
+    pub fn add_points(&mut self, n: usize) -> Result<bool, VoronoiError> {
+        let end = (self.index + n).min(self.points.len());
+        for point in self.points[self.index..end].iter() {
+            match self.diagram.add_point(point) {
+                Ok(_) => {}
+                Err(VoronoiError::SamePosition) => self.skipped += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        self.index = end;
+        Ok(self.is_done())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.index >= self.points.len()
+    }
+
+    /// (追加した点の数, 全部の点の数)
+    pub fn progress(&self) -> (usize, usize) {
+        (self.index, self.points.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(x: f64, y: f64, cluster_id: Option<ClusterId>, weight: Option<f64>) -> SiteRecord {
+        SiteRecord {
+            x,
+            y,
+            cluster_id,
+            weight,
+        }
+    }
+
+    #[test]
+    fn json_list_and_object() {
+        let expected = vec![
+            record(1., 2., None, None),
+            record(3.5, -4., Some(2), Some(0.5)),
+        ];
+        let list = r#"[{"x": 1, "y": 2}, {"x": 3.5, "y": -4, "cluster_id": 2, "weight": 0.5}]"#;
+        assert_eq!(Ok(expected.clone()), parse_sites(list, SiteFormat::Json));
+        let object = format!(r#"{{"sites": {}}}"#, list);
+        assert_eq!(Ok(expected), parse_sites(&object, SiteFormat::Json));
+        let null = r#"[{"x": 1, "y": 2, "cluster_id": null, "weight": null}]"#;
+        assert_eq!(
+            Ok(vec![record(1., 2., None, None)]),
+            parse_sites(null, SiteFormat::Json)
+        );
+    }
+
+    #[test]
+    fn csv_with_header() {
+        let text = "# comment\nweight, Y ,x\n\n0.5,2,1\n,4,3\n";
+        assert_eq!(
+            Ok(vec![
+                record(1., 2., None, Some(0.5)),
+                record(3., 4., None, None)
+            ]),
+            parse_sites(text, SiteFormat::Csv)
+        );
+    }
+
+    #[test]
+    fn csv_without_header() {
+        // 最初の行の空の列は見出しと間違えない
+        let text = "0.1,0.2,,1\n0.3,0.4,5\n0.5,0.6\n";
+        assert_eq!(
+            Ok(vec![
+                record(0.1, 0.2, None, Some(1.)),
+                record(0.3, 0.4, Some(5), None),
+                record(0.5, 0.6, None, None),
+            ]),
+            parse_sites(text, SiteFormat::Csv)
+        );
+    }
+
+    fn error(text: &str, format: SiteFormat) -> String {
+        parse_sites(text, format).unwrap_err()
+    }
+
+    #[test]
+    fn json_errors() {
+        let json = SiteFormat::Json;
+        assert_eq!(
+            "site 1: y is not a number: \"a\"",
+            error(r#"[{"x": 1, "y": 2}, {"x": 1, "y": "a"}]"#, json)
+        );
+        assert_eq!("site 0: no x", error(r#"[{"y": 2}]"#, json));
+        assert_eq!(
+            "site 0: cluster_id is not an integer: 1.5",
+            error(r#"[{"x": 1, "y": 2, "cluster_id": 1.5}]"#, json)
+        );
+        assert_eq!(
+            "site 0: cluster_id is not an integer: -1",
+            error(r#"[{"x": 1, "y": 2, "cluster_id": -1}]"#, json)
+        );
+        assert_eq!(
+            "site 0: weight is not a number: true",
+            error(r#"{"sites": [{"x": 1, "y": 2, "weight": true}]}"#, json)
+        );
+        assert_eq!("site 0: expected an object: 3", error("[3]", json));
+        assert!(error(r#"{"points": []}"#, json).starts_with("expected"));
+        assert_eq!("no sites", error("[]", json));
+        assert!(error("[{", json).contains("line 1"));
+    }
+
+    #[test]
+    fn csv_errors() {
+        let csv = SiteFormat::Csv;
+        assert_eq!("line 1: no \"y\" column", error("x,weight\n1,2\n", csv));
+        assert_eq!(
+            "line 3: y is not a number: b",
+            error("x,y\n1,2\n3,b\n", csv)
+        );
+        assert_eq!("line 2: no y", error("1,2\n3\n", csv));
+        assert_eq!(
+            "line 1: cluster_id is not an integer: 1.5",
+            error("1,2,1.5\n", csv)
+        );
+        assert_eq!("line 1: invalid position (inf, 2)", error("inf,2\n", csv));
+        assert_eq!("no sites", error("# only a comment\n", csv));
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(SiteFormat::Csv, SiteFormat::detect("a/sites.CSV?v=1", "["));
+        assert_eq!(SiteFormat::Json, SiteFormat::detect("sites.json#x", "x,y"));
+        assert_eq!(SiteFormat::Json, SiteFormat::detect("sites", " \n{"));
+        assert_eq!(SiteFormat::Csv, SiteFormat::detect("sites", "x,y"));
+    }
+}
@@ -0,0 +1,425 @@
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+use crate::geometry::voronoi_diagram::types::{
+    BoundaryPolicy, ClusterId, NodeId, VoronoiCenterPoint, VoronoiError,
+};
+use crate::geometry::voronoi_diagram::voronoi_diagram::VoronoiDiagram;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// 画像の濃さに合わせて点を置き、濃さで重み付けした Lloyd 緩和で並べる (Weighted Voronoi Stippling)
+// https://www.cs.ubc.ca/labs/imager/tr/2002/secord2002b/secord.2002b.pdf
+
+const JITTER: f64 = 1e-3; // 画素に対する割合
+
+/// グレースケール画像。pixels は行優先で 0(黒)~255(白)
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl GrayImage {
+    /// Canvas の ImageData など RGBA の並びから作る。長さが width * height * 4 でなければエラー
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Result<Self, String> {
+        let expected = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| format!("RGBA: 大きすぎます: {}x{}", width, height))?;
+        if rgba.len() != expected {
+            return Err(format!(
+                "RGBA: 長さが {} ではありません: {}",
+                expected,
+                rgba.len()
+            ));
+        }
+        let pixels = rgba
+            .chunks(4)
+            .map(|p| (0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64) as u8)
+            .collect();
+        Ok(GrayImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// PGM (P2: テキスト, P5: バイナリ, 最大値255まで) を読む
+    pub fn from_pgm(data: &[u8]) -> Result<Self, String> {
+        // ヘッダは 形式 幅 高さ 最大値 の4つ。# から行末まではコメント
+        let mut fields: Vec<String> = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 && pos < data.len() {
+            match data[pos] {
+                b'#' => {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                }
+                c if c.is_ascii_whitespace() => pos += 1,
+                _ => {
+                    let start = pos;
+                    while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                        pos += 1;
+                    }
+                    fields.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+                }
+            }
+        }
+        if fields.len() < 4 {
+            return Err(String::from("PGM: ヘッダが足りません"));
+        }
+        let number = |s: &String| -> Result<usize, String> {
+            s.parse::<usize>()
+                .map_err(|_| format!("PGM: 数値ではありません: {}", s))
+        };
+        let (width, height, max) = (
+            number(&fields[1])?,
+            number(&fields[2])?,
+            number(&fields[3])?,
+        );
+        if max == 0 || max > 255 {
+            return Err(format!("PGM: 対応していない最大値です: {}", max));
+        }
+        let n_pixels = width
+            .checked_mul(height)
+            .ok_or_else(|| format!("PGM: 大きすぎます: {}x{}", width, height))?;
+        let scale = |v: usize| (v.min(max) * 255 / max) as u8;
+        let pixels: Vec<u8> = match fields[0].as_str() {
+            "P5" => {
+                // ヘッダの後の空白1文字の次から画素
+                let body = &data[(pos + 1).min(data.len())..];
+                if body.len() < n_pixels {
+                    return Err(String::from("PGM: 画素が足りません"));
+                }
+                body[..n_pixels]
+                    .iter()
+                    .map(|v| scale(*v as usize))
+                    .collect()
+            }
+            "P2" => {
+                let values: Result<Vec<u8>, String> = String::from_utf8_lossy(&data[pos..])
+                    .split_whitespace()
+                    .take(n_pixels)
+                    .map(|s| number(&s.to_string()).map(scale))
+                    .collect();
+                let values = values?;
+                if values.len() < n_pixels {
+                    return Err(String::from("PGM: 画素が足りません"));
+                }
+                values
+            }
+            other => return Err(format!("PGM: 対応していない形式です: {}", other)),
+        };
+        Ok(GrayImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// PGM か PNG のファイルを読む (拡張子で判断)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        if path.to_lowercase().ends_with(".png") {
+            Self::from_png(&data)
+        } else {
+            Self::from_pgm(&data)
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_png(data: &[u8]) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(data);
+        // パレットや16bitは8bitに展開してもらう
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info().map_err(|e| format!("PNG: {}", e))?;
+        let mut buf = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut buf)
+            .map_err(|e| format!("PNG: {}", e))?;
+        let (width, height) = (info.width as usize, info.height as usize);
+        let channels = info.color_type.samples();
+        let pixels = match channels {
+            1 | 2 => buf.chunks(channels).map(|p| p[0]).collect(),
+            _ => {
+                let rgba: Vec<u8> = buf
+                    .chunks(channels)
+                    .flat_map(|p| vec![p[0], p[1], p[2], 255])
+                    .collect();
+                return Self::from_rgba(width, height, &rgba);
+            }
+        };
+        Ok(GrayImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// 点の濃さ。黒いほど 1 に近い
+    pub fn density(&self, x: usize, y: usize) -> f64 {
+        1. - self.pixels[y * self.width + x] as f64 / 255.
+    }
+}
+
+pub struct StippleConfig {
+    pub n_stipples: usize,
+    pub iterations: usize,
+    pub min_radius: f64, // 画素単位
+    pub max_radius: f64,
+    pub seed: u64,
+}
+
+impl StippleConfig {
+    pub fn new(n_stipples: usize, seed: u64) -> Self {
+        Self {
+            n_stipples,
+            iterations: 20,
+            min_radius: 0.5,
+            max_radius: 2.0,
+            seed,
+        }
+    }
+}
+
+/// 点の位置と半径 (どちらも画素単位)
+#[derive(Clone, Copy, Debug)]
+pub struct Stipple {
+    pub pos: Vec2d,
+    pub radius: f64,
+}
+
+/// 画像を幅1の領域 (高さは height / width) に置いて点を並べる
+pub fn stipple(image: &GrayImage, config: &StippleConfig) -> Result<Vec<Stipple>, VoronoiError> {
+    let (width, height) = (image.width, image.height);
+    if width == 0 || height == 0 || config.n_stipples == 0 {
+        return Ok(vec![]);
+    }
+    let pixel = 1. / width as f64;
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    // 濃さに比例する確率で初期位置を選ぶ (棄却法)
+    let mut sites: Vec<Vec2d> = Vec::new();
+    let max_density = (0..width * height)
+        .map(|i| image.density(i % width, i / width))
+        .fold(0., f64::max);
+    for _ in 0..config.n_stipples {
+        let mut pos = Vec2d { x: 0., y: 0. };
+        for _ in 0..100 {
+            let (x, y) = (rng.gen_range(0, width), rng.gen_range(0, height));
+            pos = Vec2d {
+                x: (x as f64 + rng.gen::<f64>()) * pixel,
+                y: (y as f64 + rng.gen::<f64>()) * pixel,
+            };
+            if rng.gen::<f64>() * max_density < image.density(x, y) {
+                break;
+            }
+        }
+        sites.push(pos);
+    }
+
+    let mut masses = vec![0.; sites.len()];
+    for iteration in 0..=config.iterations {
+        let diagram = build_diagram(&sites, height as f64 * pixel, config.seed)?;
+        // 画素ごとに、その画素を含むセルへ濃さで重み付けした位置を足し込む
+        let labels = diagram.rasterize_cells(width, height);
+        let n_cells = diagram.cells.len();
+        let mut sum_x = vec![0.; n_cells];
+        let mut sum_y = vec![0.; n_cells];
+        let mut mass = vec![0.; n_cells];
+        let mut count = vec![0; n_cells];
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(cell_id) = labels.get(x, y) {
+                    let d = image.density(x, y);
+                    let c = cell_id as usize;
+                    sum_x[c] += d * (x as f64 + 0.5) * pixel;
+                    sum_y[c] += d * (y as f64 + 0.5) * pixel;
+                    mass[c] += d;
+                    count[c] += 1;
+                }
+            }
+        }
+        for _cell in diagram.cells.iter() {
+            let cell = _cell.borrow();
+            let (c, i) = (cell.cell_id as usize, cell.point.node_id as usize);
+            masses[i] = if count[c] > 0 {
+                mass[c] / count[c] as f64
+            } else {
+                0.
+            };
+            // 最後は位置を動かさず、濃さだけ求める
+            // 重心は画素の中心の平均なので、格子状にそろって垂直二等分線がちょうど頂点を通ってしまう。
+            // VoronoiDiagram はそういう縮退を扱えないので、画素よりずっと小さくずらしておく
+            if iteration < config.iterations && mass[c] > 0. {
+                sites[i] = Vec2d {
+                    x: sum_x[c] / mass[c] + (rng.gen::<f64>() - 0.5) * pixel * JITTER,
+                    y: sum_y[c] / mass[c] + (rng.gen::<f64>() - 0.5) * pixel * JITTER,
+                };
+            }
+        }
+    }
+
+    Ok(sites
+        .iter()
+        .zip(masses.iter())
+        .map(|(pos, m)| Stipple {
+            pos: pos.div(pixel),
+            radius: config.min_radius + (config.max_radius - config.min_radius) * m,
+        })
+        .collect())
+}
+
+fn build_diagram(sites: &[Vec2d], height: f64, seed: u64) -> Result<VoronoiDiagram, VoronoiError> {
+    let mut points: Vec<VoronoiCenterPoint> = sites
+        .iter()
+        .enumerate()
+        .map(|(i, pos)| VoronoiCenterPoint {
+            node_id: i as NodeId,
+            pos: *pos,
+            cluster_id: i as ClusterId,
+        })
+        .collect();
+    let mut diagram = VoronoiDiagram::with_bounds(Rect {
+        pos: Vec2d { x: 0., y: 0. },
+        size: Vec2d { x: 1., y: height },
+    });
+    diagram.boundary_policy = BoundaryPolicy::Clamp;
+    diagram.calculate_diagram(&mut points, seed)?;
+    Ok(diagram)
+}
+
+/// 黒い円を並べた SVG
+pub fn to_svg(stipples: &[Stipple], width: usize, height: usize) -> String {
+    let mut ret = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+    ret.push_str(&format!(
+        "  <rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n",
+        width, height
+    ));
+    for s in stipples.iter() {
+        ret.push_str(&format!(
+            "  <circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"black\"/>\n",
+            s.pos.x, s.pos.y, s.radius
+        ));
+    }
+    ret.push_str("</svg>\n");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_rgba_checks_length() {
+        let rgba = [0, 0, 0, 255, 255, 255, 255, 255];
+        let image = GrayImage::from_rgba(2, 1, &rgba).unwrap();
+        assert_eq!(vec![0, 255], image.pixels);
+        assert!(GrayImage::from_rgba(2, 2, &rgba).is_err());
+        assert!(GrayImage::from_rgba(1, 1, &rgba).is_err());
+        assert!(GrayImage::from_rgba(usize::MAX, 2, &rgba).is_err());
+    }
+
+    #[test]
+    fn from_pgm_text() {
+        let image = GrayImage::from_pgm(b"P2\n# comment\n3 2\n15\n0 15 3\n15 0\n 30\n").unwrap();
+        assert_eq!((3, 2), (image.width, image.height));
+        // 最大値で 0~255 にのばし、最大値を超える値は最大値にする
+        assert_eq!(vec![0, 255, 51, 255, 0, 255], image.pixels);
+        assert_eq!(1., image.density(0, 0));
+        assert_eq!(0., image.density(1, 0));
+    }
+
+    #[test]
+    fn from_pgm_binary() {
+        let mut data = b"P5 2 2 # comment\n255\n".to_vec();
+        data.extend_from_slice(&[0, 64, 10, 255]);
+        let image = GrayImage::from_pgm(&data).unwrap();
+        assert_eq!((2, 2), (image.width, image.height));
+        assert_eq!(vec![0, 64, 10, 255], image.pixels);
+    }
+
+    #[test]
+    fn from_pgm_errors() {
+        let invalid: [&[u8]; 8] = [
+            b"",
+            b"P2 3 2",
+            b"P2 3 x 255 0 0 0 0 0 0",
+            b"P2 1 1 0 0",
+            b"P2 1 1 256 0",
+            b"P3 1 1 255 0 0 0",
+            b"P2 2 2 255 0 0 0",
+            b"P5 2 2 255\n\x00\x00\x00",
+        ];
+        for data in invalid.iter() {
+            assert!(
+                GrayImage::from_pgm(data).is_err(),
+                "{}",
+                String::from_utf8_lossy(data)
+            );
+        }
+        assert!(GrayImage::from_pgm(b"P2 2 2 255 0 0 0 -1").is_err());
+    }
+
+    // 左半分が黒、右半分が白の画像
+    fn half_black(width: usize, height: usize) -> GrayImage {
+        GrayImage {
+            width,
+            height,
+            pixels: (0..width * height)
+                .map(|i| if i % width < width / 2 { 0 } else { 255 })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn stipples_follow_density() {
+        let image = half_black(40, 20);
+        let mut config = StippleConfig::new(60, 1);
+        config.iterations = 5;
+        let stipples = stipple(&image, &config).unwrap();
+        assert_eq!(60, stipples.len());
+        for s in stipples.iter() {
+            assert!(0. <= s.pos.x && s.pos.x <= 40. && 0. <= s.pos.y && s.pos.y <= 20.);
+            assert!(config.min_radius <= s.radius && s.radius <= config.max_radius);
+        }
+        // 白いところには置かれず、黒いところの点は大きい
+        let dark: Vec<&Stipple> = stipples.iter().filter(|s| s.pos.x < 20.).collect();
+        assert_eq!(60, dark.len());
+        let mean = dark.iter().map(|s| s.radius).sum::<f64>() / dark.len() as f64;
+        assert!(
+            mean > (config.min_radius + config.max_radius) / 2.,
+            "{}",
+            mean
+        );
+
+        let again = stipple(&image, &config).unwrap();
+        for (a, b) in stipples.iter().zip(again.iter()) {
+            assert_eq!((a.pos.x, a.pos.y, a.radius), (b.pos.x, b.pos.y, b.radius));
+        }
+    }
+
+    #[test]
+    fn svg_has_a_circle_per_stipple() {
+        let stipples = [
+            Stipple {
+                pos: Vec2d { x: 1., y: 2. },
+                radius: 0.5,
+            },
+            Stipple {
+                pos: Vec2d { x: 3.25, y: 4. },
+                radius: 1.5,
+            },
+        ];
+        let svg = to_svg(&stipples, 10, 5);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(2, svg.matches("<circle").count());
+        assert!(svg.contains("cx=\"3.25\" cy=\"4.00\" r=\"1.50\""));
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use wasm_bindgen::JsValue;
+
+/// 文字列を key で保存する場所
+pub trait Storage {
+    fn get(&self, key: &str) -> Result<Option<String>, String>;
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String>;
+    fn remove(&mut self, key: &str) -> Result<(), String>;
+    fn keys(&self) -> Result<Vec<String>, String>;
+}
+
+/// ブラウザの localStorage に保存する
+pub struct LocalStorage {
+    storage: web_sys::Storage,
+}
+
+impl LocalStorage {
+    /// localStorage が使えない(プライベートモードなど)ときはエラー
+    pub fn new() -> Result<Self, String> {
+        let window = web_sys::window().ok_or("no window")?;
+        match window.local_storage() {
+            Ok(Some(storage)) => Ok(Self { storage }),
+            Ok(None) => Err(String::from("localStorage is not available")),
+            Err(e) => Err(js_error(e)),
+        }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, String> {
+        self.storage.get_item(key).map_err(js_error)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        // 容量を超えると QuotaExceededError になる
+        self.storage.set_item(key, value).map_err(js_error)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), String> {
+        self.storage.remove_item(key).map_err(js_error)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, String> {
+        let length = self.storage.length().map_err(js_error)?;
+        let mut keys = Vec::with_capacity(length as usize);
+        for i in 0..length {
+            if let Some(key) = self.storage.key(i).map_err(js_error)? {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+fn js_error(e: JsValue) -> String {
+    e.as_string().unwrap_or(format!("{:?}", e))
+}
+
+/// メモリ上に保存する (テストや、localStorage が使えないとき用。ページを閉じると消える)
+#[derive(Default)]
+pub struct MemoryStorage {
+    items: BTreeMap<String, String>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, String> {
+        Ok(self.items.get(key).cloned())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.items.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), String> {
+        self.items.remove(key);
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, String> {
+        Ok(self.items.keys().cloned().collect())
+    }
+}
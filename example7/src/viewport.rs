@@ -0,0 +1,51 @@
+use crate::geometry::rect::Rect;
+use crate::geometry::vec2d::Vec2d;
+
+// ズームの範囲 (1単位あたりの画素数)
+const MIN_SCALE: f64 = 10.;
+const MAX_SCALE: f64 = 1e7;
+
+/// 図の座標(world)と Canvas の画素(screen)の対応。screen = world * scale + offset
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub scale: f64,
+    pub offset: Vec2d,
+}
+
+impl Viewport {
+    pub fn new(scale: f64, offset: Vec2d) -> Self {
+        Self { scale, offset }
+    }
+
+    pub fn to_screen(self, world: Vec2d) -> Vec2d {
+        world.mul(self.scale) + self.offset
+    }
+
+    pub fn to_world(self, screen: Vec2d) -> Vec2d {
+        (screen - self.offset).div(self.scale)
+    }
+
+    /// 画面上の screen の位置を動かさずに factor 倍に拡大する
+    pub fn zoom_at(&mut self, screen: Vec2d, factor: f64) {
+        let world = self.to_world(screen);
+        self.scale = (self.scale * factor).clamp(MIN_SCALE, MAX_SCALE);
+        self.offset = screen - world.mul(self.scale);
+    }
+
+    pub fn pan(&mut self, delta: Vec2d) {
+        self.offset = self.offset + delta;
+    }
+
+    /// rect が screen_size の画面に padding(px) を残して収まるようにする
+    pub fn fit(&mut self, rect: &Rect, screen_size: Vec2d, padding: f64) {
+        let inner = Vec2d {
+            x: (screen_size.x - padding * 2.).max(1.),
+            y: (screen_size.y - padding * 2.).max(1.),
+        };
+        let scale = (inner.x / rect.size.x.max(1e-12)).min(inner.y / rect.size.y.max(1e-12));
+        self.scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        // rect の中心を画面の中心に
+        let center = rect.pos + rect.size.div(2.);
+        self.offset = screen_size.div(2.) - center.mul(self.scale);
+    }
+}
@@ -0,0 +1,58 @@
+// example3, example3b, example7 から #[path] で読み込んで共有する。
+
+use wasm_bindgen::JsCast;
+
+pub fn window() -> web_sys::Window {
+    web_sys::window().expect("no global `window` exists")
+}
+
+pub fn document() -> web_sys::Document {
+    window()
+        .document()
+        .expect("should have a document on window")
+}
+
+pub fn get_element_by_id<T: JsCast>(id: impl AsRef<str>) -> T {
+    document()
+        .get_element_by_id(id.as_ref())
+        .unwrap_or_else(|| panic!("not found: {}", id.as_ref()))
+        .dyn_into::<T>()
+        .map_err(|_| ())
+        .unwrap()
+}
+
+pub fn canvas(canvas_id: &str) -> web_sys::HtmlCanvasElement {
+    get_element_by_id(canvas_id)
+}
+
+pub fn device_pixel_ratio() -> f64 {
+    let ratio = window().device_pixel_ratio();
+    if ratio > 0. {
+        ratio
+    } else {
+        1.
+    }
+}
+
+/// 描画バッファを 表示の大きさ(CSS px) × devicePixelRatio にする。変えたら true
+/// (表示の大きさは CSS で決めておく。決めていないと描画バッファの大きさがそのまま表示の大きさになる)
+pub fn fit_canvas_to_display(canvas: &web_sys::HtmlCanvasElement) -> bool {
+    let ratio = device_pixel_ratio();
+    let width = (canvas.client_width() as f64 * ratio).round() as u32;
+    let height = (canvas.client_height() as f64 * ratio).round() as u32;
+    if width == 0 || height == 0 || (width == canvas.width() && height == canvas.height()) {
+        return false;
+    }
+    canvas.set_width(width);
+    canvas.set_height(height);
+    true
+}
+
+pub fn context2d(canvas_id: &str) -> web_sys::CanvasRenderingContext2d {
+    canvas(canvas_id)
+        .get_context("2d")
+        .unwrap()
+        .unwrap()
+        .dyn_into::<web_sys::CanvasRenderingContext2d>()
+        .unwrap()
+}
@@ -0,0 +1,392 @@
+// Pointer Events の読み取りとジェスチャーの判定。
+// example5, example6, example7 から #[path] で読み込んで共有する。
+use wasm_bindgen::JsValue;
+
+// これより動いたらタップではなくドラッグ(px)
+const TAP_SLOP: f64 = 4.;
+
+/// 受け取る Pointer Events の名前と種類
+#[allow(dead_code)]
+pub const POINTER_EVENTS: [(&str, PointerPhase); 4] = [
+    ("pointerdown", PointerPhase::Down),
+    ("pointermove", PointerPhase::Move),
+    ("pointerup", PointerPhase::Up),
+    ("pointercancel", PointerPhase::Cancel),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointerPhase {
+    Down,
+    Move,
+    Up,
+    Cancel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointerType {
+    Mouse,
+    Touch,
+    Pen,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub meta: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    /// Shift/Ctrl/Cmd のどれかを押している
+    #[allow(dead_code)]
+    pub fn any(&self) -> bool {
+        self.shift || self.ctrl || self.meta
+    }
+}
+
+/// PointerEvent のうち使う値。JS の値に依存しないので、そのまま作って試せる
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointerInput {
+    pub phase: PointerPhase,
+    pub pointer_id: i32,
+    pub pointer_type: PointerType,
+    pub pos: (f64, f64), // (offsetX, offsetY)
+    pub button: i16,     // 押した/離したボタン。0: 左(タッチ、ペン), 1: 中, 2: 右, -1: 変化なし
+    pub modifiers: Modifiers,
+}
+
+impl PointerInput {
+    /// PointerEvent から読む。位置が無ければ None
+    pub fn from_event(phase: PointerPhase, e: &JsValue) -> Option<Self> {
+        let number = |name: &str| get(e, name).and_then(|v| v.as_f64());
+        let flag = |name: &str| get(e, name).and_then(|v| v.as_bool()) == Some(true);
+        let pointer_type = match get(e, "pointerType").and_then(|v| v.as_string()).as_deref() {
+            Some("touch") => PointerType::Touch,
+            Some("pen") => PointerType::Pen,
+            _ => PointerType::Mouse,
+        };
+        Some(Self {
+            phase,
+            pointer_id: number("pointerId").unwrap_or(0.) as i32,
+            pointer_type,
+            pos: (number("offsetX")?, number("offsetY")?),
+            button: number("button").unwrap_or(-1.) as i16,
+            modifiers: Modifiers {
+                shift: flag("shiftKey"),
+                ctrl: flag("ctrlKey"),
+                meta: flag("metaKey"),
+                alt: flag("altKey"),
+            },
+        })
+    }
+}
+
+fn get(e: &JsValue, name: &str) -> Option<JsValue> {
+    js_sys::Reflect::get(e, &JsValue::from(name))
+        .ok()
+        .filter(|v| !v.is_undefined())
+}
+
+/// PointerTracker がまとめた操作
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    // 1本目を押した
+    Down {
+        pos: (f64, f64),
+        button: i16,
+        pointer_type: PointerType,
+        modifiers: Modifiers,
+    },
+    // 押したまま動かした
+    Move {
+        pos: (f64, f64),
+    },
+    // 離した
+    Up {
+        pos: (f64, f64),
+    },
+    // あまり動かさずに左ボタン(指)を離した。Up の後に来る
+    Tap {
+        pos: (f64, f64),
+    },
+    // 何も押さずに動かした (マウスとペンだけ)
+    Hover {
+        pos: (f64, f64),
+    },
+    // 2本指で動かした。center を中心に scale 倍して、delta だけ動かす
+    Pinch {
+        center: (f64, f64),
+        scale: f64,
+        delta: (f64, f64),
+    },
+    // 1本の操作が途中で終わった (2本目が触れた、または pointercancel)
+    Cancel,
+}
+
+struct Pressed {
+    pointer_id: i32,
+    pos: (f64, f64),
+}
+
+/// 押されているポインタを追いかけて、PointerInput を Gesture にする。
+/// 1本なら Down, Move, Up (Tap), 2本ならピンチ。全部離すまで1本の操作には戻らない。
+#[derive(Default)]
+pub struct PointerTracker {
+    pressed: Vec<Pressed>,
+    down_pos: (f64, f64),
+    button: i16,
+    moved: bool,       // 1本目が TAP_SLOP より動いた
+    multi_touch: bool, // 全部離すまでは、2本目が触れた後
+}
+
+impl PointerTracker {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 押しているポインタがある
+    #[allow(dead_code)]
+    pub fn is_pressed(&self) -> bool {
+        !self.pressed.is_empty()
+    }
+
+    pub fn handle(&mut self, input: &PointerInput) -> Vec<Gesture> {
+        let index = self
+            .pressed
+            .iter()
+            .position(|p| p.pointer_id == input.pointer_id);
+        match input.phase {
+            PointerPhase::Down => self.down(input, index),
+            PointerPhase::Move => self.move_to(input, index),
+            PointerPhase::Up | PointerPhase::Cancel => self.up(input, index),
+        }
+    }
+
+    fn down(&mut self, input: &PointerInput, index: Option<usize>) -> Vec<Gesture> {
+        if index.is_some() {
+            return Vec::new();
+        }
+        self.pressed.push(Pressed {
+            pointer_id: input.pointer_id,
+            pos: input.pos,
+        });
+        match self.pressed.len() {
+            1 => {
+                self.down_pos = input.pos;
+                self.button = input.button;
+                self.moved = false;
+                self.multi_touch = false;
+                vec![Gesture::Down {
+                    pos: input.pos,
+                    button: input.button,
+                    pointer_type: input.pointer_type,
+                    modifiers: input.modifiers,
+                }]
+            }
+            2 if !self.multi_touch => {
+                self.multi_touch = true;
+                vec![Gesture::Cancel]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn move_to(&mut self, input: &PointerInput, index: Option<usize>) -> Vec<Gesture> {
+        let index = match index {
+            Some(index) => index,
+            None if self.pressed.is_empty() && input.pointer_type != PointerType::Touch => {
+                return vec![Gesture::Hover { pos: input.pos }];
+            }
+            None => return Vec::new(),
+        };
+        if self.pressed[index].pos == input.pos {
+            return Vec::new();
+        }
+        // ピンチは先に触れた2本で計算する
+        let before = self.pinch();
+        self.pressed[index].pos = input.pos;
+        if self.multi_touch {
+            return match (before, self.pinch()) {
+                (Some((c0, d0)), Some((c1, d1))) if index < 2 => vec![Gesture::Pinch {
+                    center: c1,
+                    scale: if d0 > 0. { d1 / d0 } else { 1. },
+                    delta: (c1.0 - c0.0, c1.1 - c0.1),
+                }],
+                _ => Vec::new(),
+            };
+        }
+        if distance(input.pos, self.down_pos) > TAP_SLOP {
+            self.moved = true;
+        }
+        vec![Gesture::Move { pos: input.pos }]
+    }
+
+    fn up(&mut self, input: &PointerInput, index: Option<usize>) -> Vec<Gesture> {
+        let index = match index {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+        self.pressed.remove(index);
+        if self.multi_touch {
+            return Vec::new();
+        }
+        if input.phase == PointerPhase::Cancel {
+            return vec![Gesture::Cancel];
+        }
+        let mut gestures = vec![Gesture::Up { pos: input.pos }];
+        if !self.moved && self.button == 0 {
+            gestures.push(Gesture::Tap { pos: input.pos });
+        }
+        gestures
+    }
+
+    // 先に触れた2本の (中点, 距離)
+    fn pinch(&self) -> Option<((f64, f64), f64)> {
+        if self.pressed.len() < 2 {
+            return None;
+        }
+        let (a, b) = (self.pressed[0].pos, self.pressed[1].pos);
+        Some((((a.0 + b.0) / 2., (a.1 + b.1) / 2.), distance(a, b)))
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PointerPhase::{Cancel, Down, Move, Up};
+    use PointerType::{Mouse, Pen, Touch};
+
+    fn input(
+        phase: PointerPhase,
+        pointer_id: i32,
+        pointer_type: PointerType,
+        pos: (f64, f64),
+        button: i16,
+    ) -> PointerInput {
+        PointerInput {
+            phase,
+            pointer_id,
+            pointer_type,
+            pos,
+            button,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    #[test]
+    fn click_is_down_up_tap() {
+        let mut tracker = PointerTracker::new();
+        assert_eq!(
+            vec![Gesture::Hover { pos: (1., 1.) }],
+            tracker.handle(&input(Move, 1, Mouse, (1., 1.), -1))
+        );
+        let modifiers = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        let down = PointerInput {
+            modifiers,
+            ..input(Down, 1, Mouse, (1., 1.), 0)
+        };
+        assert_eq!(
+            vec![Gesture::Down {
+                pos: (1., 1.),
+                button: 0,
+                pointer_type: Mouse,
+                modifiers,
+            }],
+            tracker.handle(&down)
+        );
+        assert!(tracker.is_pressed());
+        // TAP_SLOP より小さい動きならタップのまま
+        assert_eq!(
+            vec![Gesture::Move { pos: (3., 2.) }],
+            tracker.handle(&input(Move, 1, Mouse, (3., 2.), -1))
+        );
+        assert_eq!(
+            vec![
+                Gesture::Up { pos: (3., 2.) },
+                Gesture::Tap { pos: (3., 2.) }
+            ],
+            tracker.handle(&input(Up, 1, Mouse, (3., 2.), 0))
+        );
+        assert!(!tracker.is_pressed());
+    }
+
+    #[test]
+    fn drag_and_right_button_are_not_tap() {
+        let mut tracker = PointerTracker::new();
+        tracker.handle(&input(Down, 1, Mouse, (1., 1.), 0));
+        tracker.handle(&input(Move, 1, Mouse, (20., 1.), -1));
+        // 戻ってきてもタップにはしない
+        tracker.handle(&input(Move, 1, Mouse, (1., 1.), -1));
+        assert_eq!(
+            vec![Gesture::Up { pos: (1., 1.) }],
+            tracker.handle(&input(Up, 1, Mouse, (1., 1.), 0))
+        );
+
+        tracker.handle(&input(Down, 1, Mouse, (1., 1.), 2));
+        assert_eq!(
+            vec![Gesture::Up { pos: (1., 1.) }],
+            tracker.handle(&input(Up, 1, Mouse, (1., 1.), 2))
+        );
+    }
+
+    #[test]
+    fn two_fingers_pinch() {
+        let mut tracker = PointerTracker::new();
+        tracker.handle(&input(Down, 5, Touch, (0., 0.), 0));
+        // 2本目が触れたら1本の操作は取り消す
+        assert_eq!(
+            vec![Gesture::Cancel],
+            tracker.handle(&input(Down, 6, Touch, (10., 0.), 0))
+        );
+        assert_eq!(
+            vec![Gesture::Pinch {
+                center: (10., 0.),
+                scale: 2.,
+                delta: (5., 0.),
+            }],
+            tracker.handle(&input(Move, 6, Touch, (20., 0.), -1))
+        );
+        // 1本離しても、全部離すまでは何も起きない
+        assert!(tracker
+            .handle(&input(Up, 6, Touch, (20., 0.), 0))
+            .is_empty());
+        assert!(tracker
+            .handle(&input(Move, 5, Touch, (3., 0.), -1))
+            .is_empty());
+        assert!(tracker.handle(&input(Up, 5, Touch, (3., 0.), 0)).is_empty());
+        assert!(!tracker.is_pressed());
+        // 触れていない指の move は無視する (タッチには hover が無い)
+        assert!(tracker
+            .handle(&input(Move, 9, Touch, (3., 0.), -1))
+            .is_empty());
+    }
+
+    #[test]
+    fn cancel_ends_single_pointer() {
+        let mut tracker = PointerTracker::new();
+        tracker.handle(&input(Down, 7, Pen, (0., 0.), 0));
+        assert_eq!(
+            vec![Gesture::Cancel],
+            tracker.handle(&input(Cancel, 7, Pen, (0., 0.), 0))
+        );
+        assert!(!tracker.is_pressed());
+        // 知らないポインタの up は無視する
+        assert!(tracker.handle(&input(Up, 3, Mouse, (0., 0.), 0)).is_empty());
+        match tracker
+            .handle(&input(Down, 8, Touch, (0., 0.), 0))
+            .as_slice()
+        {
+            [Gesture::Down { pointer_type, .. }] => assert_eq!(Touch, *pointer_type),
+            gestures => panic!("{:?}", gestures),
+        }
+    }
+}